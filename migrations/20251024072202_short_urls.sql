@@ -1,4 +1,3 @@
-DROP TABLE short_urls;
 CREATE TABLE IF NOT EXISTS short_urls (
   id uuid PRIMARY KEY DEFAULT gen_random_uuid(),
   short_code varchar(10) NOT NULL UNIQUE,
@@ -0,0 +1,5 @@
+ALTER TABLE short_urls
+  ADD COLUMN IF NOT EXISTS health_status_code integer,
+  ADD COLUMN IF NOT EXISTS health_latency_ms integer,
+  ADD COLUMN IF NOT EXISTS health_error text,
+  ADD COLUMN IF NOT EXISTS health_checked_at timestamptz;
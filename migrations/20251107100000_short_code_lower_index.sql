@@ -0,0 +1 @@
+CREATE INDEX IF NOT EXISTS idx_short_urls_code_lower ON short_urls (lower(short_code));
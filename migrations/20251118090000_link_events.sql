@@ -0,0 +1,13 @@
+-- Outbox of link events, written in the transaction of the link change and
+-- turned into webhook deliveries by the webhook job
+CREATE TABLE IF NOT EXISTS link_events (
+  id uuid PRIMARY KEY DEFAULT gen_random_uuid(),
+  kind text NOT NULL,
+  -- JSON of the link as the change left it
+  link text NOT NULL,
+  occurred_at timestamptz NOT NULL DEFAULT now(),
+  -- Claimed by a relay until then
+  available_at timestamptz NOT NULL DEFAULT now()
+);
+
+CREATE INDEX IF NOT EXISTS idx_link_events_available ON link_events (available_at);
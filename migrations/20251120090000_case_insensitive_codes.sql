@@ -0,0 +1,10 @@
+-- Lowercased codes of links created with CASE_INSENSITIVE_CODES, unique, so
+-- codes differing only by case conflict in the database like identical ones.
+-- A separate table since case-sensitive codes such as `a` and `A` may coexist;
+-- the server fills it from existing links when the mode is switched on.
+CREATE TABLE IF NOT EXISTS case_insensitive_codes (
+  code text PRIMARY KEY,
+  link_id uuid NOT NULL REFERENCES short_urls (id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_case_insensitive_codes_link ON case_insensitive_codes (link_id);
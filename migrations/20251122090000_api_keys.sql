@@ -0,0 +1,10 @@
+-- Keys for the API, minted and revoked with url-shortener-admin. Only the
+-- SHA-256 of the secret is stored
+CREATE TABLE IF NOT EXISTS api_keys (
+  id uuid PRIMARY KEY DEFAULT gen_random_uuid(),
+  name text NOT NULL,
+  prefix text NOT NULL,
+  key_hash text NOT NULL UNIQUE,
+  created_at timestamptz NOT NULL DEFAULT now(),
+  revoked_at timestamptz
+);
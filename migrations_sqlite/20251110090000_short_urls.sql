@@ -0,0 +1,20 @@
+CREATE TABLE IF NOT EXISTS short_urls (
+  id blob PRIMARY KEY NOT NULL,
+  short_code text NOT NULL UNIQUE,
+  target_url text NOT NULL,
+  clicks integer NOT NULL DEFAULT 0,
+  created_at text NOT NULL,
+  expires_at text,
+  health_status_code integer,
+  health_latency_ms integer,
+  health_error text,
+  health_checked_at text
+);
+
+CREATE INDEX IF NOT EXISTS idx_short_urls_code_lower ON short_urls (lower(short_code));
+CREATE INDEX IF NOT EXISTS idx_short_urls_expires_at ON short_urls (expires_at);
+
+-- SQLite has no sequences, every insert hands out the next value
+CREATE TABLE IF NOT EXISTS short_code_seq (
+  value integer PRIMARY KEY AUTOINCREMENT
+);
@@ -0,0 +1,28 @@
+CREATE TABLE IF NOT EXISTS webhook_subscriptions (
+  id blob PRIMARY KEY NOT NULL,
+  url text NOT NULL,
+  secret text NOT NULL,
+  -- JSON array of event names
+  events text NOT NULL,
+  created_at text NOT NULL
+);
+
+-- Outbox of event deliveries, kept after delivery for inspection and replay
+CREATE TABLE IF NOT EXISTS webhook_deliveries (
+  id blob PRIMARY KEY NOT NULL,
+  subscription_id blob NOT NULL REFERENCES webhook_subscriptions (id) ON DELETE CASCADE,
+  event text NOT NULL,
+  payload text NOT NULL,
+  status text NOT NULL DEFAULT 'pending' CHECK (status IN ('pending', 'delivered', 'failed')),
+  attempts integer NOT NULL DEFAULT 0,
+  next_attempt_at text NOT NULL,
+  last_status_code integer,
+  last_error text,
+  created_at text NOT NULL,
+  delivered_at text
+);
+
+CREATE INDEX IF NOT EXISTS idx_webhook_deliveries_due
+  ON webhook_deliveries (next_attempt_at) WHERE status = 'pending';
+CREATE INDEX IF NOT EXISTS idx_webhook_deliveries_status
+  ON webhook_deliveries (status, created_at);
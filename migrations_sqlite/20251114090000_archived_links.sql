@@ -0,0 +1,6 @@
+-- Expired links are kept, marked archived, so their clicks survive and their
+-- codes stay reserved
+ALTER TABLE short_urls ADD COLUMN archived_at text;
+
+CREATE INDEX IF NOT EXISTS idx_short_urls_archived_at ON short_urls (archived_at)
+  WHERE archived_at IS NOT NULL;
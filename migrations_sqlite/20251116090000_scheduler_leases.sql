@@ -0,0 +1,9 @@
+-- One row per lease, held by the instance running the periodic jobs until it
+-- stops renewing it
+CREATE TABLE IF NOT EXISTS scheduler_leases (
+  name text PRIMARY KEY NOT NULL,
+  holder text NOT NULL,
+  acquired_at text NOT NULL,
+  renewed_at text NOT NULL,
+  expires_at text NOT NULL
+);
@@ -0,0 +1,155 @@
+use crate::application::dtos::ApiKeyResponse;
+use crate::domain::entities::ApiKey;
+use crate::domain::errors::{DomainError, DomainResult as Result};
+use crate::domain::repositories::ApiKeyRepository;
+use async_trait::async_trait;
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// Marks our keys in configs and secret scanners
+const KEY_PREFIX: &str = "usk_";
+/// Hex digits of randomness after the prefix
+const SECRET_HEX_LEN: usize = 64;
+/// Characters of the secret kept in the clear to recognise a key
+const VISIBLE_LEN: usize = KEY_PREFIX.len() + 8;
+/// Longest key name accepted
+const MAX_NAME_LEN: usize = 100;
+
+#[async_trait]
+pub trait ApiKeyService: Send + Sync {
+    /// The response carries the secret, the only time it is shown
+    async fn mint_key(&self, name: &str) -> Result<ApiKeyResponse>;
+    async fn list_keys(&self) -> Result<Vec<ApiKeyResponse>>;
+    async fn revoke_key(&self, id: Uuid) -> Result<ApiKeyResponse>;
+    /// The unrevoked key `secret` was minted as, `None` for anything else
+    async fn authenticate(&self, secret: &str) -> Result<Option<ApiKey>>;
+}
+
+/// Mints keys as a random secret of which only the hash is stored
+pub struct ApiKeyServiceImpl {
+    repo: Arc<dyn ApiKeyRepository>,
+}
+
+impl ApiKeyServiceImpl {
+    pub fn new(repo: Arc<dyn ApiKeyRepository>) -> Self {
+        Self { repo }
+    }
+}
+
+/// Hex SHA-256 of a secret, as stored
+pub fn hash_key(secret: &str) -> String {
+    Sha256::digest(secret.as_bytes())
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+/// Whether `secret` looks like a key we minted, so garbage never reaches the
+/// store
+fn is_well_formed(secret: &str) -> bool {
+    secret.strip_prefix(KEY_PREFIX).is_some_and(|hex| {
+        hex.len() == SECRET_HEX_LEN && hex.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+    })
+}
+
+fn validate_name(name: &str) -> Result<String> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(DomainError::Invalid {
+            field: "name",
+            rule: "empty",
+            message: "key name must not be empty".to_string(),
+        });
+    }
+    if name.chars().count() > MAX_NAME_LEN {
+        return Err(DomainError::Invalid {
+            field: "name",
+            rule: "too_long",
+            message: format!("key name must be at most {MAX_NAME_LEN} characters"),
+        });
+    }
+    Ok(name.to_string())
+}
+
+#[async_trait]
+impl ApiKeyService for ApiKeyServiceImpl {
+    async fn mint_key(&self, name: &str) -> Result<ApiKeyResponse> {
+        let name = validate_name(name)?;
+
+        let mut bytes = [0u8; SECRET_HEX_LEN / 2];
+        rand::thread_rng().fill_bytes(&mut bytes);
+        let hex: String = bytes.iter().map(|b| format!("{b:02x}")).collect();
+        let secret = format!("{KEY_PREFIX}{hex}");
+
+        let key = self
+            .repo
+            .create_key(&name, &secret[..VISIBLE_LEN], &hash_key(&secret))
+            .await?;
+        tracing::info!("API key {} minted for {}", key.id, key.name);
+
+        Ok(ApiKeyResponse {
+            secret: Some(secret),
+            ..ApiKeyResponse::from(key)
+        })
+    }
+
+    async fn list_keys(&self) -> Result<Vec<ApiKeyResponse>> {
+        let keys = self.repo.list_keys().await?;
+        Ok(keys.into_iter().map(ApiKeyResponse::from).collect())
+    }
+
+    async fn revoke_key(&self, id: Uuid) -> Result<ApiKeyResponse> {
+        let key = self.repo.revoke_key(id).await.map_err(|e| match e {
+            DomainError::NotFound => DomainError::ResourceNotFound("API key"),
+            e => e,
+        })?;
+        tracing::info!("API key {} revoked", key.id);
+        Ok(key.into())
+    }
+
+    async fn authenticate(&self, secret: &str) -> Result<Option<ApiKey>> {
+        if !is_well_formed(secret) {
+            return Ok(None);
+        }
+        self.repo.find_active_key(&hash_key(secret)).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn key_hashes_are_hex_sha256() {
+        assert_eq!(
+            hash_key("abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+
+    #[test]
+    fn only_well_formed_secrets_are_looked_up() {
+        let hex = "0123456789abcdef".repeat(4);
+        assert!(is_well_formed(&format!("usk_{hex}")));
+        for secret in [
+            String::new(),
+            hex.clone(),
+            format!("USK_{hex}"),
+            format!("usk_{}", hex.to_uppercase()),
+            format!("usk_{}", &hex[1..]),
+            format!("usk_{hex}0"),
+            format!("usk_{}g", &hex[1..]),
+        ] {
+            assert!(!is_well_formed(&secret), "{secret}");
+        }
+    }
+
+    #[test]
+    fn key_names_are_trimmed_and_bounded() {
+        assert_eq!(validate_name("  deploy bot ").unwrap(), "deploy bot");
+        assert!(validate_name("   ").is_err());
+        assert!(validate_name(&"x".repeat(MAX_NAME_LEN + 1)).is_err());
+    }
+}
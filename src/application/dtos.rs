@@ -1,19 +1,40 @@
+use crate::domain::entities::{
+    ApiKey, SchedulerLease, ShortUrl, WebhookDelivery, WebhookSubscription,
+};
 use crate::domain::utils::utilities::{
     deserialize_option_datetime, serialize_datetime, serialize_option_datetime,
 };
+use crate::domain::validators::url_validator::display_url;
 use chrono::{DateTime, Utc};
 use salvo::oapi::ToSchema;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 #[derive(Debug, Deserialize, ToSchema)]
 #[salvo(schema(example = json!({
-    "expires_at": "2025-11-05 14:20:30",
+    "expires_in": "7d",
     "target_url": "github.com"
 })))]
 pub struct CreateShortUrlRequest {
     pub target_url: String,
-    #[serde(deserialize_with = "deserialize_option_datetime")]
+    /// RFC 3339, or a naive `YYYY-MM-DD HH:MM:SS` read in the `tz` / `X-Timezone`
+    /// timezone (the server's when absent)
+    #[serde(default, deserialize_with = "deserialize_option_datetime")]
+    pub expires_at: Option<DateTime<Utc>>,
+    /// Lifetime from now instead of `expires_at`, e.g. `30m`, `7d` or `P1W`
+    #[serde(default)]
+    pub expires_in: Option<String>,
+}
+
+/// Changes to an existing link, fields left out are kept
+#[derive(Debug, Default, Deserialize)]
+pub struct UpdateShortUrlRequest {
+    pub target_url: Option<String>,
     pub expires_at: Option<DateTime<Utc>>,
+    /// Lifetime from now instead of `expires_at`
+    pub expires_in: Option<String>,
+    /// Make the link permanent
+    #[serde(default)]
+    pub clear_expiry: bool,
 }
 
 #[derive(Debug, Serialize, ToSchema)]
@@ -21,9 +42,195 @@ pub struct CreateUrlResponse {
     pub id: Uuid,
     pub short_code: String,
     pub target_url: String,
+    /// Target URL with internationalized hosts shown in Unicode
+    pub display_url: String,
     pub clicks: i64,
     #[serde(serialize_with = "serialize_datetime")]
     pub created_at: DateTime<Utc>,
     #[serde(serialize_with = "serialize_option_datetime")]
     pub expires_at: Option<DateTime<Utc>>,
+    /// Result of the last destination health check, `null` until checked
+    pub health: Option<LinkHealthResponse>,
+    /// When cleanup archived the expired link, `null` for live links
+    #[serde(serialize_with = "serialize_option_datetime")]
+    pub archived_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct LinkHealthResponse {
+    pub broken: bool,
+    pub status_code: Option<i32>,
+    pub latency_ms: Option<i32>,
+    pub error: Option<String>,
+    #[serde(serialize_with = "serialize_datetime")]
+    pub checked_at: DateTime<Utc>,
+}
+
+impl From<ShortUrl> for CreateUrlResponse {
+    fn from(url: ShortUrl) -> Self {
+        let health = url.health_checked_at.map(|checked_at| LinkHealthResponse {
+            broken: url.is_broken(),
+            status_code: url.health_status_code,
+            latency_ms: url.health_latency_ms,
+            error: url.health_error.clone(),
+            checked_at,
+        });
+
+        Self {
+            id: url.id,
+            short_code: url.short_code,
+            display_url: display_url(&url.target_url),
+            target_url: url.target_url,
+            clicks: url.clicks,
+            created_at: url.created_at,
+            expires_at: url.expires_at,
+            health,
+            archived_at: url.archived_at,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+#[salvo(schema(example = json!({
+    "url": "https://cms.example.com/hooks/links",
+    "events": ["link.expired", "link.deleted"]
+})))]
+pub struct CreateWebhookRequest {
+    /// http(s) endpoint receiving the events as `POST` requests
+    pub url: String,
+    /// `link.created`, `link.updated`, `link.deleted` and/or `link.expired`,
+    /// all of them when empty
+    #[serde(default)]
+    pub events: Vec<String>,
+    /// HMAC key for the signatures, at least 16 characters. Generated when
+    /// absent
+    #[serde(default)]
+    pub secret: Option<String>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct WebhookResponse {
+    pub id: Uuid,
+    pub url: String,
+    pub events: Vec<String>,
+    #[serde(serialize_with = "serialize_datetime")]
+    pub created_at: DateTime<Utc>,
+    /// Only returned when the subscription is created
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub secret: Option<String>,
+}
+
+impl From<WebhookSubscription> for WebhookResponse {
+    fn from(sub: WebhookSubscription) -> Self {
+        Self {
+            id: sub.id,
+            url: sub.url,
+            events: sub.events,
+            created_at: sub.created_at,
+            secret: None,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct ApiKeyResponse {
+    pub id: Uuid,
+    pub name: String,
+    /// Start of the secret, to recognise the key
+    pub prefix: String,
+    #[serde(serialize_with = "serialize_datetime")]
+    pub created_at: DateTime<Utc>,
+    #[serde(serialize_with = "serialize_option_datetime")]
+    pub revoked_at: Option<DateTime<Utc>>,
+    /// Only returned when the key is minted
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub secret: Option<String>,
+}
+
+impl From<ApiKey> for ApiKeyResponse {
+    fn from(key: ApiKey) -> Self {
+        Self {
+            id: key.id,
+            name: key.name,
+            prefix: key.prefix,
+            created_at: key.created_at,
+            revoked_at: key.revoked_at,
+            secret: None,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct WebhookDeliveryResponse {
+    pub id: Uuid,
+    pub subscription_id: Uuid,
+    pub event: String,
+    /// `pending`, `delivered` or `failed`
+    pub status: String,
+    pub attempts: i32,
+    #[serde(serialize_with = "serialize_datetime")]
+    pub next_attempt_at: DateTime<Utc>,
+    pub last_status_code: Option<i32>,
+    pub last_error: Option<String>,
+    #[serde(serialize_with = "serialize_datetime")]
+    pub created_at: DateTime<Utc>,
+    #[serde(serialize_with = "serialize_option_datetime")]
+    pub delivered_at: Option<DateTime<Utc>>,
+    /// The body sent to the endpoint
+    pub payload: serde_json::Value,
+}
+
+impl From<WebhookDelivery> for WebhookDeliveryResponse {
+    fn from(delivery: WebhookDelivery) -> Self {
+        Self {
+            payload: serde_json::from_str(&delivery.payload)
+                .unwrap_or(serde_json::Value::String(delivery.payload)),
+            id: delivery.id,
+            subscription_id: delivery.subscription_id,
+            event: delivery.event,
+            status: delivery.status,
+            attempts: delivery.attempts,
+            next_attempt_at: delivery.next_attempt_at,
+            last_status_code: delivery.last_status_code,
+            last_error: delivery.last_error,
+            created_at: delivery.created_at,
+            delivered_at: delivery.delivered_at,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct SchedulerLeaseResponse {
+    /// Instance id of the leader
+    pub holder: String,
+    #[serde(serialize_with = "serialize_datetime")]
+    pub acquired_at: DateTime<Utc>,
+    #[serde(serialize_with = "serialize_datetime")]
+    pub renewed_at: DateTime<Utc>,
+    #[serde(serialize_with = "serialize_datetime")]
+    pub expires_at: DateTime<Utc>,
+    /// False once the holder stopped renewing, until another instance takes over
+    pub active: bool,
+}
+
+impl From<SchedulerLease> for SchedulerLeaseResponse {
+    fn from(lease: SchedulerLease) -> Self {
+        Self {
+            active: lease.expires_at > Utc::now(),
+            holder: lease.holder,
+            acquired_at: lease.acquired_at,
+            renewed_at: lease.renewed_at,
+            expires_at: lease.expires_at,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct SchedulerStatusResponse {
+    /// Id of the instance answering
+    pub instance_id: String,
+    /// Whether the instance answering runs the periodic jobs
+    pub leader: bool,
+    /// `null` before any instance took the lease or after the leader released it
+    pub lease: Option<SchedulerLeaseResponse>,
 }
@@ -1,2 +1,4 @@
+pub mod api_keys;
 pub mod dtos;
 pub mod services;
+pub mod webhooks;
@@ -1,91 +1,476 @@
-use crate::application::dtos::{CreateShortUrlRequest, CreateUrlResponse};
-use crate::domain::{repositories::UrlRepository, utils::utilities::generate_short_code};
-use anyhow::{Result, anyhow};
+use crate::application::dtos::{CreateShortUrlRequest, CreateUrlResponse, UpdateShortUrlRequest};
+use crate::domain::entities::ShortUrl;
+use crate::domain::errors::{DomainError, DomainResult as Result};
+use crate::domain::generators::code_generator::{CodeGenerator, CodeRequest, RandomCodeGenerator};
+use crate::domain::repositories::{UrlFilter, UrlRepository};
+use crate::domain::validators::{
+    expiry::{ExpiryError, ExpiryPolicy},
+    self_reference::{SelfReferencePolicy, is_reserved_code},
+    url_validator::{UrlPolicy, UrlValidationError, normalize_url},
+};
 use async_trait::async_trait;
 use chrono::Utc;
-use std::env;
+use std::collections::HashSet;
 use std::sync::Arc;
+use url::Url;
 
 #[async_trait]
 pub trait UrlService: Send + Sync {
     async fn create_short_url(&self, req: CreateShortUrlRequest) -> Result<CreateUrlResponse>;
-    async fn get_target_url(&self, short_code: &str) -> Result<Option<String>>;
-    async fn get_all_urls(&self) -> Result<Vec<CreateUrlResponse>>;
-    async fn delete_url(&self, code: &str) -> Result<(), anyhow::Error>;
+    /// Target of an active link, counting the click
+    async fn get_target_url(&self, short_code: &str) -> Result<String>;
+    /// Link by code without counting a click, archived ones included
+    async fn get_url(&self, code: &str) -> Result<CreateUrlResponse>;
+    async fn get_all_urls(&self, filter: UrlFilter) -> Result<Vec<CreateUrlResponse>>;
+    /// Change the target or expiry, checked like a new link. An archived
+    /// link given a future expiry is live again
+    async fn update_url(&self, code: &str, req: UpdateShortUrlRequest)
+    -> Result<CreateUrlResponse>;
+    async fn delete_url(&self, code: &str) -> Result<()>;
 }
 
-pub struct UrlServiceImpl<R: UrlRepository> {
+/// Attempts at finding an unused short code before giving up
+const MAX_CODE_ATTEMPTS: u32 = 5;
+
+pub struct UrlServiceImpl<R: UrlRepository + ?Sized> {
     repo: Arc<R>,
+    url_policy: UrlPolicy,
+    self_reference: SelfReferencePolicy,
+    expiry_policy: ExpiryPolicy,
+    code_generator: Arc<dyn CodeGenerator>,
 }
 
-impl<R: UrlRepository> UrlServiceImpl<R> {
+impl<R: UrlRepository + ?Sized> UrlServiceImpl<R> {
     pub fn new(repo: Arc<R>) -> Self {
-        Self { repo }
+        Self {
+            repo,
+            url_policy: UrlPolicy::default(),
+            self_reference: SelfReferencePolicy::default(),
+            expiry_policy: ExpiryPolicy::default(),
+            code_generator: Arc::new(RandomCodeGenerator::base62(10)),
+        }
+    }
+
+    pub fn with_code_generator(mut self, generator: Arc<dyn CodeGenerator>) -> Self {
+        self.code_generator = generator;
+        self
+    }
+
+    pub fn with_url_policy(mut self, policy: UrlPolicy) -> Self {
+        self.url_policy = policy;
+        self
+    }
+
+    pub fn with_self_reference(mut self, policy: SelfReferencePolicy) -> Self {
+        self.self_reference = policy;
+        self
+    }
+
+    pub fn with_expiry_policy(mut self, policy: ExpiryPolicy) -> Self {
+        self.expiry_policy = policy;
+        self
+    }
+
+    /// Generate a code and store the link, retrying with a new code when it
+    /// is already taken
+    async fn create_with_free_code(&self, req: &CreateShortUrlRequest) -> Result<ShortUrl> {
+        for attempt in 0..MAX_CODE_ATTEMPTS {
+            let sequence = if self.code_generator.uses_sequence() {
+                Some(self.repo.next_code_sequence().await?)
+            } else {
+                None
+            };
+
+            let code = self.code_generator.generate(&CodeRequest {
+                target_url: &req.target_url,
+                sequence,
+                attempt,
+            });
+            // Served by an API route instead of the redirect
+            if is_reserved_code(&code) {
+                tracing::warn!("short code {} is reserved, retrying", code);
+                continue;
+            }
+
+            match self
+                .repo
+                .create(&code, &req.target_url, req.expires_at)
+                .await
+            {
+                Ok(entity) => return Ok(entity),
+                Err(DomainError::Conflict(_)) => {
+                    tracing::warn!("short code collision on {}, retrying", code);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        Err(DomainError::Conflict(format!(
+            "no free short code after {MAX_CODE_ATTEMPTS} attempts"
+        )))
+    }
+
+    /// Reject targets on our own hosts, or when chain following is enabled,
+    /// walk the chain of our own short codes and reject loops. `updating` is
+    /// the code of the link whose target this becomes, a chain leading back
+    /// to it is a loop.
+    async fn check_self_reference(&self, target: &str, updating: Option<&str>) -> Result<()> {
+        let policy = &self.self_reference;
+        let mut current = Url::parse(target).map_err(|_| UrlValidationError::Malformed)?;
+
+        if !policy.is_own_host(&current) {
+            return Ok(());
+        }
+        if !policy.follows_chain() {
+            let host = current.host_str().unwrap_or_default().to_string();
+            return Err(UrlValidationError::SelfReference(host).into());
+        }
+
+        // Stored codes, which may differ in case from the ones in URLs
+        let mut seen: HashSet<String> = updating.map(str::to_string).into_iter().collect();
+        for _ in 0..policy.max_hops() {
+            // Other pages on our host are fine, only short links redirect
+            let Some(code) = policy.extract_code(&current) else {
+                return Ok(());
+            };
+            let Some(link) = self.repo.find_by_code(&code).await? else {
+                return Err(UrlValidationError::UnknownShortCode(code).into());
+            };
+            if !seen.insert(link.short_code) {
+                return Err(UrlValidationError::RedirectLoop(code).into());
+            }
+            current = Url::parse(&link.target_url).map_err(|_| UrlValidationError::Malformed)?;
+
+            if !policy.is_own_host(&current) {
+                return Ok(());
+            }
+        }
+
+        Err(UrlValidationError::RedirectChainTooLong(policy.max_hops()).into())
     }
 }
 
 #[async_trait]
-impl<R: UrlRepository> UrlService for UrlServiceImpl<R> {
-    async fn create_short_url(&self, req: CreateShortUrlRequest) -> Result<CreateUrlResponse> {
-        let length_code = env::var("LENGTH_CODE")
-            .ok()
-            .filter(|s| !s.trim().is_empty())
-            .unwrap_or_else(|| "10".to_string());
-        let code = generate_short_code(length_code.parse().unwrap());
-
-        let entity = self
+impl<R: UrlRepository + ?Sized> UrlService for UrlServiceImpl<R> {
+    async fn create_short_url(&self, mut req: CreateShortUrlRequest) -> Result<CreateUrlResponse> {
+        let url = normalize_url(&req.target_url, &self.url_policy).inspect_err(|e| {
+            tracing::warn!("rejected target url {}: {}", req.target_url, e);
+        })?;
+        req.target_url = url.to_string();
+
+        req.expires_at = self
+            .expiry_policy
+            .resolve(req.expires_at, req.expires_in.as_deref())?;
+
+        self.check_self_reference(&req.target_url, None).await?;
+
+        let link = self.create_with_free_code(&req).await?;
+        Ok(CreateUrlResponse::from(link))
+    }
+
+    async fn get_target_url(&self, short_code: &str) -> Result<String> {
+        let url = self
             .repo
-            .create(&code, &req.target_url, req.expires_at)
-            .await?;
-
-        Ok(CreateUrlResponse {
-            id: entity.id,
-            short_code: entity.short_code,
-            target_url: entity.target_url,
-            clicks: entity.clicks,
-            created_at: entity.created_at,
-            expires_at: entity.expires_at,
-        })
-    }
-
-    async fn get_target_url(&self, short_code: &str) -> Result<Option<String>> {
-        if let Some(url) = self.repo.find_by_code(short_code).await? {
-            // check expired url
-            if let Some(exp) = url.expires_at {
-                if Utc::now() > exp {
-                    let expired_local = exp
-                        .with_timezone(&chrono::Local)
-                        .format("%Y-%m-%d %H:%M:%S")
-                        .to_string();
-
-                    return Err(anyhow!(format!("EXPIRED:{}", expired_local)));
-                }
-            }
+            .find_by_code(short_code)
+            .await?
+            .ok_or(DomainError::NotFound)?;
 
-            self.repo.increments_clicks(url.id).await?;
-            return Ok(Some(url.target_url));
+        // check expired url
+        if let Some(exp) = url.expires_at
+            && Utc::now() > exp
+        {
+            return Err(DomainError::Expired { at: exp });
         }
 
-        Ok(None)
+        self.repo.increments_clicks(url.id).await?;
+        Ok(url.target_url)
     }
 
-    async fn get_all_urls(&self) -> Result<Vec<CreateUrlResponse>> {
-        let urls = self.repo.get_all_url().await?;
-        Ok(urls
-            .into_iter()
-            .map(|url| CreateUrlResponse {
-                id: url.id,
-                short_code: url.short_code,
-                target_url: url.target_url,
-                clicks: url.clicks,
-                created_at: url.created_at,
-                expires_at: url.expires_at,
-            })
-            .collect())
+    async fn get_url(&self, code: &str) -> Result<CreateUrlResponse> {
+        let link = self
+            .repo
+            .find_by_code(code)
+            .await?
+            .ok_or(DomainError::NotFound)?;
+        Ok(CreateUrlResponse::from(link))
+    }
+
+    async fn get_all_urls(&self, filter: UrlFilter) -> Result<Vec<CreateUrlResponse>> {
+        let urls = self.repo.get_all_url(&filter).await?;
+        Ok(urls.into_iter().map(CreateUrlResponse::from).collect())
+    }
+
+    async fn update_url(
+        &self,
+        code: &str,
+        req: UpdateShortUrlRequest,
+    ) -> Result<CreateUrlResponse> {
+        let mut link = self
+            .repo
+            .find_by_code(code)
+            .await?
+            .ok_or(DomainError::NotFound)?;
+
+        if let Some(target) = &req.target_url {
+            let url = normalize_url(target, &self.url_policy)?;
+            let target = url.to_string();
+            self.check_self_reference(&target, Some(&link.short_code))
+                .await?;
+            if target != link.target_url {
+                // Health was checked against the old target
+                link.target_url = target;
+                link.health_status_code = None;
+                link.health_latency_ms = None;
+                link.health_error = None;
+                link.health_checked_at = None;
+            }
+        }
+
+        if req.clear_expiry {
+            if req.expires_at.is_some() || req.expires_in.is_some() {
+                return Err(ExpiryError::Conflicting.into());
+            }
+            self.expiry_policy.check_permanent()?;
+            link.expires_at = None;
+        } else if req.expires_at.is_some() || req.expires_in.is_some() {
+            link.expires_at = self
+                .expiry_policy
+                .resolve(req.expires_at, req.expires_in.as_deref())?;
+        }
+        if link.expires_at.is_none_or(|at| at > Utc::now()) {
+            link.archived_at = None;
+        }
+
+        self.repo.update_url(&link).await?;
+        Ok(CreateUrlResponse::from(link))
     }
 
-    async fn delete_url(&self, code: &str) -> Result<(), anyhow::Error> {
+    async fn delete_url(&self, code: &str) -> Result<()> {
         self.repo.delete_by_code(code).await?;
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::entities::LinkHealth;
+    use crate::domain::validators::self_reference::RESERVED_CODES;
+    use chrono::DateTime;
+    use std::sync::Mutex;
+    use uuid::Uuid;
+
+    /// Links kept in a vector, enough for the service's own rules
+    #[derive(Default)]
+    struct MemoryRepository {
+        links: Mutex<Vec<ShortUrl>>,
+    }
+
+    #[async_trait]
+    impl UrlRepository for MemoryRepository {
+        async fn create(
+            &self,
+            short_code: &str,
+            target_url: &str,
+            expires_at: Option<DateTime<Utc>>,
+        ) -> Result<ShortUrl> {
+            let mut links = self.links.lock().unwrap();
+            if links.iter().any(|l| l.short_code == short_code) {
+                return Err(DomainError::Conflict(format!("{short_code} is taken")));
+            }
+            let link = ShortUrl {
+                id: Uuid::new_v4(),
+                short_code: short_code.to_string(),
+                target_url: target_url.to_string(),
+                clicks: 0,
+                created_at: Utc::now(),
+                expires_at,
+                health_status_code: None,
+                health_latency_ms: None,
+                health_error: None,
+                health_checked_at: None,
+                archived_at: None,
+            };
+            links.push(link.clone());
+            Ok(link)
+        }
+
+        async fn find_by_code(&self, code: &str) -> Result<Option<ShortUrl>> {
+            let links = self.links.lock().unwrap();
+            Ok(links.iter().find(|l| l.short_code == code).cloned())
+        }
+
+        async fn increments_clicks(&self, _id: Uuid) -> Result<()> {
+            Ok(())
+        }
+
+        async fn get_all_url(&self, _filter: &UrlFilter) -> Result<Vec<ShortUrl>> {
+            Ok(self.links.lock().unwrap().clone())
+        }
+
+        async fn find_active_urls(&self) -> Result<Vec<ShortUrl>> {
+            Ok(self.links.lock().unwrap().clone())
+        }
+
+        async fn update_health(&self, _id: Uuid, _health: &LinkHealth) -> Result<()> {
+            Ok(())
+        }
+
+        async fn update_url(&self, link: &ShortUrl) -> Result<()> {
+            let mut links = self.links.lock().unwrap();
+            let stored = links
+                .iter_mut()
+                .find(|l| l.id == link.id)
+                .ok_or(DomainError::NotFound)?;
+            *stored = link.clone();
+            Ok(())
+        }
+
+        async fn delete_expired_url(&self) -> Result<Vec<ShortUrl>> {
+            Ok(Vec::new())
+        }
+
+        async fn archive_expired_url(&self) -> Result<Vec<ShortUrl>> {
+            Ok(Vec::new())
+        }
+
+        async fn purge_archived_url(&self, _before: DateTime<Utc>) -> Result<u64> {
+            Ok(0)
+        }
+
+        async fn next_code_sequence(&self) -> Result<i64> {
+            Ok(self.links.lock().unwrap().len() as i64 + 1)
+        }
+
+        async fn find_case_collisions(&self) -> Result<Vec<String>> {
+            Ok(Vec::new())
+        }
+
+        async fn reserve_case_insensitive_codes(&self) -> Result<u64> {
+            Ok(0)
+        }
+
+        async fn delete_by_code(&self, code: &str) -> Result<ShortUrl> {
+            let mut links = self.links.lock().unwrap();
+            let index = links
+                .iter()
+                .position(|l| l.short_code == code)
+                .ok_or(DomainError::NotFound)?;
+            Ok(links.remove(index))
+        }
+    }
+
+    const BASE: &str = "https://sho.rt/";
+
+    fn service() -> UrlServiceImpl<MemoryRepository> {
+        let policy = SelfReferencePolicy::new(vec![Url::parse(BASE).unwrap()]);
+        UrlServiceImpl::new(Arc::new(MemoryRepository::default()))
+            .with_self_reference(policy.with_chain_following(5))
+    }
+
+    async fn create(service: &UrlServiceImpl<MemoryRepository>, target: &str) -> String {
+        service
+            .create_short_url(CreateShortUrlRequest {
+                target_url: target.to_string(),
+                expires_at: None,
+                expires_in: None,
+            })
+            .await
+            .unwrap()
+            .short_code
+    }
+
+    fn retarget(target: String) -> UpdateShortUrlRequest {
+        UpdateShortUrlRequest {
+            target_url: Some(target),
+            expires_at: None,
+            expires_in: None,
+            clear_expiry: false,
+        }
+    }
+
+    #[tokio::test]
+    async fn update_rejects_loop_through_another_link() {
+        let service = service();
+        let a = create(&service, "https://example.com/").await;
+        let b = create(&service, &format!("{BASE}api/v1/{a}")).await;
+
+        let err = service
+            .update_url(&a, retarget(format!("{BASE}api/v1/{b}")))
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(
+                err,
+                DomainError::Validation(UrlValidationError::RedirectLoop(_))
+            ),
+            "{err:?}"
+        );
+        let a = service.get_url(&a).await.unwrap();
+        assert_eq!(a.target_url, "https://example.com/");
+    }
+
+    #[tokio::test]
+    async fn update_rejects_link_to_itself() {
+        let service = service();
+        let a = create(&service, "https://example.com/").await;
+
+        let err = service
+            .update_url(&a, retarget(format!("{BASE}api/v1/{a}")))
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(
+                err,
+                DomainError::Validation(UrlValidationError::RedirectLoop(_))
+            ),
+            "{err:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn update_follows_chain_without_loop() {
+        let service = service();
+        let a = create(&service, "https://example.com/").await;
+        let b = create(&service, "https://example.org/").await;
+
+        let updated = service
+            .update_url(&a, retarget(format!("{BASE}api/v1/{b}")))
+            .await
+            .unwrap();
+        assert_eq!(updated.target_url, format!("{BASE}api/v1/{b}"));
+    }
+
+    /// Hands out the given codes in turn
+    struct ScriptedCodes(Mutex<Vec<&'static str>>);
+
+    impl CodeGenerator for ScriptedCodes {
+        fn generate(&self, _req: &CodeRequest<'_>) -> String {
+            self.0.lock().unwrap().remove(0).to_string()
+        }
+    }
+
+    #[tokio::test]
+    async fn reserved_codes_are_not_handed_out() {
+        let codes = ScriptedCodes(Mutex::new(vec!["webhooks", "Scheduler", "free"]));
+        let service = service().with_code_generator(Arc::new(codes));
+
+        assert_eq!(create(&service, "https://example.com/").await, "free");
+    }
+
+    #[tokio::test]
+    async fn api_routes_on_our_host_are_not_short_links() {
+        let service = service();
+        for route in RESERVED_CODES {
+            let target = format!("{BASE}api/v1/{route}");
+            let link = service
+                .create_short_url(CreateShortUrlRequest {
+                    target_url: target.clone(),
+                    expires_at: None,
+                    expires_in: None,
+                })
+                .await;
+            assert!(link.is_ok(), "{target}: {link:?}");
+        }
+    }
+}
@@ -0,0 +1,230 @@
+use crate::application::dtos::{
+    CreateUrlResponse, CreateWebhookRequest, WebhookDeliveryResponse, WebhookResponse,
+};
+use crate::domain::entities::WebhookDelivery;
+use crate::domain::errors::{DomainError, DomainResult as Result};
+use crate::domain::events::{LinkEventKind, LinkEventRelay};
+use crate::domain::repositories::WebhookRepository;
+use crate::domain::utils::utilities::{DateTimeContext, format_datetime};
+use crate::domain::validators::url_validator::{UrlPolicy, normalize_url};
+use async_trait::async_trait;
+use chrono::TimeDelta;
+use rand::RngCore;
+use serde_json::json;
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// Shortest secret accepted from callers
+const MIN_SECRET_LEN: usize = 16;
+/// Deliveries listed at most per request
+pub const MAX_DELIVERY_PAGE: i64 = 500;
+/// How long a claimed event stays hidden from other relays, after which it is
+/// taken again if its deliveries were not queued
+const EVENT_LEASE: TimeDelta = TimeDelta::seconds(60);
+
+#[async_trait]
+pub trait WebhookService: Send + Sync {
+    /// The response carries the secret, the only time it is shown
+    async fn create_subscription(&self, req: CreateWebhookRequest) -> Result<WebhookResponse>;
+    async fn list_subscriptions(&self) -> Result<Vec<WebhookResponse>>;
+    async fn delete_subscription(&self, id: Uuid) -> Result<()>;
+    /// Newest first, optionally only `pending`, `delivered` or `failed` ones
+    async fn list_deliveries(
+        &self,
+        status: Option<String>,
+        limit: i64,
+    ) -> Result<Vec<WebhookDeliveryResponse>>;
+    /// Send a delivery again from scratch, typically a failed one
+    async fn replay_delivery(&self, id: Uuid) -> Result<WebhookDeliveryResponse>;
+}
+
+/// Manages subscriptions and turns recorded link events into deliveries, one
+/// per subscribed endpoint. Sending is left to the dispatcher job.
+pub struct WebhookServiceImpl {
+    repo: Arc<dyn WebhookRepository>,
+    url_policy: UrlPolicy,
+}
+
+impl WebhookServiceImpl {
+    /// Endpoint URLs go through `url_policy` like link targets, so events
+    /// can't be pointed at internal addresses
+    pub fn new(repo: Arc<dyn WebhookRepository>, url_policy: UrlPolicy) -> Self {
+        Self { repo, url_policy }
+    }
+
+    fn validate_url(&self, raw: &str) -> Result<String> {
+        let url = normalize_url(raw, &self.url_policy).map_err(|e| DomainError::Invalid {
+            field: "url",
+            rule: e.rule(),
+            message: e.to_string(),
+        })?;
+        if !matches!(url.scheme(), "http" | "https") {
+            return Err(DomainError::Invalid {
+                field: "url",
+                rule: "unsupported_scheme",
+                message: format!("scheme {}: is not supported for webhooks", url.scheme()),
+            });
+        }
+        Ok(url.to_string())
+    }
+}
+
+fn validate_events(events: &[String]) -> Result<Vec<String>> {
+    if events.is_empty() {
+        return Ok(LinkEventKind::ALL
+            .iter()
+            .map(|kind| kind.as_str().to_string())
+            .collect());
+    }
+
+    let mut valid = Vec::new();
+    for event in events {
+        let kind = event
+            .parse::<LinkEventKind>()
+            .map_err(|message| DomainError::Invalid {
+                field: "events",
+                rule: "unknown_event",
+                message,
+            })?;
+        let name = kind.as_str().to_string();
+        if !valid.contains(&name) {
+            valid.push(name);
+        }
+    }
+    Ok(valid)
+}
+
+/// Repositories report every miss as `NotFound`, which reads as a short link
+fn not_found_as(e: DomainError, resource: &'static str) -> DomainError {
+    match e {
+        DomainError::NotFound => DomainError::ResourceNotFound(resource),
+        e => e,
+    }
+}
+
+fn validate_secret(secret: Option<String>) -> Result<String> {
+    match secret {
+        Some(secret) if secret.chars().count() < MIN_SECRET_LEN => Err(DomainError::Invalid {
+            field: "secret",
+            rule: "too_short",
+            message: format!("secret must be at least {MIN_SECRET_LEN} characters"),
+        }),
+        Some(secret) => Ok(secret),
+        None => {
+            let mut bytes = [0u8; 32];
+            rand::thread_rng().fill_bytes(&mut bytes);
+            Ok(bytes.iter().map(|b| format!("{b:02x}")).collect())
+        }
+    }
+}
+
+#[async_trait]
+impl WebhookService for WebhookServiceImpl {
+    async fn create_subscription(&self, req: CreateWebhookRequest) -> Result<WebhookResponse> {
+        let url = self.validate_url(&req.url)?;
+        let events = validate_events(&req.events)?;
+        let secret = validate_secret(req.secret)?;
+
+        let sub = self
+            .repo
+            .create_subscription(&url, &secret, &events)
+            .await?;
+        tracing::info!(
+            "webhook {} subscribed to {:?} at {}",
+            sub.id,
+            sub.events,
+            sub.url
+        );
+
+        Ok(WebhookResponse {
+            secret: Some(sub.secret.clone()),
+            ..WebhookResponse::from(sub)
+        })
+    }
+
+    async fn list_subscriptions(&self) -> Result<Vec<WebhookResponse>> {
+        let subs = self.repo.list_subscriptions().await?;
+        Ok(subs.into_iter().map(WebhookResponse::from).collect())
+    }
+
+    async fn delete_subscription(&self, id: Uuid) -> Result<()> {
+        self.repo
+            .delete_subscription(id)
+            .await
+            .map_err(|e| not_found_as(e, "webhook subscription"))
+    }
+
+    async fn list_deliveries(
+        &self,
+        status: Option<String>,
+        limit: i64,
+    ) -> Result<Vec<WebhookDeliveryResponse>> {
+        if let Some(status) = &status
+            && ![
+                WebhookDelivery::PENDING,
+                WebhookDelivery::DELIVERED,
+                WebhookDelivery::FAILED,
+            ]
+            .contains(&status.as_str())
+        {
+            return Err(DomainError::Invalid {
+                field: "status",
+                rule: "unknown_status",
+                message: format!(
+                    "unknown status {status:?}, expected pending, delivered or failed"
+                ),
+            });
+        }
+
+        let deliveries = self
+            .repo
+            .list_deliveries(status.as_deref(), limit.clamp(1, MAX_DELIVERY_PAGE))
+            .await?;
+        Ok(deliveries
+            .into_iter()
+            .map(WebhookDeliveryResponse::from)
+            .collect())
+    }
+
+    async fn replay_delivery(&self, id: Uuid) -> Result<WebhookDeliveryResponse> {
+        let delivery = self
+            .repo
+            .replay_delivery(id)
+            .await
+            .map_err(|e| not_found_as(e, "webhook delivery"))?;
+        tracing::info!("webhook delivery {} queued again", delivery.id);
+        Ok(delivery.into())
+    }
+}
+
+#[async_trait]
+impl LinkEventRelay for WebhookServiceImpl {
+    async fn relay(&self, limit: i64) -> Result<usize> {
+        let events = self.repo.claim_link_events(limit, EVENT_LEASE).await?;
+        for event in &events {
+            // Receivers get RFC 3339 in UTC whatever the triggering request asked for
+            let payload = DateTimeContext::default()
+                .scope(async {
+                    json!({
+                        "id": event.id,
+                        "type": event.kind,
+                        "occurred_at": format_datetime(&event.occurred_at),
+                        "data": CreateUrlResponse::from(event.link.clone()),
+                    })
+                    .to_string()
+                })
+                .await;
+
+            // Left for a later pass once the lease runs out
+            if let Err(e) = self.repo.enqueue_event_deliveries(event, &payload).await {
+                tracing::error!(
+                    "webhook {} for {} not queued: {:?}",
+                    event.kind,
+                    event.link.short_code,
+                    e
+                );
+            }
+        }
+        Ok(events.len())
+    }
+}
@@ -0,0 +1,552 @@
+//! Operator commands run directly against the server's database, configured
+//! the same way (`DATABASE_URL`, `CONFIG_FILE`, ...). Links go through the
+//! same `UrlService` as the API, so the same rules apply and webhook events
+//! are queued for the server to deliver. API keys are minted here only, their
+//! secret is printed once and never stored.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use clap::{Parser, Subcommand, ValueEnum};
+use dotenvy::dotenv;
+use serde::Serialize;
+use std::io::{self, Write};
+use tracing_subscriber::EnvFilter;
+use url_shortener::application::api_keys::{ApiKeyService, ApiKeyServiceImpl};
+use url_shortener::application::dtos::{
+    ApiKeyResponse, CreateShortUrlRequest, CreateUrlResponse, UpdateShortUrlRequest,
+};
+use url_shortener::application::services::{UrlService, UrlServiceImpl};
+use url_shortener::config::AppConfig;
+use url_shortener::domain::repositories::UrlFilter;
+use url_shortener::domain::utils::utilities::{DateTimeContext, format_datetime};
+use url_shortener::infrastructure::database::Database;
+use url_shortener::infrastructure::migrations::{applied_versions, prepare_schema};
+use uuid::Uuid;
+
+#[derive(Parser)]
+#[command(
+    name = "url-shortener-admin",
+    version,
+    about = "Manage short links and the database schema without going through the API"
+)]
+struct Cli {
+    /// Output as an aligned table or as JSON for scripts
+    #[arg(long, value_enum, default_value_t = Format::Table, global = true)]
+    format: Format,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Format {
+    Table,
+    Json,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Create, inspect, change or delete short links
+    #[command(subcommand)]
+    Links(LinkCommand),
+    /// Mint, list or revoke API keys
+    #[command(subcommand)]
+    Keys(KeyCommand),
+    /// Apply pending database migrations
+    Migrate,
+}
+
+#[derive(Subcommand)]
+enum LinkCommand {
+    /// Shorten a URL
+    Create {
+        target_url: String,
+        /// RFC 3339, or `YYYY-MM-DD HH:MM:SS` in the local timezone
+        #[arg(long, conflicts_with = "expires_in")]
+        expires_at: Option<String>,
+        /// Lifetime from now, e.g. `30m`, `7d` or `P1W`
+        #[arg(long)]
+        expires_in: Option<String>,
+    },
+    /// List live links, or archived ones
+    List {
+        #[arg(long)]
+        archived: bool,
+        /// Only links whose last health check failed
+        #[arg(long)]
+        broken: bool,
+    },
+    /// Show one link without counting a click
+    Show { code: String },
+    /// Change the target or the expiry of a link
+    Update {
+        code: String,
+        #[arg(long)]
+        target_url: Option<String>,
+        #[arg(long, conflicts_with_all = ["expires_in", "no_expiry"])]
+        expires_at: Option<String>,
+        #[arg(long, conflicts_with = "no_expiry")]
+        expires_in: Option<String>,
+        /// Make the link permanent
+        #[arg(long)]
+        no_expiry: bool,
+    },
+    /// Delete a link, freeing its code
+    Delete { code: String },
+    /// Delete links past their expiry now instead of waiting for cleanup
+    PurgeExpired {
+        /// Delete archived links as well
+        #[arg(long)]
+        archived: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum KeyCommand {
+    /// Create a key and print its secret, shown only this once
+    Mint {
+        /// What the key is for, e.g. `deploy bot`
+        name: String,
+    },
+    /// List keys, revoked ones included
+    List,
+    /// Revoke a key for good
+    Revoke { id: Uuid },
+}
+
+#[tokio::main]
+async fn main() {
+    dotenv().ok();
+    let cli = Cli::parse();
+
+    // Logs go to stderr so JSON output stays parseable
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("warn"));
+    tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(std::io::stderr)
+        .init();
+
+    if let Err(e) = run(cli).await {
+        // Output piped into `head` and the like
+        if e.downcast_ref::<io::Error>()
+            .is_some_and(|e| e.kind() == io::ErrorKind::BrokenPipe)
+        {
+            return;
+        }
+        eprintln!("error: {e:#}");
+        std::process::exit(1);
+    }
+}
+
+async fn run(cli: Cli) -> Result<()> {
+    let config = AppConfig::load()?;
+    let db = Database::connect(&config.database)
+        .await
+        .context("cannot connect to the database")?;
+
+    let result = match cli.command {
+        Command::Migrate => migrate(&db, cli.format).await,
+        Command::Links(command) => {
+            prepare_schema(&db, false).await?;
+            links(&config, &db, command, cli.format).await
+        }
+        Command::Keys(command) => {
+            prepare_schema(&db, false).await?;
+            keys(&db, command, cli.format).await
+        }
+    };
+    db.close().await;
+    result
+}
+
+async fn migrate(db: &Database, format: Format) -> Result<()> {
+    let before = applied_versions(db).await?;
+    prepare_schema(db, true).await?;
+    let after = applied_versions(db).await?;
+
+    let applied: Vec<i64> = after
+        .iter()
+        .copied()
+        .filter(|v| !before.contains(v))
+        .collect();
+    let current = after.last().copied();
+    match format {
+        Format::Json => print_json(&serde_json::json!({
+            "backend": db.backend(),
+            "applied": applied,
+            "version": current,
+        })),
+        Format::Table => {
+            let mut out = io::stdout().lock();
+            if applied.is_empty() {
+                writeln!(out, "{} schema is up to date", db.backend())?;
+            } else {
+                for version in &applied {
+                    writeln!(out, "applied {version}")?;
+                }
+            }
+            if let Some(version) = current {
+                writeln!(out, "schema version {version}")?;
+            }
+            Ok(())
+        }
+    }
+}
+
+async fn links(
+    config: &AppConfig,
+    db: &Database,
+    command: LinkCommand,
+    format: Format,
+) -> Result<()> {
+    let repo = db.url_repository(config.codes.case_insensitive);
+    let service = UrlServiceImpl::new(repo.clone())
+        .with_url_policy(config.targets.url_policy())
+        .with_self_reference(config.targets.self_reference_policy())
+        .with_expiry_policy(config.expiry.expiry_policy())
+        .with_code_generator(config.codes.code_generator());
+
+    match command {
+        LinkCommand::Create {
+            target_url,
+            expires_at,
+            expires_in,
+        } => {
+            let link = service
+                .create_short_url(CreateShortUrlRequest {
+                    target_url,
+                    expires_at: parse_datetime(expires_at.as_deref())?,
+                    expires_in,
+                })
+                .await?;
+            print_link(link, format)
+        }
+        LinkCommand::List { archived, broken } => {
+            let links = service
+                .get_all_urls(UrlFilter {
+                    broken: broken.then_some(true),
+                    archived,
+                })
+                .await?;
+            print_links(&links, format)
+        }
+        LinkCommand::Show { code } => {
+            let link = service.get_url(&code).await?;
+            print_link_details(&link, format)
+        }
+        LinkCommand::Update {
+            code,
+            target_url,
+            expires_at,
+            expires_in,
+            no_expiry,
+        } => {
+            let link = service
+                .update_url(
+                    &code,
+                    UpdateShortUrlRequest {
+                        target_url,
+                        expires_at: parse_datetime(expires_at.as_deref())?,
+                        expires_in,
+                        clear_expiry: no_expiry,
+                    },
+                )
+                .await?;
+            print_link(link, format)
+        }
+        LinkCommand::Delete { code } => {
+            service.delete_url(&code).await?;
+            print_count("deleted", 1, format)
+        }
+        LinkCommand::PurgeExpired { archived } => {
+            let mut count = repo.delete_expired_url().await?.len() as u64;
+            if archived {
+                count += repo.purge_archived_url(Utc::now()).await?;
+            }
+            print_count("deleted", count, format)
+        }
+    }
+}
+
+async fn keys(db: &Database, command: KeyCommand, format: Format) -> Result<()> {
+    let service = ApiKeyServiceImpl::new(db.api_key_repository());
+
+    match command {
+        KeyCommand::Mint { name } => {
+            let key = service.mint_key(&name).await?;
+            if let Format::Json = format {
+                return print_json(&key);
+            }
+            print_keys(std::slice::from_ref(&key), format)?;
+            let secret = key.secret.as_deref().unwrap_or_default();
+            writeln!(
+                io::stdout(),
+                "\nsecret {secret}\nstore it now, it is shown only once"
+            )?;
+            Ok(())
+        }
+        KeyCommand::List => {
+            let keys = service.list_keys().await?;
+            print_keys(&keys, format)
+        }
+        KeyCommand::Revoke { id } => {
+            let key = service.revoke_key(id).await?;
+            match format {
+                Format::Json => print_json(&key),
+                Format::Table => print_keys(&[key], format),
+            }
+        }
+    }
+}
+
+fn parse_datetime(raw: Option<&str>) -> Result<Option<DateTime<Utc>>> {
+    raw.map(|s| DateTimeContext::default().parse(s))
+        .transpose()
+        .map_err(anyhow::Error::msg)
+}
+
+/// `archived`, `expired`, `broken` or `active`
+fn link_status(link: &CreateUrlResponse) -> &'static str {
+    if link.archived_at.is_some() {
+        "archived"
+    } else if link.expires_at.is_some_and(|at| at <= Utc::now()) {
+        "expired"
+    } else if link.health.as_ref().is_some_and(|h| h.broken) {
+        "broken"
+    } else {
+        "active"
+    }
+}
+
+fn optional_datetime(dt: Option<DateTime<Utc>>) -> String {
+    dt.map(|dt| format_datetime(&dt))
+        .unwrap_or_else(|| "-".to_string())
+}
+
+/// A created or updated link, one object in JSON
+fn print_link(link: CreateUrlResponse, format: Format) -> Result<()> {
+    match format {
+        Format::Json => print_json(&link),
+        Format::Table => print_links(&[link], format),
+    }
+}
+
+fn print_links(links: &[CreateUrlResponse], format: Format) -> Result<()> {
+    if let Format::Json = format {
+        return print_json(&links);
+    }
+
+    let rows = links
+        .iter()
+        .map(|link| {
+            vec![
+                link.short_code.clone(),
+                link.display_url.clone(),
+                link.clicks.to_string(),
+                format_datetime(&link.created_at),
+                optional_datetime(link.expires_at),
+                link_status(link).to_string(),
+            ]
+        })
+        .collect::<Vec<_>>();
+    write_table(
+        &mut io::stdout().lock(),
+        &["CODE", "TARGET", "CLICKS", "CREATED", "EXPIRES", "STATUS"],
+        &rows,
+    )
+}
+
+fn print_link_details(link: &CreateUrlResponse, format: Format) -> Result<()> {
+    if let Format::Json = format {
+        return print_json(link);
+    }
+
+    let health = match &link.health {
+        None => "not checked".to_string(),
+        Some(h) => {
+            let outcome = match (&h.error, h.status_code) {
+                (Some(error), _) => error.clone(),
+                (None, Some(code)) => format!("HTTP {code}"),
+                (None, None) => "no answer".to_string(),
+            };
+            format!("{outcome} at {}", format_datetime(&h.checked_at))
+        }
+    };
+    let rows = [
+        ("id", link.id.to_string()),
+        ("code", link.short_code.clone()),
+        ("target", link.target_url.clone()),
+        ("display", link.display_url.clone()),
+        ("clicks", link.clicks.to_string()),
+        ("created", format_datetime(&link.created_at)),
+        ("expires", optional_datetime(link.expires_at)),
+        ("archived", optional_datetime(link.archived_at)),
+        ("health", health),
+        ("status", link_status(link).to_string()),
+    ];
+    let mut out = io::stdout().lock();
+    for (field, value) in rows {
+        writeln!(out, "{field:<9} {value}")?;
+    }
+    Ok(())
+}
+
+fn print_keys(keys: &[ApiKeyResponse], format: Format) -> Result<()> {
+    if let Format::Json = format {
+        return print_json(&keys);
+    }
+
+    let rows = keys
+        .iter()
+        .map(|key| {
+            let status = match key.revoked_at {
+                Some(at) => format!("revoked {}", format_datetime(&at)),
+                None => "active".to_string(),
+            };
+            vec![
+                key.id.to_string(),
+                key.name.clone(),
+                key.prefix.clone(),
+                format_datetime(&key.created_at),
+                status,
+            ]
+        })
+        .collect::<Vec<_>>();
+    write_table(
+        &mut io::stdout().lock(),
+        &["ID", "NAME", "PREFIX", "CREATED", "STATUS"],
+        &rows,
+    )
+}
+
+fn print_count(action: &str, count: u64, format: Format) -> Result<()> {
+    match format {
+        Format::Json => print_json(&serde_json::json!({ action: count })),
+        Format::Table => {
+            let plural = if count == 1 { "" } else { "s" };
+            writeln!(io::stdout(), "{action} {count} link{plural}")?;
+            Ok(())
+        }
+    }
+}
+
+fn print_json(value: &impl Serialize) -> Result<()> {
+    writeln!(io::stdout(), "{}", serde_json::to_string_pretty(value)?)?;
+    Ok(())
+}
+
+/// Columns padded to their widest cell, the last one left ragged
+fn write_table(out: &mut impl Write, headers: &[&str], rows: &[Vec<String>]) -> Result<()> {
+    let mut widths: Vec<usize> = headers.iter().map(|h| h.chars().count()).collect();
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let header = headers.iter().map(|h| h.to_string()).collect::<Vec<_>>();
+    for cells in std::iter::once(&header).chain(rows) {
+        let padded: Vec<String> = cells
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{cell:<width$}"))
+            .collect();
+        writeln!(out, "{}", padded.join("  ").trim_end())?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeDelta;
+    use url_shortener::application::dtos::LinkHealthResponse;
+
+    fn link() -> CreateUrlResponse {
+        CreateUrlResponse {
+            id: Uuid::nil(),
+            short_code: "abc".to_string(),
+            target_url: "https://example.com/".to_string(),
+            display_url: "https://example.com/".to_string(),
+            clicks: 0,
+            created_at: Utc::now(),
+            expires_at: None,
+            health: None,
+            archived_at: None,
+        }
+    }
+
+    fn health(broken: bool) -> Option<LinkHealthResponse> {
+        Some(LinkHealthResponse {
+            broken,
+            status_code: Some(if broken { 404 } else { 200 }),
+            latency_ms: Some(12),
+            error: None,
+            checked_at: Utc::now(),
+        })
+    }
+
+    #[test]
+    fn datetimes_take_an_offset_or_the_server_timezone() {
+        assert_eq!(parse_datetime(None).unwrap(), None);
+        assert_eq!(
+            parse_datetime(Some("2025-11-05T14:20:30+02:00")).unwrap(),
+            Some("2025-11-05T12:20:30Z".parse().unwrap())
+        );
+        let naive = parse_datetime(Some("2025-11-05 14:20:30")).unwrap();
+        assert_eq!(
+            naive,
+            Some(
+                DateTimeContext::default()
+                    .parse("2025-11-05 14:20:30")
+                    .unwrap()
+            )
+        );
+        let err = parse_datetime(Some("next week")).unwrap_err();
+        assert!(err.to_string().starts_with("invalid datetime"), "{err}");
+    }
+
+    #[test]
+    fn status_prefers_archived_then_expired_then_broken() {
+        let past = Some(Utc::now() - TimeDelta::minutes(1));
+        let future = Some(Utc::now() + TimeDelta::minutes(1));
+        for (expires_at, health, archived_at, expected) in [
+            (None, None, None, "active"),
+            (future, health(false), None, "active"),
+            (future, health(true), None, "broken"),
+            (past, health(true), None, "expired"),
+            (past, health(true), past, "archived"),
+        ] {
+            let link = CreateUrlResponse {
+                expires_at,
+                health,
+                archived_at,
+                ..link()
+            };
+            assert_eq!(link_status(&link), expected);
+        }
+    }
+
+    #[test]
+    fn tables_are_padded_to_the_widest_cell() {
+        let rows = vec![
+            vec![
+                "abc".to_string(),
+                "https://example.com/".to_string(),
+                "7".to_string(),
+            ],
+            vec![
+                "longer-code".to_string(),
+                "https://é.example/".to_string(),
+                String::new(),
+            ],
+        ];
+        let mut out = Vec::new();
+        write_table(&mut out, &["CODE", "TARGET", "CLICKS"], &rows).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "CODE         TARGET                CLICKS\n\
+             abc          https://example.com/  7\n\
+             longer-code  https://é.example/\n"
+        );
+    }
+}
@@ -0,0 +1,963 @@
+use crate::domain::generators::code_generator::{
+    CodeGenerator, CodeStrategy, build_code_generator,
+};
+use crate::domain::utils::utilities::DateTimeFormat;
+use crate::domain::validators::expiry::{ExpiryPolicy, LinkLifetime};
+use crate::domain::validators::self_reference::SelfReferencePolicy;
+use crate::domain::validators::url_validator::UrlPolicy;
+use crate::infrastructure::scheduler::CronSchedule;
+use chrono::TimeDelta;
+use serde::{Deserialize, Deserializer};
+use std::env;
+use std::fmt::Display;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+use thiserror::Error;
+use url::Url;
+
+/// Longest code the `short_code varchar(10)` column can hold
+const MAX_CODE_LENGTH: usize = 10;
+
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error("cannot read config file {path}: {source}")]
+    Read {
+        path: String,
+        #[source]
+        source: std::io::Error,
+    },
+    #[error("invalid config file {path}: {source}")]
+    Parse {
+        path: String,
+        #[source]
+        source: toml::de::Error,
+    },
+    #[error("invalid {key}={value:?}: {reason}")]
+    Env {
+        key: &'static str,
+        value: String,
+        reason: String,
+    },
+    #[error("invalid configuration:\n  - {}", .0.join("\n  - "))]
+    Invalid(Vec<String>),
+}
+
+/// Application settings, loaded once at startup.
+///
+/// Defaults are overridden by the TOML file named in `CONFIG_FILE` (if set),
+/// which is in turn overridden by environment variables.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AppConfig {
+    pub server: ServerConfig,
+    pub logging: LoggingConfig,
+    pub api: ApiConfig,
+    pub database: DatabaseConfig,
+    pub cleanup: CleanupConfig,
+    pub scheduler: SchedulerConfig,
+    pub expiry: ExpiryConfig,
+    pub codes: CodeConfig,
+    pub targets: TargetConfig,
+    pub health_check: HealthCheckConfig,
+    pub webhooks: WebhookConfig,
+    pub jobs: JobsConfig,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    /// `APP_PORT`, default 5800
+    pub port: u16,
+    /// `SHUTDOWN_TIMEOUT_SECS`, default 30. How long in-flight requests and
+    /// background jobs may take to finish after SIGTERM
+    pub shutdown_timeout_secs: u64,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LoggingConfig {
+    /// `LOG_FORMAT`, `text` (default) or `json`. Levels come from `RUST_LOG`
+    #[serde(deserialize_with = "from_str")]
+    pub format: LogFormat,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LogFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            other => Err(format!(
+                "unknown log format {other:?}, expected text or json"
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ApiConfig {
+    /// `API_DATETIME_FORMAT`, `rfc3339` (default, UTC) or `local` for the v1
+    /// `YYYY-MM-DD HH:MM:SS` in the request's or the server's timezone
+    #[serde(deserialize_with = "from_str")]
+    pub datetime_format: DateTimeFormat,
+    /// `API_REQUIRE_KEY`, default false. Refuse `/api/v1` calls without an
+    /// unrevoked API key, redirects aside
+    pub require_key: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DatabaseConfig {
+    /// `DATABASE_URL`, required. `postgres://` or, with the `sqlite` and
+    /// `redb` features, `sqlite:` and `redb:`
+    pub url: String,
+    /// `DATABASE_MAX_CONNECTIONS`, default 5
+    pub max_connections: u32,
+    /// `RUN_MIGRATIONS`, default true. Apply pending migrations at startup
+    pub run_migrations: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CleanupConfig {
+    /// `CLEANUP_INTERVAL_SECS`, default 60
+    pub interval_secs: u64,
+    /// `CLEANUP_EXPIRED_ACTION`, `archive` (default) or `delete`
+    #[serde(deserialize_with = "from_str")]
+    pub expired_action: ExpiredAction,
+    /// `ARCHIVE_RETENTION`, unset by default (kept forever). How long archived
+    /// links are kept before they are deleted, e.g. `90d` or `P26W`
+    #[serde(deserialize_with = "option_from_str")]
+    pub archive_retention: Option<LinkLifetime>,
+}
+
+/// Leader election for the periodic jobs, so only one replica runs them
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SchedulerConfig {
+    /// `INSTANCE_ID`, default `<HOSTNAME>-<pid>`. Shown as the lease holder
+    pub instance_id: String,
+    /// `SCHEDULER_LEASE_TTL_SECS`, default 30. How long after its last renewal
+    /// a dead leader's lease can be taken over; renewed every third of it
+    pub lease_ttl_secs: u64,
+    /// `SCHEDULER_JITTER_PERCENT`, default 10. Job intervals vary randomly by
+    /// up to this much either way, so replicas don't wake up together
+    pub jitter_percent: u8,
+}
+
+/// What cleanup does with links past their expiry
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ExpiredAction {
+    /// Keep them, with their clicks, and keep their codes reserved
+    #[default]
+    Archive,
+    /// Delete them for good
+    Delete,
+}
+
+impl FromStr for ExpiredAction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "archive" => Ok(Self::Archive),
+            "delete" => Ok(Self::Delete),
+            other => Err(format!(
+                "unknown expired action {other:?}, expected archive or delete"
+            )),
+        }
+    }
+}
+
+/// Durations are written `30m`, `7d`, `1h30m` or ISO 8601 such as `P1W`
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ExpiryConfig {
+    /// `LINK_MAX_LIFETIME`, unset by default. Longest a link may live, links
+    /// without an expiry get it when there is no default TTL
+    #[serde(deserialize_with = "option_from_str")]
+    pub max_lifetime: Option<LinkLifetime>,
+    /// `LINK_DEFAULT_TTL`, unset by default. Lifetime of links created
+    /// without `expires_at` or `expires_in`
+    #[serde(deserialize_with = "option_from_str")]
+    pub default_ttl: Option<LinkLifetime>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CodeConfig {
+    /// `CODE_STRATEGY`, default `random`
+    #[serde(deserialize_with = "from_str")]
+    pub strategy: CodeStrategy,
+    /// `LENGTH_CODE`, default 10
+    pub length: usize,
+    /// `HASHIDS_SALT`, required by the `hashids` strategy
+    pub hashids_salt: String,
+    /// `CODE_SEED`, unset by default
+    pub seed: Option<u64>,
+    /// `CASE_INSENSITIVE_CODES`, default false
+    pub case_insensitive: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TargetConfig {
+    /// `BLOCKED_DOMAINS`, empty by default
+    pub blocked_domains: Vec<String>,
+    /// `ALLOWED_DOMAINS`, empty (allowlist mode off) by default
+    pub allowed_domains: Vec<String>,
+    /// `EXTRA_URL_SCHEMES`, empty by default
+    pub extra_schemes: Vec<String>,
+    /// `PUBLIC_BASE_URLS`, empty by default
+    pub public_base_urls: Vec<String>,
+    /// `FOLLOW_SELF_REDIRECTS`, default false
+    pub follow_self_redirects: bool,
+    /// `MAX_REDIRECT_HOPS`, default 5
+    pub max_redirect_hops: usize,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HealthCheckConfig {
+    /// `HEALTH_CHECK_INTERVAL_SECS`, default 3600, 0 disables the checks
+    pub interval_secs: u64,
+    /// `HEALTH_CHECK_TIMEOUT_SECS`, default 10
+    pub timeout_secs: u64,
+    /// `HEALTH_CHECK_CONCURRENCY`, default 8
+    pub concurrency: usize,
+    /// `HEALTH_CHECK_HOST_DELAY_MS`, default 1000
+    pub host_delay_ms: u64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WebhookConfig {
+    /// `WEBHOOK_DISPATCH_INTERVAL_SECS`, default 5, 0 stops sending (events
+    /// are still queued)
+    pub dispatch_interval_secs: u64,
+    /// `WEBHOOK_TIMEOUT_SECS`, default 10
+    pub timeout_secs: u64,
+    /// `WEBHOOK_MAX_ATTEMPTS`, default 8, after which a delivery is failed
+    pub max_attempts: i32,
+    /// `WEBHOOK_BACKOFF_BASE_SECS`, default 30, doubled after every failure
+    pub backoff_base_secs: u64,
+    /// `WEBHOOK_BACKOFF_MAX_SECS`, default 21600 (6 hours)
+    pub backoff_max_secs: u64,
+    /// `WEBHOOK_BATCH_SIZE`, default 50
+    pub batch_size: i64,
+}
+
+/// Switches of the background jobs, on top of the intervals set in their
+/// own sections
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct JobsConfig {
+    /// `JOB_CLEANUP_ENABLED`, `JOB_CLEANUP_SCHEDULE`, `JOB_CLEANUP_TIMEOUT_SECS`
+    pub cleanup: JobConfig,
+    /// `JOB_HEALTH_CHECK_ENABLED`, `JOB_HEALTH_CHECK_SCHEDULE`,
+    /// `JOB_HEALTH_CHECK_TIMEOUT_SECS`
+    pub health_check: JobConfig,
+    /// `JOB_WEBHOOKS_ENABLED`, `JOB_WEBHOOKS_SCHEDULE`, `JOB_WEBHOOKS_TIMEOUT_SECS`
+    pub webhooks: JobConfig,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct JobConfig {
+    /// Default true
+    pub enabled: bool,
+    /// Unset by default, running every interval of the job's section. A cron
+    /// expression in UTC such as `0 3 * * *` runs it at those times instead
+    #[serde(deserialize_with = "option_from_str")]
+    pub schedule: Option<CronSchedule>,
+    /// Default 300, 0 for no limit. A run taking longer is cancelled
+    pub timeout_secs: u64,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            port: 5800,
+            shutdown_timeout_secs: 30,
+        }
+    }
+}
+
+impl Default for DatabaseConfig {
+    fn default() -> Self {
+        Self {
+            url: String::new(),
+            max_connections: 5,
+            run_migrations: true,
+        }
+    }
+}
+
+impl Default for CleanupConfig {
+    fn default() -> Self {
+        Self {
+            interval_secs: 60,
+            expired_action: ExpiredAction::Archive,
+            archive_retention: None,
+        }
+    }
+}
+
+impl Default for SchedulerConfig {
+    fn default() -> Self {
+        Self {
+            instance_id: String::new(),
+            lease_ttl_secs: 30,
+            jitter_percent: 10,
+        }
+    }
+}
+
+impl Default for JobConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            schedule: None,
+            timeout_secs: 300,
+        }
+    }
+}
+
+impl Default for CodeConfig {
+    fn default() -> Self {
+        Self {
+            strategy: CodeStrategy::Random,
+            length: MAX_CODE_LENGTH,
+            hashids_salt: String::new(),
+            seed: None,
+            case_insensitive: false,
+        }
+    }
+}
+
+impl Default for TargetConfig {
+    fn default() -> Self {
+        Self {
+            blocked_domains: Vec::new(),
+            allowed_domains: Vec::new(),
+            extra_schemes: Vec::new(),
+            public_base_urls: Vec::new(),
+            follow_self_redirects: false,
+            max_redirect_hops: 5,
+        }
+    }
+}
+
+impl Default for HealthCheckConfig {
+    fn default() -> Self {
+        Self {
+            interval_secs: 3600,
+            timeout_secs: 10,
+            concurrency: 8,
+            host_delay_ms: 1000,
+        }
+    }
+}
+
+impl Default for WebhookConfig {
+    fn default() -> Self {
+        Self {
+            dispatch_interval_secs: 5,
+            timeout_secs: 10,
+            max_attempts: 8,
+            backoff_base_secs: 30,
+            backoff_max_secs: 6 * 3600,
+            batch_size: 50,
+        }
+    }
+}
+
+impl AppConfig {
+    /// Load defaults, then `CONFIG_FILE`, then environment overrides, and
+    /// validate the result
+    pub fn load() -> Result<Self, ConfigError> {
+        let mut config = match env_value("CONFIG_FILE") {
+            Some(path) => Self::from_file(&path)?,
+            None => Self::default(),
+        };
+        config.apply_env()?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn from_file(path: &str) -> Result<Self, ConfigError> {
+        let raw = std::fs::read_to_string(path).map_err(|source| ConfigError::Read {
+            path: path.to_string(),
+            source,
+        })?;
+        toml::from_str(&raw).map_err(|source| ConfigError::Parse {
+            path: path.to_string(),
+            source,
+        })
+    }
+
+    fn apply_env(&mut self) -> Result<(), ConfigError> {
+        env_parse("APP_PORT", &mut self.server.port)?;
+        env_parse(
+            "SHUTDOWN_TIMEOUT_SECS",
+            &mut self.server.shutdown_timeout_secs,
+        )?;
+
+        env_parse("LOG_FORMAT", &mut self.logging.format)?;
+
+        env_parse("API_DATETIME_FORMAT", &mut self.api.datetime_format)?;
+        env_parse("API_REQUIRE_KEY", &mut self.api.require_key)?;
+
+        env_parse("DATABASE_URL", &mut self.database.url)?;
+        env_parse(
+            "DATABASE_MAX_CONNECTIONS",
+            &mut self.database.max_connections,
+        )?;
+        env_parse("RUN_MIGRATIONS", &mut self.database.run_migrations)?;
+
+        env_parse("CLEANUP_INTERVAL_SECS", &mut self.cleanup.interval_secs)?;
+        env_parse("CLEANUP_EXPIRED_ACTION", &mut self.cleanup.expired_action)?;
+        if let Some(retention) = env_value("ARCHIVE_RETENTION") {
+            self.cleanup.archive_retention = Some(parse("ARCHIVE_RETENTION", retention)?);
+        }
+
+        env_parse("INSTANCE_ID", &mut self.scheduler.instance_id)?;
+        env_parse(
+            "SCHEDULER_LEASE_TTL_SECS",
+            &mut self.scheduler.lease_ttl_secs,
+        )?;
+        env_parse(
+            "SCHEDULER_JITTER_PERCENT",
+            &mut self.scheduler.jitter_percent,
+        )?;
+
+        if let Some(max) = env_value("LINK_MAX_LIFETIME") {
+            self.expiry.max_lifetime = Some(parse("LINK_MAX_LIFETIME", max)?);
+        }
+        if let Some(ttl) = env_value("LINK_DEFAULT_TTL") {
+            self.expiry.default_ttl = Some(parse("LINK_DEFAULT_TTL", ttl)?);
+        }
+
+        env_parse("CODE_STRATEGY", &mut self.codes.strategy)?;
+        env_parse("LENGTH_CODE", &mut self.codes.length)?;
+        env_parse("HASHIDS_SALT", &mut self.codes.hashids_salt)?;
+        if let Some(seed) = env_value("CODE_SEED") {
+            self.codes.seed = Some(parse("CODE_SEED", seed)?);
+        }
+        env_parse("CASE_INSENSITIVE_CODES", &mut self.codes.case_insensitive)?;
+
+        env_list("BLOCKED_DOMAINS", &mut self.targets.blocked_domains);
+        env_list("ALLOWED_DOMAINS", &mut self.targets.allowed_domains);
+        env_list("EXTRA_URL_SCHEMES", &mut self.targets.extra_schemes);
+        env_list("PUBLIC_BASE_URLS", &mut self.targets.public_base_urls);
+        env_parse(
+            "FOLLOW_SELF_REDIRECTS",
+            &mut self.targets.follow_self_redirects,
+        )?;
+        env_parse("MAX_REDIRECT_HOPS", &mut self.targets.max_redirect_hops)?;
+
+        let health = &mut self.health_check;
+        env_parse("HEALTH_CHECK_INTERVAL_SECS", &mut health.interval_secs)?;
+        env_parse("HEALTH_CHECK_TIMEOUT_SECS", &mut health.timeout_secs)?;
+        env_parse("HEALTH_CHECK_CONCURRENCY", &mut health.concurrency)?;
+        env_parse("HEALTH_CHECK_HOST_DELAY_MS", &mut health.host_delay_ms)?;
+
+        let webhooks = &mut self.webhooks;
+        env_parse(
+            "WEBHOOK_DISPATCH_INTERVAL_SECS",
+            &mut webhooks.dispatch_interval_secs,
+        )?;
+        env_parse("WEBHOOK_TIMEOUT_SECS", &mut webhooks.timeout_secs)?;
+        env_parse("WEBHOOK_MAX_ATTEMPTS", &mut webhooks.max_attempts)?;
+        env_parse("WEBHOOK_BACKOFF_BASE_SECS", &mut webhooks.backoff_base_secs)?;
+        env_parse("WEBHOOK_BACKOFF_MAX_SECS", &mut webhooks.backoff_max_secs)?;
+        env_parse("WEBHOOK_BATCH_SIZE", &mut webhooks.batch_size)?;
+
+        self.jobs.cleanup.apply_env([
+            "JOB_CLEANUP_ENABLED",
+            "JOB_CLEANUP_SCHEDULE",
+            "JOB_CLEANUP_TIMEOUT_SECS",
+        ])?;
+        self.jobs.health_check.apply_env([
+            "JOB_HEALTH_CHECK_ENABLED",
+            "JOB_HEALTH_CHECK_SCHEDULE",
+            "JOB_HEALTH_CHECK_TIMEOUT_SECS",
+        ])?;
+        self.jobs.webhooks.apply_env([
+            "JOB_WEBHOOKS_ENABLED",
+            "JOB_WEBHOOKS_SCHEDULE",
+            "JOB_WEBHOOKS_TIMEOUT_SECS",
+        ])?;
+        Ok(())
+    }
+
+    /// Report every problem at once rather than the first one
+    fn validate(&self) -> Result<(), ConfigError> {
+        let mut problems = Vec::new();
+
+        if self.server.port == 0 {
+            problems.push("server.port (APP_PORT) must not be 0".to_string());
+        }
+
+        if self.database.url.trim().is_empty() {
+            problems.push("database.url (DATABASE_URL) must be set".to_string());
+        } else if self.database.url.starts_with("sqlite:") {
+            if !cfg!(feature = "sqlite") {
+                problems.push(
+                    "database.url (DATABASE_URL) is a sqlite: URL but this binary was built without the sqlite feature"
+                        .to_string(),
+                );
+            }
+        } else if self.database.url.starts_with("redb:") {
+            if !cfg!(feature = "redb") {
+                problems.push(
+                    "database.url (DATABASE_URL) is a redb: URL but this binary was built without the redb feature"
+                        .to_string(),
+                );
+            }
+        } else if !self.database.url.starts_with("postgres://")
+            && !self.database.url.starts_with("postgresql://")
+        {
+            problems.push(
+                "database.url (DATABASE_URL) must be a postgres://, postgresql://, sqlite: or redb: URL"
+                    .to_string(),
+            );
+        }
+        if self.database.max_connections == 0 {
+            problems.push(
+                "database.max_connections (DATABASE_MAX_CONNECTIONS) must be at least 1"
+                    .to_string(),
+            );
+        }
+
+        if self.cleanup.interval_secs == 0 {
+            problems.push(
+                "cleanup.interval_secs (CLEANUP_INTERVAL_SECS) must be at least 1".to_string(),
+            );
+        }
+
+        if self.scheduler.lease_ttl_secs < 3 {
+            problems.push(
+                "scheduler.lease_ttl_secs (SCHEDULER_LEASE_TTL_SECS) must be at least 3"
+                    .to_string(),
+            );
+        }
+        if self.scheduler.jitter_percent > 50 {
+            problems.push(
+                "scheduler.jitter_percent (SCHEDULER_JITTER_PERCENT) must be at most 50"
+                    .to_string(),
+            );
+        }
+
+        let limit = LinkLifetime::CONFIG_LIMIT;
+        for (name, lifetime) in [
+            (
+                "expiry.max_lifetime (LINK_MAX_LIFETIME)",
+                self.expiry.max_lifetime,
+            ),
+            (
+                "expiry.default_ttl (LINK_DEFAULT_TTL)",
+                self.expiry.default_ttl,
+            ),
+            (
+                "cleanup.archive_retention (ARCHIVE_RETENTION)",
+                self.cleanup.archive_retention,
+            ),
+        ] {
+            if let Some(lifetime) = lifetime
+                && lifetime > limit
+            {
+                problems.push(format!("{name} {lifetime} must be at most {limit}"));
+            }
+        }
+        if let (Some(max), Some(ttl)) = (self.expiry.max_lifetime, self.expiry.default_ttl)
+            && ttl > max
+        {
+            problems.push(format!(
+                "expiry.default_ttl (LINK_DEFAULT_TTL) {ttl} exceeds expiry.max_lifetime (LINK_MAX_LIFETIME) {max}"
+            ));
+        }
+
+        if !(1..=MAX_CODE_LENGTH).contains(&self.codes.length) {
+            problems.push(format!(
+                "codes.length (LENGTH_CODE) must be between 1 and {MAX_CODE_LENGTH}, got {}",
+                self.codes.length
+            ));
+        }
+        if self.codes.strategy == CodeStrategy::Hashids && self.codes.hashids_salt.is_empty() {
+            problems.push(
+                "codes.hashids_salt (HASHIDS_SALT) must be set for the hashids strategy"
+                    .to_string(),
+            );
+        }
+
+        for base in &self.targets.public_base_urls {
+            if let Err(e) = Url::parse(base) {
+                problems.push(format!(
+                    "targets.public_base_urls (PUBLIC_BASE_URLS) entry {base:?} is not a URL: {e}"
+                ));
+            }
+        }
+        if self.targets.max_redirect_hops == 0 {
+            problems.push(
+                "targets.max_redirect_hops (MAX_REDIRECT_HOPS) must be at least 1".to_string(),
+            );
+        }
+
+        if self.health_check.timeout_secs == 0 {
+            problems.push(
+                "health_check.timeout_secs (HEALTH_CHECK_TIMEOUT_SECS) must be at least 1"
+                    .to_string(),
+            );
+        }
+        if self.health_check.concurrency == 0 {
+            problems.push(
+                "health_check.concurrency (HEALTH_CHECK_CONCURRENCY) must be at least 1"
+                    .to_string(),
+            );
+        }
+
+        let webhooks = &self.webhooks;
+        if webhooks.timeout_secs == 0 {
+            problems.push(
+                "webhooks.timeout_secs (WEBHOOK_TIMEOUT_SECS) must be at least 1".to_string(),
+            );
+        }
+        if webhooks.max_attempts < 1 {
+            problems.push(
+                "webhooks.max_attempts (WEBHOOK_MAX_ATTEMPTS) must be at least 1".to_string(),
+            );
+        }
+        if webhooks.backoff_base_secs == 0 {
+            problems.push(
+                "webhooks.backoff_base_secs (WEBHOOK_BACKOFF_BASE_SECS) must be at least 1"
+                    .to_string(),
+            );
+        }
+        if webhooks.backoff_max_secs < webhooks.backoff_base_secs {
+            problems.push(format!(
+                "webhooks.backoff_max_secs (WEBHOOK_BACKOFF_MAX_SECS) must be at least backoff_base_secs ({})",
+                webhooks.backoff_base_secs
+            ));
+        }
+        if webhooks.batch_size < 1 {
+            problems
+                .push("webhooks.batch_size (WEBHOOK_BATCH_SIZE) must be at least 1".to_string());
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(ConfigError::Invalid(problems))
+        }
+    }
+}
+
+impl ExpiryConfig {
+    pub fn expiry_policy(&self) -> ExpiryPolicy {
+        ExpiryPolicy::new(self.max_lifetime, self.default_ttl)
+    }
+}
+
+impl CodeConfig {
+    pub fn code_generator(&self) -> Arc<dyn CodeGenerator> {
+        build_code_generator(
+            self.strategy,
+            self.length,
+            &self.hashids_salt,
+            self.seed,
+            self.case_insensitive,
+        )
+    }
+}
+
+impl TargetConfig {
+    pub fn url_policy(&self) -> UrlPolicy {
+        UrlPolicy::new(self.blocked_domains.clone(), self.allowed_domains.clone())
+            .with_extra_schemes(self.extra_schemes.clone())
+    }
+
+    /// Targets on our own hosts are rejected unless `follow_self_redirects`
+    /// is set, which instead follows the chain of codes and rejects loops
+    pub fn self_reference_policy(&self) -> SelfReferencePolicy {
+        // Already validated by `AppConfig::validate`
+        let base_urls = self
+            .public_base_urls
+            .iter()
+            .filter_map(|s| Url::parse(s).ok())
+            .collect();
+        let policy = SelfReferencePolicy::new(base_urls);
+
+        if self.follow_self_redirects {
+            policy.with_chain_following(self.max_redirect_hops)
+        } else {
+            policy
+        }
+    }
+}
+
+impl SchedulerConfig {
+    /// The configured id, else `<HOSTNAME>-<pid>`, unique per process even
+    /// when replicas share a host
+    pub fn instance_id(&self) -> String {
+        let configured = self.instance_id.trim();
+        if !configured.is_empty() {
+            return configured.to_string();
+        }
+        let host = env_value("HOSTNAME").unwrap_or_else(|| "localhost".to_string());
+        format!("{host}-{}", std::process::id())
+    }
+
+    pub fn lease_ttl(&self) -> TimeDelta {
+        TimeDelta::seconds(self.lease_ttl_secs as i64)
+    }
+}
+
+impl JobConfig {
+    /// Override from the variables named `[enabled, schedule, timeout_secs]`
+    fn apply_env(
+        &mut self,
+        [enabled, schedule, timeout]: [&'static str; 3],
+    ) -> Result<(), ConfigError> {
+        env_parse(enabled, &mut self.enabled)?;
+        if let Some(value) = env_value(schedule) {
+            self.schedule = Some(parse(schedule, value)?);
+        }
+        env_parse(timeout, &mut self.timeout_secs)
+    }
+
+    pub fn timeout(&self) -> Option<Duration> {
+        (self.timeout_secs > 0).then(|| Duration::from_secs(self.timeout_secs))
+    }
+}
+
+impl ServerConfig {
+    pub fn shutdown_timeout(&self) -> Duration {
+        Duration::from_secs(self.shutdown_timeout_secs)
+    }
+}
+
+impl CleanupConfig {
+    pub fn interval(&self) -> Duration {
+        Duration::from_secs(self.interval_secs)
+    }
+}
+
+impl HealthCheckConfig {
+    pub fn enabled(&self) -> bool {
+        self.interval_secs > 0
+    }
+
+    pub fn interval(&self) -> Duration {
+        Duration::from_secs(self.interval_secs)
+    }
+
+    pub fn timeout(&self) -> Duration {
+        Duration::from_secs(self.timeout_secs)
+    }
+
+    pub fn host_delay(&self) -> Duration {
+        Duration::from_millis(self.host_delay_ms)
+    }
+}
+
+impl WebhookConfig {
+    pub fn enabled(&self) -> bool {
+        self.dispatch_interval_secs > 0
+    }
+
+    pub fn dispatch_interval(&self) -> Duration {
+        Duration::from_secs(self.dispatch_interval_secs)
+    }
+}
+
+/// Environment variable, treating blank values as unset
+fn env_value(key: &str) -> Option<String> {
+    env::var(key).ok().filter(|v| !v.trim().is_empty())
+}
+
+fn env_parse<T>(key: &'static str, target: &mut T) -> Result<(), ConfigError>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    if let Some(value) = env_value(key) {
+        *target = parse(key, value)?;
+    }
+    Ok(())
+}
+
+fn parse<T>(key: &'static str, value: String) -> Result<T, ConfigError>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    value.trim().parse().map_err(|e: T::Err| ConfigError::Env {
+        key,
+        reason: e.to_string(),
+        value,
+    })
+}
+
+/// Comma separated list, replacing the configured one when set
+fn env_list(key: &str, target: &mut Vec<String>) {
+    if let Some(value) = env_value(key) {
+        *target = value
+            .split(',')
+            .map(|s| s.trim().to_string())
+            .filter(|s| !s.is_empty())
+            .collect();
+    }
+}
+
+fn from_str<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr,
+    T::Err: Display,
+{
+    let raw = String::deserialize(deserializer)?;
+    raw.parse().map_err(serde::de::Error::custom)
+}
+
+fn option_from_str<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr,
+    T::Err: Display,
+{
+    match Option::<String>::deserialize(deserializer)? {
+        Some(raw) if !raw.trim().is_empty() => {
+            raw.parse().map(Some).map_err(serde::de::Error::custom)
+        }
+        _ => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn valid() -> AppConfig {
+        let mut config = AppConfig::default();
+        config.database.url = "postgres://localhost/url_shortener".to_string();
+        config
+    }
+
+    fn problems(config: &AppConfig) -> Vec<String> {
+        match config.validate() {
+            Ok(()) => Vec::new(),
+            Err(ConfigError::Invalid(problems)) => problems,
+            Err(e) => panic!("unexpected error {e}"),
+        }
+    }
+
+    #[test]
+    fn the_environment_overrides_the_file() {
+        let path = env::temp_dir().join(format!("url-shortener-{}.toml", std::process::id()));
+        std::fs::write(
+            &path,
+            r#"
+            [server]
+            port = 7000
+
+            [database]
+            url = "postgres://localhost/from_file"
+
+            [codes]
+            length = 8
+
+            [health_check]
+            concurrency = 3
+            "#,
+        )
+        .unwrap();
+
+        // SAFETY: no other test reads these variables, and std serializes its
+        // own environment access
+        unsafe {
+            env::set_var("CONFIG_FILE", &path);
+            env::set_var("APP_PORT", "7100");
+            // Blank is unset, the file's value stays
+            env::set_var("HEALTH_CHECK_CONCURRENCY", " ");
+        }
+        let loaded = AppConfig::load();
+        unsafe { env::set_var("APP_PORT", "http") }
+        let invalid = AppConfig::load();
+        unsafe {
+            env::remove_var("CONFIG_FILE");
+            env::remove_var("APP_PORT");
+            env::remove_var("HEALTH_CHECK_CONCURRENCY");
+        }
+        std::fs::remove_file(&path).unwrap();
+
+        let config = loaded.unwrap();
+        assert_eq!(config.server.port, 7100);
+        assert_eq!(config.codes.length, 8);
+        assert_eq!(config.health_check.concurrency, 3);
+        // Untouched by either
+        assert_eq!(config.cleanup.interval_secs, 60);
+
+        let err = invalid.unwrap_err().to_string();
+        assert!(err.starts_with("invalid APP_PORT=\"http\": "), "{err}");
+    }
+
+    #[test]
+    fn unknown_file_keys_are_refused() {
+        let err = toml::from_str::<AppConfig>("[server]\nprot = 80\n").unwrap_err();
+        assert!(err.to_string().contains("unknown field `prot`"), "{err}");
+    }
+
+    #[test]
+    fn every_problem_is_reported_at_once() {
+        let mut config = valid();
+        config.server.port = 0;
+        config.database.url = String::new();
+        config.codes.strategy = CodeStrategy::Hashids;
+        config.health_check.concurrency = 0;
+
+        let problems = problems(&config);
+        assert_eq!(
+            problems,
+            [
+                "server.port (APP_PORT) must not be 0",
+                "database.url (DATABASE_URL) must be set",
+                "codes.hashids_salt (HASHIDS_SALT) must be set for the hashids strategy",
+                "health_check.concurrency (HEALTH_CHECK_CONCURRENCY) must be at least 1",
+            ]
+        );
+        let message = ConfigError::Invalid(problems).to_string();
+        assert!(message.starts_with("invalid configuration:\n  - server.port"));
+        assert_eq!(message.lines().count(), 5);
+    }
+
+    #[test]
+    fn code_length_is_bounded() {
+        assert!(problems(&valid()).is_empty());
+        for length in [1, MAX_CODE_LENGTH] {
+            let mut config = valid();
+            config.codes.length = length;
+            assert!(problems(&config).is_empty(), "{length}");
+        }
+        for length in [0, MAX_CODE_LENGTH + 1] {
+            let mut config = valid();
+            config.codes.length = length;
+            assert_eq!(
+                problems(&config),
+                [format!(
+                    "codes.length (LENGTH_CODE) must be between 1 and 10, got {length}"
+                )]
+            );
+        }
+    }
+}
@@ -10,4 +10,110 @@ pub struct ShortUrl {
     pub clicks: i64,
     pub created_at: DateTime<Utc>,
     pub expires_at: Option<DateTime<Utc>>,
+    pub health_status_code: Option<i32>,
+    pub health_latency_ms: Option<i32>,
+    pub health_error: Option<String>,
+    pub health_checked_at: Option<DateTime<Utc>>,
+    /// Set once the link expired and cleanup archived it instead of deleting
+    /// it. The code stays taken
+    pub archived_at: Option<DateTime<Utc>>,
+}
+
+impl ShortUrl {
+    pub fn with_health(mut self, health: &LinkHealth) -> Self {
+        self.health_status_code = health.status_code;
+        self.health_latency_ms = Some(health.latency_ms);
+        self.health_error = health.error.clone();
+        self.health_checked_at = Some(health.checked_at);
+        self
+    }
+
+    /// Last health check got an error status or could not reach the target
+    pub fn is_broken(&self) -> bool {
+        self.health_checked_at.is_some()
+            && (self.health_error.is_some() || self.health_status_code.is_some_and(|s| s >= 400))
+    }
+}
+
+/// Outcome of probing a link's target URL
+#[derive(Debug, Clone)]
+pub struct LinkHealth {
+    pub status_code: Option<i32>,
+    pub latency_ms: i32,
+    pub error: Option<String>,
+    pub checked_at: DateTime<Utc>,
+}
+
+/// Endpoint receiving link events, signed with its `secret`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookSubscription {
+    pub id: Uuid,
+    pub url: String,
+    pub secret: String,
+    /// Names of the subscribed events, e.g. `link.expired`
+    pub events: Vec<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// One event for one subscription in the outbox, with its delivery state
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookDelivery {
+    pub id: Uuid,
+    pub subscription_id: Uuid,
+    pub event: String,
+    /// JSON body sent as is, so the signature covers the exact bytes
+    pub payload: String,
+    /// `pending`, `delivered` or `failed` (attempts exhausted)
+    pub status: String,
+    pub attempts: i32,
+    pub next_attempt_at: DateTime<Utc>,
+    pub last_status_code: Option<i32>,
+    pub last_error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub delivered_at: Option<DateTime<Utc>>,
+}
+
+impl WebhookDelivery {
+    pub const PENDING: &'static str = "pending";
+    pub const DELIVERED: &'static str = "delivered";
+    pub const FAILED: &'static str = "failed";
+}
+
+/// Link event in the outbox, recorded in the transaction of the change that
+/// caused it, until it is turned into deliveries for the subscribed webhooks
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LinkEvent {
+    pub id: Uuid,
+    /// Event name, e.g. `link.created`
+    pub kind: String,
+    /// The link as the change left it
+    pub link: ShortUrl,
+    pub occurred_at: DateTime<Utc>,
+}
+
+/// Lease on periodic work, held by one instance at a time until it expires
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SchedulerLease {
+    pub name: String,
+    /// Instance id of the holder
+    pub holder: String,
+    /// Since when the holder has held it without interruption
+    pub acquired_at: DateTime<Utc>,
+    pub renewed_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+}
+
+/// Key for calling the API. Only a hash of the secret is stored, the secret
+/// itself is shown once when the key is minted
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiKey {
+    pub id: Uuid,
+    /// What the key is for, e.g. the team or integration holding it
+    pub name: String,
+    /// Start of the secret, to tell keys apart without revealing them
+    pub prefix: String,
+    /// Hex SHA-256 of the secret
+    pub key_hash: String,
+    pub created_at: DateTime<Utc>,
+    pub revoked_at: Option<DateTime<Utc>>,
 }
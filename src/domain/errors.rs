@@ -0,0 +1,41 @@
+use crate::domain::validators::expiry::ExpiryError;
+use crate::domain::validators::url_validator::UrlValidationError;
+use chrono::{DateTime, Utc};
+use thiserror::Error;
+
+/// Failures surfaced by repositories and services
+#[derive(Debug, Error)]
+pub enum DomainError {
+    #[error("short url not found")]
+    NotFound,
+
+    /// Something other than a short link, e.g. `webhook subscription`
+    #[error("{0} not found")]
+    ResourceNotFound(&'static str),
+
+    #[error("short url expired at {at}")]
+    Expired { at: DateTime<Utc> },
+
+    /// The operation clashes with existing data, e.g. a short code already taken
+    #[error("{0}")]
+    Conflict(String),
+
+    #[error(transparent)]
+    Validation(#[from] UrlValidationError),
+
+    #[error(transparent)]
+    Expiry(#[from] ExpiryError),
+
+    /// A request field broke `rule`
+    #[error("{message}")]
+    Invalid {
+        field: &'static str,
+        rule: &'static str,
+        message: String,
+    },
+
+    #[error("storage error: {0}")]
+    Storage(#[source] anyhow::Error),
+}
+
+pub type DomainResult<T> = Result<T, DomainError>;
@@ -0,0 +1,57 @@
+use crate::domain::errors::DomainResult;
+use async_trait::async_trait;
+use std::fmt;
+use std::str::FromStr;
+
+/// Link lifecycle events webhooks can subscribe to. Repositories record them
+/// in an outbox in the same transaction as the change, so none is lost.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkEventKind {
+    Created,
+    /// The target or expiry was edited, or the health check started or
+    /// stopped failing
+    Updated,
+    Deleted,
+    /// Archived or removed by the cleanup sweep after its expiry
+    Expired,
+}
+
+impl LinkEventKind {
+    pub const ALL: [Self; 4] = [Self::Created, Self::Updated, Self::Deleted, Self::Expired];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Created => "link.created",
+            Self::Updated => "link.updated",
+            Self::Deleted => "link.deleted",
+            Self::Expired => "link.expired",
+        }
+    }
+}
+
+impl FromStr for LinkEventKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|kind| kind.as_str() == s.trim())
+            .ok_or_else(|| {
+                let known: Vec<_> = Self::ALL.iter().map(|k| k.as_str()).collect();
+                format!("unknown event {s:?}, expected one of {}", known.join(", "))
+            })
+    }
+}
+
+impl fmt::Display for LinkEventKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Hands link events recorded in the outbox on to their receivers
+#[async_trait]
+pub trait LinkEventRelay: Send + Sync {
+    /// Relay up to `limit` recorded events, returning how many were taken
+    async fn relay(&self, limit: i64) -> DomainResult<usize>;
+}
@@ -0,0 +1,450 @@
+use rand::{Rng, SeedableRng, rngs::StdRng};
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+
+pub const BASE62_ALPHABET: &[u8] =
+    b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+
+/// Base58 without the easily confused `0`, `O`, `I` and `l`
+pub const BASE58_ALPHABET: &[u8] = b"123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+
+/// Single-case alphabet for case-insensitive codes
+pub const BASE36_ALPHABET: &[u8] = b"0123456789abcdefghijklmnopqrstuvwxyz";
+
+/// Lowercase without the easily confused `0`, `1`, `i`, `l` and `o`
+pub const BASE31_ALPHABET: &[u8] = b"23456789abcdefghjkmnpqrstuvwxyz";
+
+/// Input for generating one candidate code
+#[derive(Debug, Clone)]
+pub struct CodeRequest<'a> {
+    pub target_url: &'a str,
+    /// Next value of the code sequence, set when the generator uses one
+    pub sequence: Option<i64>,
+    /// 0 on the first try, incremented after every collision
+    pub attempt: u32,
+}
+
+/// Strategy producing short codes for new links
+pub trait CodeGenerator: Send + Sync {
+    fn generate(&self, req: &CodeRequest<'_>) -> String;
+
+    /// Whether the service must fetch a value from the code sequence first
+    fn uses_sequence(&self) -> bool {
+        false
+    }
+}
+
+/// Code strategy selected through config
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CodeStrategy {
+    Random,
+    Base58,
+    Sequential,
+    Hashids,
+    Hash,
+}
+
+impl FromStr for CodeStrategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "random" | "base62" => Ok(Self::Random),
+            "base58" => Ok(Self::Base58),
+            "sequential" => Ok(Self::Sequential),
+            "hashids" => Ok(Self::Hashids),
+            "hash" => Ok(Self::Hash),
+            other => Err(format!(
+                "unknown code strategy {other:?}, expected random, base58, sequential, hashids or hash"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for CodeStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Random => "random",
+            Self::Base58 => "base58",
+            Self::Sequential => "sequential",
+            Self::Hashids => "hashids",
+            Self::Hash => "hash",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Build the generator for a strategy. `salt` is only used by `Hashids`, and
+/// `seed` makes the random strategies reproducible (e.g. for end-to-end tests).
+/// With `case_insensitive` every strategy emits lowercase codes only.
+pub fn build_code_generator(
+    strategy: CodeStrategy,
+    length: usize,
+    salt: &str,
+    seed: Option<u64>,
+    case_insensitive: bool,
+) -> Arc<dyn CodeGenerator> {
+    let (alphabet, unambiguous) = if case_insensitive {
+        (BASE36_ALPHABET, BASE31_ALPHABET)
+    } else {
+        (BASE62_ALPHABET, BASE58_ALPHABET)
+    };
+    let random = |alphabet| {
+        let generator = RandomCodeGenerator::new(alphabet, length);
+        match seed {
+            Some(seed) => generator.seeded(seed),
+            None => generator,
+        }
+    };
+
+    match strategy {
+        CodeStrategy::Random => Arc::new(random(alphabet)),
+        CodeStrategy::Base58 => Arc::new(random(unambiguous)),
+        CodeStrategy::Sequential => Arc::new(SequentialCodeGenerator::new(alphabet, length)),
+        CodeStrategy::Hashids => Arc::new(HashidsCodeGenerator::new(alphabet, length, salt)),
+        CodeStrategy::Hash => Arc::new(TargetHashCodeGenerator::new(alphabet, length)),
+    }
+}
+
+/// Random codes drawn from an alphabet
+pub struct RandomCodeGenerator {
+    alphabet: &'static [u8],
+    length: usize,
+    rng: Mutex<StdRng>,
+}
+
+impl RandomCodeGenerator {
+    pub fn base62(length: usize) -> Self {
+        Self::new(BASE62_ALPHABET, length)
+    }
+
+    pub fn new(alphabet: &'static [u8], length: usize) -> Self {
+        Self {
+            alphabet,
+            length,
+            rng: Mutex::new(StdRng::from_entropy()),
+        }
+    }
+
+    /// Reproducible sequence of codes
+    pub fn seeded(mut self, seed: u64) -> Self {
+        self.rng = Mutex::new(StdRng::seed_from_u64(seed));
+        self
+    }
+}
+
+impl CodeGenerator for RandomCodeGenerator {
+    fn generate(&self, _req: &CodeRequest<'_>) -> String {
+        let mut rng = self.rng.lock().unwrap_or_else(|e| e.into_inner());
+        (0..self.length)
+            .map(|_| char::from(self.alphabet[rng.gen_range(0..self.alphabet.len())]))
+            .collect()
+    }
+}
+
+/// Encoding of a database sequence: short, dense and guessable
+pub struct SequentialCodeGenerator {
+    alphabet: &'static [u8],
+    min_length: usize,
+}
+
+impl SequentialCodeGenerator {
+    pub fn new(alphabet: &'static [u8], min_length: usize) -> Self {
+        Self {
+            alphabet,
+            min_length,
+        }
+    }
+}
+
+impl CodeGenerator for SequentialCodeGenerator {
+    fn generate(&self, req: &CodeRequest<'_>) -> String {
+        let n = req
+            .sequence
+            .expect("sequential codes need a sequence value");
+        encode(n.unsigned_abs() as u128, self.alphabet, self.min_length)
+    }
+
+    fn uses_sequence(&self) -> bool {
+        true
+    }
+}
+
+/// Hashids-style codes: the sequence value is scrambled with a bijective
+/// multiplication and encoded in an alphabet shuffled by a secret salt, so
+/// codes stay unique without revealing how many links exist.
+pub struct HashidsCodeGenerator {
+    alphabet: Vec<u8>,
+    length: usize,
+}
+
+impl HashidsCodeGenerator {
+    /// Largest prime below 2^64, coprime with base^n for any alphabet size
+    const MULTIPLIER: u128 = 0xFFFF_FFFF_FFFF_FFC5;
+
+    pub fn new(alphabet: &[u8], length: usize, salt: &str) -> Self {
+        Self {
+            alphabet: consistent_shuffle(alphabet, salt.as_bytes()),
+            length,
+        }
+    }
+}
+
+impl CodeGenerator for HashidsCodeGenerator {
+    fn generate(&self, req: &CodeRequest<'_>) -> String {
+        let n = req.sequence.expect("hashids codes need a sequence value") as u128;
+        let space = code_space(self.alphabet.len(), self.length);
+
+        // Past the code space the value no longer fits, fall back to a plain
+        // (longer) encoding which is still unique.
+        let scrambled = if n < space {
+            mul_mod(n, Self::MULTIPLIER % space, space)
+        } else {
+            n
+        };
+        encode(scrambled, &self.alphabet, self.length)
+    }
+
+    fn uses_sequence(&self) -> bool {
+        true
+    }
+}
+
+/// Deterministic codes derived from a hash of the target URL. Collisions are
+/// resolved by mixing the attempt number into the hash.
+///
+/// The code is only stable for the first link to a target: shortening the
+/// same target again collides with that link and gets the code of the next
+/// attempt, so every request still creates its own link until the service
+/// runs out of attempts.
+pub struct TargetHashCodeGenerator {
+    alphabet: &'static [u8],
+    length: usize,
+}
+
+impl TargetHashCodeGenerator {
+    pub fn new(alphabet: &'static [u8], length: usize) -> Self {
+        Self { alphabet, length }
+    }
+}
+
+impl CodeGenerator for TargetHashCodeGenerator {
+    fn generate(&self, req: &CodeRequest<'_>) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(req.target_url.as_bytes());
+        if req.attempt > 0 {
+            hasher.update(req.attempt.to_be_bytes());
+        }
+        let digest = hasher.finalize();
+
+        let n = u128::from_be_bytes(digest[..16].try_into().expect("digest is 32 bytes"));
+        let space = code_space(self.alphabet.len(), self.length);
+        encode(n % space, self.alphabet, self.length)
+    }
+}
+
+/// Number of distinct codes of `length` symbols
+fn code_space(alphabet_len: usize, length: usize) -> u128 {
+    (alphabet_len as u128)
+        .checked_pow(length as u32)
+        .unwrap_or(u128::MAX)
+}
+
+/// Encode `n` in `alphabet`, left padded with the alphabet's first symbol
+fn encode(mut n: u128, alphabet: &[u8], min_length: usize) -> String {
+    let base = alphabet.len() as u128;
+    let mut out = Vec::new();
+
+    loop {
+        out.push(alphabet[(n % base) as usize]);
+        n /= base;
+        if n == 0 {
+            break;
+        }
+    }
+    while out.len() < min_length {
+        out.push(alphabet[0]);
+    }
+
+    out.iter().rev().map(|&b| char::from(b)).collect()
+}
+
+/// `a * b % m` without overflowing u128
+fn mul_mod(mut a: u128, mut b: u128, m: u128) -> u128 {
+    let mut result = 0u128;
+    a %= m;
+    while b > 0 {
+        if b & 1 == 1 {
+            result = (result + a) % m;
+        }
+        a = (a << 1) % m;
+        b >>= 1;
+    }
+    result
+}
+
+/// Hashids' salt-driven Fisher-Yates shuffle
+fn consistent_shuffle(alphabet: &[u8], salt: &[u8]) -> Vec<u8> {
+    let mut alphabet = alphabet.to_vec();
+    if salt.is_empty() {
+        return alphabet;
+    }
+
+    let (mut v, mut p) = (0usize, 0usize);
+    for i in (1..alphabet.len()).rev() {
+        v %= salt.len();
+        let n = salt[v] as usize;
+        p += n;
+        let j = (n + v + p) % i;
+        alphabet.swap(i, j);
+        v += 1;
+    }
+    alphabet
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    fn request(target_url: &str, sequence: Option<i64>, attempt: u32) -> CodeRequest<'_> {
+        CodeRequest {
+            target_url,
+            sequence,
+            attempt,
+        }
+    }
+
+    fn codes(generator: &dyn CodeGenerator, count: usize) -> Vec<String> {
+        (0..count)
+            .map(|_| generator.generate(&request("https://example.com/", None, 0)))
+            .collect()
+    }
+
+    /// Inverse of `HashidsCodeGenerator::generate` within the code space
+    fn decode_hashid(code: &str, alphabet: &[u8], space: u128) -> u128 {
+        let base = alphabet.len() as u128;
+        let scrambled = code.bytes().fold(0u128, |n, c| {
+            n * base + alphabet.iter().position(|&a| a == c).unwrap() as u128
+        });
+
+        // Modular inverse of the multiplier by the extended Euclidean algorithm
+        let (mut r0, mut r1) = (
+            (HashidsCodeGenerator::MULTIPLIER % space) as i128,
+            space as i128,
+        );
+        let (mut s0, mut s1) = (1i128, 0i128);
+        while r1 != 0 {
+            let q = r0 / r1;
+            (r0, r1) = (r1, r0 - q * r1);
+            (s0, s1) = (s1, s0 - q * s1);
+        }
+        assert_eq!(r0, 1, "multiplier must be invertible");
+        let inverse = s0.rem_euclid(space as i128) as u128;
+        mul_mod(scrambled, inverse, space)
+    }
+
+    #[test]
+    fn seeded_random_codes_are_reproducible() {
+        let first = RandomCodeGenerator::base62(10).seeded(42);
+        let second = RandomCodeGenerator::base62(10).seeded(42);
+        let other = RandomCodeGenerator::base62(10).seeded(43);
+
+        let codes_first = codes(&first, 20);
+        assert_eq!(codes_first, codes(&second, 20));
+        assert_ne!(codes_first, codes(&other, 20));
+        assert!(
+            codes_first
+                .iter()
+                .all(|code| code.len() == 10 && code.bytes().all(|c| BASE62_ALPHABET.contains(&c)))
+        );
+    }
+
+    #[test]
+    fn unambiguous_alphabets_leave_out_confusable_symbols() {
+        assert_eq!(BASE58_ALPHABET.len(), 58);
+        assert_eq!(BASE31_ALPHABET.len(), 31);
+        for c in b"0OIl" {
+            assert!(!BASE58_ALPHABET.contains(c), "{} in base58", char::from(*c));
+        }
+        for c in b"01ilo" {
+            assert!(!BASE31_ALPHABET.contains(c), "{} in base31", char::from(*c));
+        }
+        for alphabet in [
+            BASE62_ALPHABET,
+            BASE58_ALPHABET,
+            BASE36_ALPHABET,
+            BASE31_ALPHABET,
+        ] {
+            let unique: HashSet<_> = alphabet.iter().collect();
+            assert_eq!(unique.len(), alphabet.len());
+        }
+
+        let generator = build_code_generator(CodeStrategy::Base58, 12, "", Some(7), false);
+        for code in codes(generator.as_ref(), 200) {
+            assert!(!code.contains(['0', 'O', 'I', 'l']), "{code}");
+        }
+        let generator = build_code_generator(CodeStrategy::Base58, 12, "", Some(7), true);
+        for code in codes(generator.as_ref(), 200) {
+            assert!(!code.contains(['0', '1', 'i', 'l', 'o']), "{code}");
+            assert_eq!(code, code.to_lowercase());
+        }
+    }
+
+    #[test]
+    fn hashids_round_trip_to_the_sequence_value() {
+        let generator = HashidsCodeGenerator::new(BASE62_ALPHABET, 8, "pepper");
+        let space = code_space(BASE62_ALPHABET.len(), 8);
+        for n in [0, 1, 2, 61, 62, 1_000, 123_456_789, space as i64 - 1] {
+            let code = generator.generate(&request("", Some(n), 0));
+            assert_eq!(code.len(), 8);
+            assert_eq!(decode_hashid(&code, &generator.alphabet, space), n as u128);
+        }
+    }
+
+    #[test]
+    fn hashids_are_unique_per_sequence_value() {
+        // A small code space, so it is covered entirely and then exceeded
+        let generator = HashidsCodeGenerator::new(BASE36_ALPHABET, 2, "pepper");
+        let space = code_space(BASE36_ALPHABET.len(), 2) as i64;
+
+        let mut seen = HashSet::new();
+        for n in 0..space + 100 {
+            let code = generator.generate(&request("", Some(n), 0));
+            assert_eq!(code.len(), if n < space { 2 } else { 3 }, "{n}: {code}");
+            assert!(seen.insert(code), "duplicate code for {n}");
+        }
+
+        // Consecutive values don't give consecutive codes, and the salt matters
+        let one = generator.generate(&request("", Some(1), 0));
+        let two = generator.generate(&request("", Some(2), 0));
+        assert_ne!(encode(1, BASE36_ALPHABET, 2), one);
+        assert_ne!(encode(2, BASE36_ALPHABET, 2), two);
+        let resalted = HashidsCodeGenerator::new(BASE36_ALPHABET, 2, "salt");
+        assert_ne!(resalted.generate(&request("", Some(1), 0)), one);
+    }
+
+    #[test]
+    fn target_hash_is_stable_until_a_retry() {
+        let generator = TargetHashCodeGenerator::new(BASE62_ALPHABET, 10);
+        let target = "https://example.com/page";
+
+        let first = generator.generate(&request(target, None, 0));
+        assert_eq!(first.len(), 10);
+        assert_eq!(generator.generate(&request(target, None, 0)), first);
+        assert_ne!(
+            generator.generate(&request("https://example.com/other", None, 0)),
+            first
+        );
+
+        // Every retry after a collision gets its own, equally stable, code
+        let retries: Vec<_> = (1..5)
+            .map(|attempt| generator.generate(&request(target, None, attempt)))
+            .collect();
+        let unique: HashSet<_> = retries.iter().chain([&first]).collect();
+        assert_eq!(unique.len(), 5);
+        assert_eq!(generator.generate(&request(target, None, 1)), retries[0]);
+    }
+}
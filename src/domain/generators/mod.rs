@@ -0,0 +1 @@
+pub mod code_generator;
@@ -1,4 +1,7 @@
 pub mod entities;
+pub mod errors;
+pub mod events;
+pub mod generators;
 pub mod repositories;
 pub mod utils;
 pub mod validators;
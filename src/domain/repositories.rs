@@ -1,10 +1,25 @@
-use crate::domain::entities::ShortUrl;
-use anyhow::Result;
-use chrono::{DateTime, Utc};
+use crate::domain::entities::{
+    ApiKey, LinkEvent, LinkHealth, SchedulerLease, ShortUrl, WebhookDelivery, WebhookSubscription,
+};
+use crate::domain::errors::DomainResult as Result;
+use chrono::{DateTime, TimeDelta, Utc};
 use uuid::Uuid;
 
+/// Filters for listing short URLs
+#[derive(Debug, Clone, Default)]
+pub struct UrlFilter {
+    /// Only links whose last health check failed (`true`) or did not (`false`)
+    pub broken: Option<bool>,
+    /// Archived links instead of the live ones
+    pub archived: bool,
+}
+
+/// Short links. Changes to a link record the matching link event in the
+/// outbox in the same transaction, when a webhook subscribes to it
 #[async_trait::async_trait]
 pub trait UrlRepository: Send + Sync {
+    /// Records `link.created`. Fails with `DomainError::Conflict` when the
+    /// short code is taken, ignoring case with case-insensitive codes
     async fn create(
         &self,
         short_code: &str,
@@ -13,7 +28,96 @@ pub trait UrlRepository: Send + Sync {
     ) -> Result<ShortUrl>;
     async fn find_by_code(&self, code: &str) -> Result<Option<ShortUrl>>;
     async fn increments_clicks(&self, id: Uuid) -> Result<()>;
-    async fn get_all_url(&self) -> Result<Vec<ShortUrl>>;
-    async fn delete_expired_url(&self) -> Result<u64>;
-    async fn delete_by_code(&self, code: &str) -> Result<(), anyhow::Error>;
+    async fn get_all_url(&self, filter: &UrlFilter) -> Result<Vec<ShortUrl>>;
+    async fn find_active_urls(&self) -> Result<Vec<ShortUrl>>;
+    /// Records `link.updated` when the link starts or stops being broken
+    async fn update_health(&self, id: Uuid, health: &LinkHealth) -> Result<()>;
+    /// Save the target, expiry, health and archive state of an existing
+    /// link, recording `link.updated`. Fails with `DomainError::NotFound`
+    /// when it is gone
+    async fn update_url(&self, link: &ShortUrl) -> Result<()>;
+    /// Delete links past their expiry that aren't archived, returning them.
+    /// Records `link.expired` for each
+    async fn delete_expired_url(&self) -> Result<Vec<ShortUrl>>;
+    /// Mark links past their expiry archived, returning them. They keep
+    /// their code and clicks. Records `link.expired` for each
+    async fn archive_expired_url(&self) -> Result<Vec<ShortUrl>>;
+    /// Delete links archived before `before`, returning how many
+    async fn purge_archived_url(&self, before: DateTime<Utc>) -> Result<u64>;
+    async fn next_code_sequence(&self) -> Result<i64>;
+    /// Codes that would collide if compared case-insensitively (lowercased)
+    async fn find_case_collisions(&self) -> Result<Vec<String>>;
+    /// Reserve the lowercased codes of existing links, so new codes that only
+    /// differ by case conflict. Run once no collisions are left, returns how
+    /// many were reserved
+    async fn reserve_case_insensitive_codes(&self) -> Result<u64>;
+    /// Returns the deleted link and records `link.deleted`. Fails with
+    /// `DomainError::NotFound` when no link has this code
+    async fn delete_by_code(&self, code: &str) -> Result<ShortUrl>;
+}
+
+/// Webhook subscriptions, the outbox of link events and their deliveries
+#[async_trait::async_trait]
+pub trait WebhookRepository: Send + Sync {
+    async fn create_subscription(
+        &self,
+        url: &str,
+        secret: &str,
+        events: &[String],
+    ) -> Result<WebhookSubscription>;
+    async fn list_subscriptions(&self) -> Result<Vec<WebhookSubscription>>;
+    /// Drops its deliveries too. Fails with `DomainError::NotFound` when unknown
+    async fn delete_subscription(&self, id: Uuid) -> Result<()>;
+    /// Up to `limit` recorded link events, oldest first, held back from
+    /// other relays for `lease`
+    async fn claim_link_events(&self, limit: i64, lease: TimeDelta) -> Result<Vec<LinkEvent>>;
+    /// Queue a pending delivery of `payload`, due now, for every subscription
+    /// to the event and remove the event from the outbox, in one transaction.
+    /// Returns how many were queued
+    async fn enqueue_event_deliveries(&self, event: &LinkEvent, payload: &str) -> Result<u64>;
+    /// Up to `limit` pending deliveries that are due, pushed back by `lease`
+    /// so another instance doesn't send them at the same time
+    async fn claim_due_deliveries(
+        &self,
+        limit: i64,
+        lease: TimeDelta,
+    ) -> Result<Vec<WebhookDelivery>>;
+    /// Store the outcome of an attempt: status, attempts, next attempt,
+    /// last status code and error, delivery time
+    async fn update_delivery(&self, delivery: &WebhookDelivery) -> Result<()>;
+    /// Newest first, optionally only those with `status`
+    async fn list_deliveries(
+        &self,
+        status: Option<&str>,
+        limit: i64,
+    ) -> Result<Vec<WebhookDelivery>>;
+    /// Make a delivery pending and due now with its attempts reset. Fails
+    /// with `DomainError::NotFound` when unknown
+    async fn replay_delivery(&self, id: Uuid) -> Result<WebhookDelivery>;
+}
+
+/// Leases electing the instance that runs periodic work. Expiry is judged by
+/// the database clock where there is one, so replica clocks don't matter.
+#[async_trait::async_trait]
+pub trait LeaseRepository: Send + Sync {
+    /// Take the lease when it is free or expired, or renew it when `holder`
+    /// already has it, for `ttl` from now. Returns whether `holder` has it
+    async fn try_acquire(&self, name: &str, holder: &str, ttl: TimeDelta) -> Result<bool>;
+    /// Give the lease up if `holder` has it, so another instance can take
+    /// over without waiting for it to expire
+    async fn release(&self, name: &str, holder: &str) -> Result<()>;
+    async fn find_lease(&self, name: &str) -> Result<Option<SchedulerLease>>;
+}
+
+/// API keys, kept after revocation so their history stays visible
+#[async_trait::async_trait]
+pub trait ApiKeyRepository: Send + Sync {
+    async fn create_key(&self, name: &str, prefix: &str, key_hash: &str) -> Result<ApiKey>;
+    /// Oldest first, revoked ones included
+    async fn list_keys(&self) -> Result<Vec<ApiKey>>;
+    /// Returns the key, revoked now unless it already was. Fails with
+    /// `DomainError::NotFound` when no key has this id
+    async fn revoke_key(&self, id: Uuid) -> Result<ApiKey>;
+    /// The unrevoked key with this hash, if any
+    async fn find_active_key(&self, key_hash: &str) -> Result<Option<ApiKey>>;
 }
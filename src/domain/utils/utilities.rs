@@ -1,16 +1,153 @@
-use chrono::{DateTime, Local, NaiveDateTime, TimeZone, Utc};
-use rand::{Rng, distributions::Alphanumeric};
+use chrono::{
+    DateTime, FixedOffset, Local, LocalResult, NaiveDateTime, SecondsFormat, TimeZone, Utc,
+};
+use chrono_tz::Tz;
 use serde::{self, Deserialize, Deserializer, Serializer};
+use std::fmt;
+use std::future::Future;
+use std::str::FromStr;
 
-const FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+/// Format of the v1 API, no offset, read in the server's timezone
+const LEGACY_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+/// Naive inputs also accepted, e.g. `2025-11-05T14:20:30`
+const NAIVE_FORMATS: [&str; 2] = [LEGACY_FORMAT, "%Y-%m-%dT%H:%M:%S%.f"];
+
+/// How datetimes are written in responses
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DateTimeFormat {
+    /// RFC 3339 in UTC, e.g. `2025-11-05T14:20:30Z`
+    #[default]
+    Rfc3339,
+    /// `%Y-%m-%d %H:%M:%S` in the request's timezone, else the server's, as v1
+    /// clients expect
+    Local,
+}
+
+impl FromStr for DateTimeFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "rfc3339" => Ok(Self::Rfc3339),
+            "local" => Ok(Self::Local),
+            other => Err(format!(
+                "unknown datetime format {other:?}, expected rfc3339 or local"
+            )),
+        }
+    }
+}
+
+/// Timezone for naive inputs and local output: an IANA zone, or the server's
+/// own when the request doesn't name one
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RequestTimezone {
+    #[default]
+    Server,
+    Named(Tz),
+}
+
+impl FromStr for RequestTimezone {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.trim().parse::<Tz>().map(Self::Named).map_err(|_| {
+            format!("unknown timezone {s:?}, expected an IANA name such as Europe/Paris")
+        })
+    }
+}
+
+impl fmt::Display for RequestTimezone {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Server => f.write_str("the server timezone"),
+            Self::Named(tz) => write!(f, "{tz}"),
+        }
+    }
+}
+
+impl RequestTimezone {
+    /// Instant of a wall clock time, refusing times skipped or repeated by a
+    /// DST change rather than guessing
+    fn resolve(&self, naive: &NaiveDateTime) -> Result<DateTime<Utc>, String> {
+        let result: LocalResult<DateTime<FixedOffset>> = match self {
+            Self::Server => Local.from_local_datetime(naive).map(|dt| dt.fixed_offset()),
+            Self::Named(tz) => tz.from_local_datetime(naive).map(|dt| dt.fixed_offset()),
+        };
+        match result {
+            LocalResult::Single(dt) => Ok(dt.with_timezone(&Utc)),
+            LocalResult::Ambiguous(..) => Err(format!(
+                "{naive} happens twice in {self} (DST change), add a UTC offset"
+            )),
+            LocalResult::None => Err(format!(
+                "{naive} does not exist in {self} (DST change), add a UTC offset"
+            )),
+        }
+    }
+
+    fn format(&self, dt: &DateTime<Utc>) -> String {
+        match self {
+            Self::Server => dt.with_timezone(&Local).format(LEGACY_FORMAT).to_string(),
+            Self::Named(tz) => dt.with_timezone(tz).format(LEGACY_FORMAT).to_string(),
+        }
+    }
+}
+
+/// How the current request reads and writes datetimes
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DateTimeContext {
+    pub timezone: RequestTimezone,
+    pub format: DateTimeFormat,
+}
+
+tokio::task_local! {
+    static DATETIME_CONTEXT: DateTimeContext;
+}
+
+impl DateTimeContext {
+    /// Context of the running request, the default outside of one
+    pub fn current() -> Self {
+        DATETIME_CONTEXT.try_with(|ctx| *ctx).unwrap_or_default()
+    }
+
+    /// Run `fut` with this context, so the serde helpers below see it
+    pub async fn scope<F: Future>(self, fut: F) -> F::Output {
+        DATETIME_CONTEXT.scope(self, fut).await
+    }
+
+    pub fn format(&self, dt: &DateTime<Utc>) -> String {
+        match self.format {
+            DateTimeFormat::Rfc3339 => dt.to_rfc3339_opts(SecondsFormat::AutoSi, true),
+            DateTimeFormat::Local => self.timezone.format(dt),
+        }
+    }
+
+    /// RFC 3339 with an offset, or a naive time in the context's timezone
+    pub fn parse(&self, s: &str) -> Result<DateTime<Utc>, String> {
+        let s = s.trim();
+        if let Ok(dt) = DateTime::parse_from_rfc3339(s) {
+            return Ok(dt.with_timezone(&Utc));
+        }
+        let naive = NAIVE_FORMATS
+            .iter()
+            .find_map(|format| NaiveDateTime::parse_from_str(s, format).ok())
+            .ok_or_else(|| {
+                format!("invalid datetime {s:?}, expected RFC 3339 such as 2025-11-05T14:20:30Z")
+            })?;
+        self.timezone.resolve(&naive)
+    }
+}
+
+/// Format a datetime the way the current request asked for
+pub fn format_datetime(dt: &DateTime<Utc>) -> String {
+    DateTimeContext::current().format(dt)
+}
 
 // Serialize required datetime
 pub fn serialize_datetime<S>(dt: &DateTime<Utc>, serializer: S) -> Result<S::Ok, S::Error>
 where
     S: Serializer,
 {
-    let s = dt.with_timezone(&Local).format(FORMAT).to_string();
-    serializer.serialize_str(&s)
+    serializer.serialize_str(&format_datetime(dt))
 }
 
 // Serialize optional datetime
@@ -22,7 +159,7 @@ where
     S: Serializer,
 {
     match dt {
-        Some(v) => serializer.serialize_str(&v.with_timezone(&Local).format(FORMAT).to_string()),
+        Some(v) => serializer.serialize_str(&format_datetime(v)),
         None => serializer.serialize_none(),
     }
 }
@@ -36,24 +173,132 @@ where
 {
     let opt = Option::<String>::deserialize(deserializer)?;
     match opt {
-        Some(s) => {
-            let naive =
-                NaiveDateTime::parse_from_str(&s, FORMAT).map_err(serde::de::Error::custom)?;
-            let local_dt = Local
-                .from_local_datetime(&naive)
-                .earliest()
-                .ok_or_else(|| serde::de::Error::custom("invalid datetime"))?;
-            Ok(Some(local_dt.with_timezone(&Utc)))
-        }
+        Some(s) => DateTimeContext::current()
+            .parse(&s)
+            .map(Some)
+            .map_err(serde::de::Error::custom),
         None => Ok(None),
     }
 }
 
-// generate short code for url
-pub fn generate_short_code(len: usize) -> String {
-    rand::thread_rng()
-        .sample_iter(&Alphanumeric)
-        .take(len)
-        .map(char::from)
-        .collect()
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn paris() -> DateTimeContext {
+        DateTimeContext {
+            timezone: "Europe/Paris".parse().unwrap(),
+            format: DateTimeFormat::Rfc3339,
+        }
+    }
+
+    fn utc(s: &str) -> DateTime<Utc> {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn offsets_win_over_the_timezone() {
+        for (raw, expected) in [
+            ("2025-11-05T14:20:30Z", "2025-11-05T14:20:30Z"),
+            ("2025-11-05T14:20:30+05:30", "2025-11-05T08:50:30Z"),
+            ("2025-11-05T14:20:30.250-02:00", "2025-11-05T16:20:30.250Z"),
+            ("  2025-03-30T02:30:00+01:00 ", "2025-03-30T01:30:00Z"),
+        ] {
+            assert_eq!(paris().parse(raw), Ok(utc(expected)), "{raw}");
+        }
+    }
+
+    #[test]
+    fn naive_times_are_read_in_the_timezone() {
+        for (raw, expected) in [
+            // Legacy v1 format, CET in winter
+            ("2025-11-05 14:20:30", "2025-11-05T13:20:30Z"),
+            // CEST in summer
+            ("2025-07-01T09:00:00", "2025-07-01T07:00:00Z"),
+            ("2025-07-01T09:00:00.5", "2025-07-01T07:00:00.5Z"),
+            // Either side of the gap
+            ("2025-03-30 01:59:59", "2025-03-30T00:59:59Z"),
+            ("2025-03-30 03:00:00", "2025-03-30T01:00:00Z"),
+        ] {
+            assert_eq!(paris().parse(raw), Ok(utc(expected)), "{raw}");
+        }
+    }
+
+    #[test]
+    fn naive_times_default_to_the_server_timezone() {
+        let naive = NaiveDateTime::parse_from_str("2025-11-05 14:20:30", LEGACY_FORMAT).unwrap();
+        let expected = Local
+            .from_local_datetime(&naive)
+            .single()
+            .unwrap()
+            .with_timezone(&Utc);
+        let parsed = DateTimeContext::default().parse("2025-11-05 14:20:30");
+        assert_eq!(parsed, Ok(expected));
+    }
+
+    #[test]
+    fn skipped_wall_clock_times_are_refused() {
+        let err = paris().parse("2025-03-30 02:30:00").unwrap_err();
+        assert_eq!(
+            err,
+            "2025-03-30 02:30:00 does not exist in Europe/Paris (DST change), add a UTC offset"
+        );
+    }
+
+    #[test]
+    fn repeated_wall_clock_times_are_refused() {
+        let err = paris().parse("2025-10-26T02:30:00").unwrap_err();
+        assert_eq!(
+            err,
+            "2025-10-26 02:30:00 happens twice in Europe/Paris (DST change), add a UTC offset"
+        );
+        // An offset picks one of the two
+        assert_eq!(
+            paris().parse("2025-10-26T02:30:00+01:00"),
+            Ok(utc("2025-10-26T01:30:00Z"))
+        );
+    }
+
+    #[test]
+    fn garbage_and_unknown_timezones_are_refused() {
+        for raw in ["", "yesterday", "2025-13-01 00:00:00", "2025-11-05"] {
+            let err = paris().parse(raw).unwrap_err();
+            assert!(err.starts_with("invalid datetime"), "{raw}: {err}");
+        }
+
+        let err = "Mars/Olympus_Mons".parse::<RequestTimezone>().unwrap_err();
+        assert_eq!(
+            err,
+            "unknown timezone \"Mars/Olympus_Mons\", expected an IANA name such as Europe/Paris"
+        );
+        assert_eq!(
+            " Asia/Tokyo ".parse::<RequestTimezone>(),
+            Ok(RequestTimezone::Named(chrono_tz::Asia::Tokyo))
+        );
+    }
+
+    #[test]
+    fn formats_in_utc_or_the_local_timezone() {
+        let dt = utc("2025-07-01T07:00:00Z");
+        assert_eq!(paris().format(&dt), "2025-07-01T07:00:00Z");
+        let local = DateTimeContext {
+            format: DateTimeFormat::Local,
+            ..paris()
+        };
+        assert_eq!(local.format(&dt), "2025-07-01 09:00:00");
+        assert_eq!("LOCAL".parse(), Ok(DateTimeFormat::Local));
+        assert!("iso".parse::<DateTimeFormat>().is_err());
+    }
+
+    #[tokio::test]
+    async fn the_request_context_applies_within_its_scope() {
+        let dt = utc("2025-11-05T13:20:30Z");
+        let local = DateTimeContext {
+            format: DateTimeFormat::Local,
+            ..paris()
+        };
+        let inside = local.scope(async { format_datetime(&dt) }).await;
+        assert_eq!(inside, "2025-11-05 14:20:30");
+        assert_eq!(format_datetime(&dt), "2025-11-05T13:20:30Z");
+    }
 }
@@ -0,0 +1,435 @@
+use crate::domain::utils::utilities::format_datetime;
+use chrono::{DateTime, TimeDelta, Timelike, Utc};
+use std::fmt;
+use std::str::FromStr;
+
+const MINUTE: i64 = 60;
+const HOUR: i64 = 60 * MINUTE;
+const DAY: i64 = 24 * HOUR;
+const WEEK: i64 = 7 * DAY;
+
+/// Positive link lifetime, written either compactly (`30m`, `7d`, `1h30m`,
+/// units `s`, `m`, `h`, `d` and `w`, largest first) or as an ISO 8601
+/// duration (`P1W`, `P1DT12H`, `PT30M`). Months and years have no fixed
+/// length and are refused.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct LinkLifetime(TimeDelta);
+
+impl LinkLifetime {
+    /// Longest lifetime accepted in the configuration, 100 years
+    pub const CONFIG_LIMIT: Self = Self(TimeDelta::days(36_500));
+
+    pub fn as_delta(&self) -> TimeDelta {
+        self.0
+    }
+}
+
+impl FromStr for LinkLifetime {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let seconds = match s.strip_prefix(['P', 'p']) {
+            Some(iso) => parse_iso(&iso.to_ascii_uppercase())?,
+            None => sum_units(&s.to_ascii_lowercase(), |unit| match unit {
+                's' => Some(1),
+                'm' => Some(MINUTE),
+                'h' => Some(HOUR),
+                'd' => Some(DAY),
+                'w' => Some(WEEK),
+                _ => None,
+            }),
+        }
+        .ok_or_else(|| {
+            format!("invalid duration {s:?}, expected e.g. 30m, 12h, 7d or ISO 8601 such as P1W")
+        })?;
+
+        if seconds <= 0 {
+            return Err(format!("duration {s:?} must be longer than zero"));
+        }
+        TimeDelta::try_seconds(seconds)
+            .map(Self)
+            .ok_or_else(|| format!("duration {s:?} is too long"))
+    }
+}
+
+/// Seconds of `P[nW][nD][T[nH][nM][nS]]`, the part after `P`
+fn parse_iso(s: &str) -> Result<Option<i64>, String> {
+    let (date, time) = match s.split_once('T') {
+        // A `T` must be followed by a time component
+        Some((_, "")) => return Ok(None),
+        Some(parts) => parts,
+        None => (s, ""),
+    };
+    if date.contains(['Y', 'M']) {
+        return Err(format!(
+            "duration \"P{s}\" uses months or years, which have no fixed length; use weeks or days"
+        ));
+    }
+    if date.is_empty() && time.is_empty() {
+        return Ok(None);
+    }
+
+    let date_seconds = if date.is_empty() {
+        Some(0)
+    } else {
+        sum_units(date, |unit| match unit {
+            'W' => Some(WEEK),
+            'D' => Some(DAY),
+            _ => None,
+        })
+    };
+    let time_seconds = if time.is_empty() {
+        Some(0)
+    } else {
+        sum_units(time, |unit| match unit {
+            'H' => Some(HOUR),
+            'M' => Some(MINUTE),
+            'S' => Some(1),
+            _ => None,
+        })
+    };
+    Ok(date_seconds.zip(time_seconds).map(|(d, t)| d + t))
+}
+
+/// Total of `<number><unit>` pairs, `None` unless the whole string is made of
+/// them, largest unit first and each unit at most once
+fn sum_units(s: &str, unit_seconds: impl Fn(char) -> Option<i64>) -> Option<i64> {
+    let mut total: i64 = 0;
+    let mut digits = String::new();
+    let mut previous_unit = i64::MAX;
+    for c in s.chars() {
+        if c.is_ascii_digit() {
+            digits.push(c);
+            continue;
+        }
+        let n: i64 = digits.parse().ok()?;
+        digits.clear();
+        let unit = unit_seconds(c)?;
+        if unit >= previous_unit {
+            return None;
+        }
+        previous_unit = unit;
+        total = total.checked_add(n.checked_mul(unit)?)?;
+    }
+    (digits.is_empty() && !s.is_empty()).then_some(total)
+}
+
+impl fmt::Display for LinkLifetime {
+    /// Compact form in days at most, e.g. `30d` or `1h30m`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut rest = self.0.num_seconds();
+        for (unit, seconds) in [("d", DAY), ("h", HOUR), ("m", MINUTE), ("s", 1)] {
+            if rest >= seconds {
+                write!(f, "{}{unit}", rest / seconds)?;
+                rest %= seconds;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Server-wide limits on link expiry. Links created without `expires_at` or
+/// `expires_in` get `default_ttl`, or the maximum lifetime when only that is
+/// set, since a permanent link would exceed it.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ExpiryPolicy {
+    max_lifetime: Option<LinkLifetime>,
+    default_ttl: Option<LinkLifetime>,
+}
+
+impl ExpiryPolicy {
+    pub fn new(max_lifetime: Option<LinkLifetime>, default_ttl: Option<LinkLifetime>) -> Self {
+        Self {
+            max_lifetime,
+            default_ttl,
+        }
+    }
+
+    /// Expiry of a new link from the absolute `expires_at` or the relative
+    /// `expires_in` of the request, at most one of them
+    pub fn resolve(
+        &self,
+        expires_at: Option<DateTime<Utc>>,
+        expires_in: Option<&str>,
+    ) -> Result<Option<DateTime<Utc>>, ExpiryError> {
+        // Whole seconds, like `created_at`
+        let now = Utc::now().with_nanosecond(0).unwrap();
+
+        let (field, expiry) = match (expires_at, expires_in) {
+            (Some(_), Some(_)) => return Err(ExpiryError::Conflicting),
+            (Some(at), None) => {
+                if at <= now {
+                    return Err(ExpiryError::InPast(at));
+                }
+                ("expires_at", Some(at))
+            }
+            (None, Some(raw)) => {
+                let lifetime = raw
+                    .parse::<LinkLifetime>()
+                    .map_err(ExpiryError::InvalidDuration)?;
+                let at = now
+                    .checked_add_signed(lifetime.as_delta())
+                    .ok_or_else(|| ExpiryError::InvalidDuration(format!("{raw:?} is too long")))?;
+                ("expires_in", Some(at))
+            }
+            (None, None) => {
+                let lifetime = self.default_ttl.or(self.max_lifetime);
+                ("expires_at", lifetime.map(|l| after(now, l)).transpose()?)
+            }
+        };
+
+        if let (Some(at), Some(max)) = (expiry, self.max_lifetime)
+            && at > after(now, max)?
+        {
+            return Err(ExpiryError::ExceedsMaxLifetime { field, max });
+        }
+        Ok(expiry)
+    }
+
+    /// Whether a link may be made permanent, not when a maximum lifetime is
+    /// set
+    pub fn check_permanent(&self) -> Result<(), ExpiryError> {
+        match self.max_lifetime {
+            Some(max) => Err(ExpiryError::ExceedsMaxLifetime {
+                field: "expires_at",
+                max,
+            }),
+            None => Ok(()),
+        }
+    }
+}
+
+/// `now` plus a configured lifetime
+fn after(now: DateTime<Utc>, lifetime: LinkLifetime) -> Result<DateTime<Utc>, ExpiryError> {
+    now.checked_add_signed(lifetime.as_delta())
+        .ok_or(ExpiryError::OutOfRange(lifetime))
+}
+
+/// Reason the requested expiry was rejected
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExpiryError {
+    Conflicting,
+    InvalidDuration(String),
+    InPast(DateTime<Utc>),
+    ExceedsMaxLifetime {
+        field: &'static str,
+        max: LinkLifetime,
+    },
+    /// A configured lifetime reaches past the latest representable date
+    OutOfRange(LinkLifetime),
+}
+
+impl ExpiryError {
+    /// Machine-readable name of the rule that rejected the expiry
+    pub fn rule(&self) -> &'static str {
+        match self {
+            Self::Conflicting => "conflicting_fields",
+            Self::InvalidDuration(_) => "invalid_duration",
+            Self::InPast(_) => "in_past",
+            Self::ExceedsMaxLifetime { .. } => "exceeds_max_lifetime",
+            Self::OutOfRange(_) => "out_of_range",
+        }
+    }
+
+    /// Request field the error is about
+    pub fn field(&self) -> &'static str {
+        match self {
+            Self::Conflicting | Self::InvalidDuration(_) => "expires_in",
+            Self::InPast(_) | Self::OutOfRange(_) => "expires_at",
+            Self::ExceedsMaxLifetime { field, .. } => field,
+        }
+    }
+}
+
+impl fmt::Display for ExpiryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Conflicting => write!(f, "give either expires_at or expires_in, not both"),
+            Self::InvalidDuration(reason) => f.write_str(reason),
+            Self::InPast(at) => write!(f, "expiry {} is in the past", format_datetime(at)),
+            Self::ExceedsMaxLifetime { max, .. } => {
+                write!(f, "links may live at most {max}")
+            }
+            Self::OutOfRange(lifetime) => {
+                write!(f, "configured link lifetime {lifetime} is out of range")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ExpiryError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn seconds(raw: &str) -> Result<i64, String> {
+        raw.parse::<LinkLifetime>()
+            .map(|l| l.as_delta().num_seconds())
+    }
+
+    #[test]
+    fn parses_compact_durations() {
+        for (raw, expected) in [
+            ("45s", 45),
+            ("30m", 30 * MINUTE),
+            ("12h", 12 * HOUR),
+            ("7d", 7 * DAY),
+            ("2w", 2 * WEEK),
+            ("1h30m", HOUR + 30 * MINUTE),
+            ("1w2d3h4m5s", WEEK + 2 * DAY + 3 * HOUR + 4 * MINUTE + 5),
+            (" 7D ", 7 * DAY),
+        ] {
+            assert_eq!(seconds(raw), Ok(expected), "{raw}");
+        }
+    }
+
+    #[test]
+    fn parses_iso_durations() {
+        for (raw, expected) in [
+            ("P1W", WEEK),
+            ("P1D", DAY),
+            ("P1DT12H", DAY + 12 * HOUR),
+            ("PT30M", 30 * MINUTE),
+            ("PT1H30M15S", HOUR + 30 * MINUTE + 15),
+            ("p2wt1s", 2 * WEEK + 1),
+        ] {
+            assert_eq!(seconds(raw), Ok(expected), "{raw}");
+        }
+    }
+
+    #[test]
+    fn refuses_malformed_durations() {
+        for raw in [
+            "", "7", "d", "7x", "1.5h", "-1d", "1d1d", "30m1h", "1h 30m", "P", "PT", "P1DT", "P1H",
+            "PT1D", "P1D1W", "PT1M1M",
+        ] {
+            let err = seconds(raw).unwrap_err();
+            assert!(err.starts_with("invalid duration"), "{raw}: {err}");
+        }
+    }
+
+    #[test]
+    fn refuses_months_and_years() {
+        for raw in ["P1M", "P1Y", "P1Y2M", "p3m"] {
+            let err = seconds(raw).unwrap_err();
+            assert!(err.contains("months or years"), "{raw}: {err}");
+        }
+        // `M` after `T` is minutes
+        assert_eq!(seconds("PT1M"), Ok(MINUTE));
+    }
+
+    #[test]
+    fn refuses_zero_and_overflowing_durations() {
+        for raw in ["0s", "0d0h", "PT0S"] {
+            let err = seconds(raw).unwrap_err();
+            assert!(err.contains("longer than zero"), "{raw}: {err}");
+        }
+        for raw in ["9223372036854775807w", "99999999999999999999s"] {
+            assert!(seconds(raw).is_err(), "{raw}");
+        }
+        // Fits in seconds but not in a `TimeDelta`
+        let err = seconds("9223372036854776s").unwrap_err();
+        assert!(err.contains("too long"), "{err}");
+    }
+
+    #[test]
+    fn displays_in_compact_form() {
+        for (raw, shown) in [("P1W", "7d"), ("90m", "1h30m"), ("PT1H0M5S", "1h5s")] {
+            assert_eq!(raw.parse::<LinkLifetime>().unwrap().to_string(), shown);
+        }
+    }
+
+    fn lifetime(raw: &str) -> Option<LinkLifetime> {
+        Some(raw.parse().unwrap())
+    }
+
+    #[test]
+    fn resolves_absolute_and_relative_expiry() {
+        let policy = ExpiryPolicy::default();
+        let at = Utc::now() + TimeDelta::hours(1);
+        assert_eq!(policy.resolve(Some(at), None), Ok(Some(at)));
+        assert_eq!(policy.resolve(None, None), Ok(None));
+
+        let before = Utc::now().with_nanosecond(0).unwrap();
+        let resolved = policy.resolve(None, Some("1h")).unwrap().unwrap();
+        assert!(resolved >= before + TimeDelta::hours(1));
+        assert!(resolved <= Utc::now() + TimeDelta::hours(1));
+        assert_eq!(resolved.nanosecond(), 0);
+    }
+
+    #[test]
+    fn rejects_conflicting_past_and_invalid_expiry() {
+        let policy = ExpiryPolicy::default();
+        let future = Utc::now() + TimeDelta::hours(1);
+        let err = policy.resolve(Some(future), Some("1h")).unwrap_err();
+        assert_eq!(err, ExpiryError::Conflicting);
+        assert_eq!(
+            (err.field(), err.rule()),
+            ("expires_in", "conflicting_fields")
+        );
+
+        let past = Utc::now() - TimeDelta::seconds(1);
+        let err = policy.resolve(Some(past), None).unwrap_err();
+        assert_eq!(err, ExpiryError::InPast(past));
+        assert_eq!((err.field(), err.rule()), ("expires_at", "in_past"));
+
+        let err = policy.resolve(None, Some("soon")).unwrap_err();
+        assert_eq!(err.rule(), "invalid_duration");
+    }
+
+    #[test]
+    fn applies_the_default_ttl_then_the_max_lifetime() {
+        let now = Utc::now();
+        let within = |at: Option<DateTime<Utc>>, hours: i64| {
+            let at = at.unwrap();
+            at > now + TimeDelta::hours(hours) - TimeDelta::seconds(2)
+                && at <= Utc::now() + TimeDelta::hours(hours)
+        };
+
+        let both = ExpiryPolicy::new(lifetime("30d"), lifetime("1d"));
+        assert!(within(both.resolve(None, None).unwrap(), 24));
+        // Without a default, links live as long as allowed
+        let max_only = ExpiryPolicy::new(lifetime("2d"), None);
+        assert!(within(max_only.resolve(None, None).unwrap(), 48));
+        let ttl_only = ExpiryPolicy::new(None, lifetime("3h"));
+        assert!(within(ttl_only.resolve(None, None).unwrap(), 3));
+    }
+
+    #[test]
+    fn enforces_the_max_lifetime() {
+        let policy = ExpiryPolicy::new(lifetime("7d"), None);
+        assert!(policy.resolve(None, Some("7d")).is_ok());
+
+        let err = policy.resolve(None, Some("8d")).unwrap_err();
+        assert_eq!(
+            (err.field(), err.rule()),
+            ("expires_in", "exceeds_max_lifetime")
+        );
+        assert_eq!(err.to_string(), "links may live at most 7d");
+
+        let late = Utc::now() + TimeDelta::days(8);
+        let err = policy.resolve(Some(late), None).unwrap_err();
+        assert_eq!(
+            (err.field(), err.rule()),
+            ("expires_at", "exceeds_max_lifetime")
+        );
+    }
+
+    #[test]
+    fn permanent_links_need_no_max_lifetime() {
+        assert_eq!(ExpiryPolicy::default().check_permanent(), Ok(()));
+        assert_eq!(
+            ExpiryPolicy::new(None, lifetime("1d")).check_permanent(),
+            Ok(())
+        );
+        let err = ExpiryPolicy::new(lifetime("30d"), None)
+            .check_permanent()
+            .unwrap_err();
+        assert_eq!(
+            (err.field(), err.rule()),
+            ("expires_at", "exceeds_max_lifetime")
+        );
+    }
+}
@@ -1 +1,3 @@
+pub mod expiry;
+pub mod self_reference;
 pub mod url_validator;
@@ -0,0 +1,82 @@
+use url::Url;
+
+/// Path under a public base URL where short codes are served
+const REDIRECT_PATH_PREFIX: &str = "api/v1/";
+
+/// Segments right under `/api/v1/` taken by API routes, which shadow short
+/// links with the same code. Keep in step with the router.
+pub const RESERVED_CODES: &[&str] = &["shorten", "webhooks", "scheduler"];
+
+/// Whether a short link with `code` could never be reached, in either case
+/// mode
+pub fn is_reserved_code(code: &str) -> bool {
+    RESERVED_CODES
+        .iter()
+        .any(|reserved| reserved.eq_ignore_ascii_case(code))
+}
+
+/// Knows the shortener's own public base URL(s) so targets pointing back at
+/// the service can be rejected, or followed to detect redirect loops.
+#[derive(Debug, Clone, Default)]
+pub struct SelfReferencePolicy {
+    base_urls: Vec<Url>,
+    follow_chain: bool,
+    max_hops: usize,
+}
+
+impl SelfReferencePolicy {
+    /// By default targets on any of `base_urls` hosts are rejected outright.
+    pub fn new(base_urls: Vec<Url>) -> Self {
+        Self {
+            base_urls,
+            follow_chain: false,
+            max_hops: 5,
+        }
+    }
+
+    /// Allow targets on our own hosts, but follow the chain of short codes
+    /// (up to `max_hops`) at creation time and reject cycles.
+    pub fn with_chain_following(mut self, max_hops: usize) -> Self {
+        self.follow_chain = true;
+        self.max_hops = max_hops;
+        self
+    }
+
+    pub fn follows_chain(&self) -> bool {
+        self.follow_chain
+    }
+
+    pub fn max_hops(&self) -> usize {
+        self.max_hops
+    }
+
+    /// Whether `url` is served by one of our own hosts
+    pub fn is_own_host(&self, url: &Url) -> bool {
+        let Some(host) = url.host_str() else {
+            return false;
+        };
+
+        self.base_urls
+            .iter()
+            .filter_map(|base| base.host_str())
+            .any(|own| own.eq_ignore_ascii_case(host))
+    }
+
+    /// Short code a URL on our own host redirects through, if it is a short link
+    pub fn extract_code(&self, url: &Url) -> Option<String> {
+        self.base_urls
+            .iter()
+            .filter(|base| base.host_str() == url.host_str())
+            .find_map(|base| {
+                let base_path = base.path().trim_end_matches('/');
+                let rest = url.path().strip_prefix(base_path)?.strip_prefix('/')?;
+                let code = rest.strip_prefix(REDIRECT_PATH_PREFIX)?;
+
+                // `/api/v1/shorten` and the like are the API itself
+                if code.is_empty() || code.contains('/') || is_reserved_code(code) {
+                    return None;
+                }
+                Some(code.to_string())
+            })
+    }
+}
@@ -1,18 +1,200 @@
-use anyhow::Result;
-use once_cell::sync::OnceCell;
+use crate::config::DatabaseConfig;
+use crate::domain::repositories::{
+    ApiKeyRepository, LeaseRepository, UrlRepository, WebhookRepository,
+};
+use crate::infrastructure::repositories::{
+    PostgresApiKeyRepository, PostgresLeaseRepository, PostgresUrlRepository,
+    PostgresWebhookRepository,
+};
+use anyhow::{Result, bail};
 use sqlx::postgres::{PgPool, PgPoolOptions};
+use std::sync::Arc;
 
-pub static DB_POOL: OnceCell<PgPool> = OnceCell::new();
+#[cfg(feature = "sqlite")]
+use crate::infrastructure::sqlite_repository::{
+    SqliteApiKeyRepository, SqliteLeaseRepository, SqliteUrlRepository, SqliteWebhookRepository,
+};
+#[cfg(feature = "sqlite")]
+use sqlx::sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePool, SqlitePoolOptions};
 
-pub async fn init_db_pool(database_url: &str) -> Result<()> {
-    let pool = PgPoolOptions::new()
-        .max_connections(5)
-        .connect(database_url)
-        .await?;
-    DB_POOL.set(pool).expect("DB_POOL is already initialized");
-    Ok(())
+#[cfg(feature = "redb")]
+use crate::infrastructure::redb_repository::{
+    self, RedbApiKeyRepository, RedbLeaseRepository, RedbUrlRepository, RedbWebhookRepository,
+};
+
+/// Connection pool of the storage backend picked by the `DATABASE_URL` scheme
+#[derive(Clone)]
+pub enum Database {
+    Postgres(PgPool),
+    #[cfg(feature = "sqlite")]
+    Sqlite(SqlitePool),
+    /// Embedded store file, no pool
+    #[cfg(feature = "redb")]
+    Redb(Arc<redb::Database>),
+}
+
+/// Connections of the pool, for metrics
+pub struct PoolStats {
+    pub size: u32,
+    pub idle: usize,
+    pub max: u32,
 }
 
-pub fn db_pool() -> &'static PgPool {
-    DB_POOL.get().expect("DB_POOL is not initialized")
+impl Database {
+    pub async fn connect(config: &DatabaseConfig) -> Result<Self> {
+        let url = config.url.as_str();
+
+        if url.starts_with("postgres://") || url.starts_with("postgresql://") {
+            let pool = PgPoolOptions::new()
+                .max_connections(config.max_connections)
+                .connect(url)
+                .await?;
+            return Ok(Self::Postgres(pool));
+        }
+
+        #[cfg(feature = "sqlite")]
+        if url.starts_with("sqlite:") {
+            use std::str::FromStr;
+
+            let options = SqliteConnectOptions::from_str(url)?
+                .create_if_missing(true)
+                .journal_mode(SqliteJournalMode::Wal)
+                .busy_timeout(std::time::Duration::from_secs(5));
+            let pool = SqlitePoolOptions::new()
+                .max_connections(config.max_connections)
+                .connect_with(options)
+                .await?;
+            return Ok(Self::Sqlite(pool));
+        }
+
+        #[cfg(feature = "redb")]
+        if let Some(path) = url.strip_prefix("redb:") {
+            let path = path.strip_prefix("//").unwrap_or(path).to_string();
+            let db =
+                tokio::task::spawn_blocking(move || redb_repository::open_store(path)).await??;
+            return Ok(Self::Redb(Arc::new(db)));
+        }
+
+        bail!("unsupported DATABASE_URL scheme in {url:?}")
+    }
+
+    pub fn backend(&self) -> &'static str {
+        match self {
+            Self::Postgres(_) => "postgres",
+            #[cfg(feature = "sqlite")]
+            Self::Sqlite(_) => "sqlite",
+            #[cfg(feature = "redb")]
+            Self::Redb(_) => "redb",
+        }
+    }
+
+    pub fn url_repository(&self, case_insensitive_codes: bool) -> Arc<dyn UrlRepository> {
+        match self {
+            Self::Postgres(pool) => Arc::new(
+                PostgresUrlRepository::new(pool.clone())
+                    .with_case_insensitive_codes(case_insensitive_codes),
+            ),
+            #[cfg(feature = "sqlite")]
+            Self::Sqlite(pool) => Arc::new(
+                SqliteUrlRepository::new(pool.clone())
+                    .with_case_insensitive_codes(case_insensitive_codes),
+            ),
+            #[cfg(feature = "redb")]
+            Self::Redb(db) => Arc::new(
+                RedbUrlRepository::new(db.clone())
+                    .with_case_insensitive_codes(case_insensitive_codes),
+            ),
+        }
+    }
+
+    pub fn webhook_repository(&self) -> Arc<dyn WebhookRepository> {
+        match self {
+            Self::Postgres(pool) => Arc::new(PostgresWebhookRepository::new(pool.clone())),
+            #[cfg(feature = "sqlite")]
+            Self::Sqlite(pool) => Arc::new(SqliteWebhookRepository::new(pool.clone())),
+            #[cfg(feature = "redb")]
+            Self::Redb(db) => Arc::new(RedbWebhookRepository::new(db.clone())),
+        }
+    }
+
+    pub fn lease_repository(&self) -> Arc<dyn LeaseRepository> {
+        match self {
+            Self::Postgres(pool) => Arc::new(PostgresLeaseRepository::new(pool.clone())),
+            #[cfg(feature = "sqlite")]
+            Self::Sqlite(pool) => Arc::new(SqliteLeaseRepository::new(pool.clone())),
+            #[cfg(feature = "redb")]
+            Self::Redb(db) => Arc::new(RedbLeaseRepository::new(db.clone())),
+        }
+    }
+
+    pub fn api_key_repository(&self) -> Arc<dyn ApiKeyRepository> {
+        match self {
+            Self::Postgres(pool) => Arc::new(PostgresApiKeyRepository::new(pool.clone())),
+            #[cfg(feature = "sqlite")]
+            Self::Sqlite(pool) => Arc::new(SqliteApiKeyRepository::new(pool.clone())),
+            #[cfg(feature = "redb")]
+            Self::Redb(db) => Arc::new(RedbApiKeyRepository::new(db.clone())),
+        }
+    }
+
+    /// Cheapest possible round trip
+    pub async fn ping(&self) -> Result<()> {
+        match self {
+            Self::Postgres(pool) => {
+                sqlx::query("SELECT 1").execute(pool).await?;
+            }
+            #[cfg(feature = "sqlite")]
+            Self::Sqlite(pool) => {
+                sqlx::query("SELECT 1").execute(pool).await?;
+            }
+            #[cfg(feature = "redb")]
+            Self::Redb(db) => {
+                db.begin_read()?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn pool_stats(&self) -> PoolStats {
+        match self {
+            Self::Postgres(pool) => PoolStats {
+                size: pool.size(),
+                idle: pool.num_idle(),
+                max: pool.options().get_max_connections(),
+            },
+            #[cfg(feature = "sqlite")]
+            Self::Sqlite(pool) => PoolStats {
+                size: pool.size(),
+                idle: pool.num_idle(),
+                max: pool.options().get_max_connections(),
+            },
+            #[cfg(feature = "redb")]
+            Self::Redb(_) => PoolStats {
+                size: 0,
+                idle: 0,
+                max: 0,
+            },
+        }
+    }
+
+    pub async fn close(&self) {
+        match self {
+            Self::Postgres(pool) => pool.close().await,
+            #[cfg(feature = "sqlite")]
+            Self::Sqlite(pool) => pool.close().await,
+            // Clicks are committed without fsync, persist them before the
+            // file is closed on drop
+            #[cfg(feature = "redb")]
+            Self::Redb(db) => {
+                let db = db.clone();
+                let flushed = tokio::task::spawn_blocking(move || redb_repository::flush(&db))
+                    .await
+                    .map_err(anyhow::Error::from)
+                    .and_then(|result| result);
+                if let Err(e) = flushed {
+                    tracing::warn!("failed to flush the redb store: {e}");
+                }
+            }
+        }
+    }
 }
@@ -0,0 +1,281 @@
+use crate::domain::entities::{LinkHealth, ShortUrl};
+use crate::domain::repositories::UrlRepository;
+use crate::domain::validators::url_validator::{UrlPolicy, normalize_url};
+use crate::infrastructure::outbound;
+use anyhow::Result;
+use chrono::Utc;
+use reqwest::{Client, ClientBuilder, Method, redirect};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+use tokio::time::{Duration, sleep};
+use url::Url;
+
+/// Probes link targets with HEAD, falling back to GET, and records the outcome
+/// on each link.
+///
+/// A target the URL policy has come to refuse is recorded as not probed.
+#[derive(Clone)]
+pub struct HealthChecker {
+    client: Client,
+    concurrency: usize,
+    host_delay: Duration,
+    url_policy: UrlPolicy,
+}
+
+impl HealthChecker {
+    pub fn new(timeout: Duration, concurrency: usize, host_delay: Duration) -> Result<Self> {
+        Ok(Self {
+            client: client_builder(timeout).build()?,
+            concurrency: concurrency.max(1),
+            host_delay,
+            url_policy: UrlPolicy::default(),
+        })
+    }
+
+    /// Domain rules targets must still pass to be probed
+    pub fn with_url_policy(mut self, policy: UrlPolicy) -> Self {
+        self.url_policy = policy;
+        self
+    }
+
+    /// Check every active http(s) link once and return how many were recorded.
+    ///
+    /// At most `concurrency` probes are in flight. Links sharing a host are
+    /// probed one after another with `host_delay` in between, so a single
+    /// popular domain is never hammered.
+    pub async fn run_sweep<R: UrlRepository + 'static>(&self, repo: Arc<R>) -> Result<usize> {
+        let mut by_host: HashMap<String, Vec<ShortUrl>> = HashMap::new();
+        for link in repo.find_active_urls().await? {
+            let Ok(url) = Url::parse(&link.target_url) else {
+                continue;
+            };
+            if !matches!(url.scheme(), "http" | "https") {
+                continue;
+            }
+            if let Some(host) = url.host_str() {
+                by_host.entry(host.to_string()).or_default().push(link);
+            }
+        }
+
+        let permits = Arc::new(Semaphore::new(self.concurrency));
+        let mut tasks = JoinSet::new();
+
+        for links in by_host.into_values() {
+            let checker = self.clone();
+            let repo = repo.clone();
+            let permits = permits.clone();
+
+            tasks.spawn(async move {
+                let mut recorded = 0;
+                for (i, link) in links.iter().enumerate() {
+                    if i > 0 {
+                        sleep(checker.host_delay).await;
+                    }
+
+                    let health = {
+                        let _permit = permits.acquire().await.expect("semaphore closed");
+                        checker.probe(&link.target_url).await
+                    };
+
+                    match repo.update_health(link.id, &health).await {
+                        Ok(()) => recorded += 1,
+                        Err(e) => tracing::error!(
+                            "Health check update error for {}: {:?}",
+                            link.short_code,
+                            e
+                        ),
+                    }
+                }
+                recorded
+            });
+        }
+
+        let mut recorded = 0;
+        while let Some(res) = tasks.join_next().await {
+            match res {
+                Ok(count) => recorded += count,
+                Err(e) => tracing::error!("Health check task failed: {:?}", e),
+            }
+        }
+
+        Ok(recorded)
+    }
+
+    /// Probe a single target URL
+    pub async fn probe(&self, target: &str) -> LinkHealth {
+        if let Err(e) = normalize_url(target, &self.url_policy) {
+            return LinkHealth {
+                status_code: None,
+                latency_ms: 0,
+                error: Some(format!("not probed, {e}")),
+                checked_at: Utc::now(),
+            };
+        }
+
+        let (mut result, mut elapsed) = self.request(Method::HEAD, target).await;
+
+        // Plenty of servers reject or mishandle HEAD, retry those with GET
+        if !matches!(result, Ok(status) if status < 400) {
+            (result, elapsed) = self.request(Method::GET, target).await;
+        }
+
+        let latency_ms = i32::try_from(elapsed.as_millis()).unwrap_or(i32::MAX);
+        match result {
+            Ok(status) => LinkHealth {
+                status_code: Some(i32::from(status)),
+                latency_ms,
+                error: None,
+                checked_at: Utc::now(),
+            },
+            Err(e) => LinkHealth {
+                status_code: None,
+                latency_ms,
+                error: Some(describe_error(&e)),
+                checked_at: Utc::now(),
+            },
+        }
+    }
+
+    async fn request(
+        &self,
+        method: Method,
+        target: &str,
+    ) -> (Result<u16, reqwest::Error>, Duration) {
+        let started = Instant::now();
+        let result = self
+            .client
+            .request(method, target)
+            .send()
+            .await
+            .map(|res| res.status().as_u16());
+        (result, started.elapsed())
+    }
+}
+
+fn client_builder(timeout: Duration) -> ClientBuilder {
+    outbound::guard(Client::builder())
+        .timeout(timeout)
+        // Don't follow redirects, a public target could bounce the probe into our network
+        .redirect(redirect::Policy::none())
+        .user_agent(concat!("url-shortener-health/", env!("CARGO_PKG_VERSION")))
+}
+
+/// reqwest's top-level message ("error sending request") hides the cause
+pub fn describe_error(e: &reqwest::Error) -> String {
+    if e.is_timeout() {
+        return "timed out".to_string();
+    }
+
+    let mut cause: &dyn std::error::Error = e;
+    while let Some(source) = cause.source() {
+        cause = source;
+    }
+    cause.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::SocketAddr;
+    use std::sync::Mutex;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    const STUB_HOST: &str = "stub.example.com";
+
+    /// HTTP server answering each method with a fixed status and recording
+    /// the methods it was asked with
+    async fn stub(
+        statuses: &'static [(&'static str, u16)],
+    ) -> (SocketAddr, Arc<Mutex<Vec<String>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let log = seen.clone();
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let mut buf = vec![0; 4096];
+                let n = socket.read(&mut buf).await.unwrap_or(0);
+                let head = String::from_utf8_lossy(&buf[..n]);
+                let method = head.split(' ').next().unwrap_or_default().to_string();
+                let status = statuses
+                    .iter()
+                    .find(|(m, _)| *m == method)
+                    .map_or(500, |(_, status)| *status);
+                log.lock().unwrap().push(method);
+                let response = format!(
+                    "HTTP/1.1 {status} Stub\r\ncontent-length: 0\r\nconnection: close\r\n\r\n"
+                );
+                socket.write_all(response.as_bytes()).await.ok();
+            }
+        });
+        (addr, seen)
+    }
+
+    /// Checker reaching the stub under a public-looking name, the way a
+    /// target would be reached after DNS
+    fn checker(addr: SocketAddr) -> HealthChecker {
+        let timeout = Duration::from_secs(2);
+        HealthChecker {
+            client: client_builder(timeout)
+                .resolve(STUB_HOST, addr)
+                .build()
+                .unwrap(),
+            ..HealthChecker::new(timeout, 1, Duration::ZERO).unwrap()
+        }
+    }
+
+    fn stub_url(addr: SocketAddr) -> String {
+        format!("http://{STUB_HOST}:{}/page", addr.port())
+    }
+
+    #[tokio::test]
+    async fn head_answer_is_recorded() {
+        let (addr, seen) = stub(&[("HEAD", 204)]).await;
+        let health = checker(addr).probe(&stub_url(addr)).await;
+        assert_eq!(health.status_code, Some(204));
+        assert_eq!(health.error, None);
+        assert_eq!(*seen.lock().unwrap(), ["HEAD"]);
+    }
+
+    #[tokio::test]
+    async fn rejected_head_falls_back_to_get() {
+        let (addr, seen) = stub(&[("HEAD", 405), ("GET", 200)]).await;
+        let health = checker(addr).probe(&stub_url(addr)).await;
+        assert_eq!(health.status_code, Some(200));
+        assert_eq!(*seen.lock().unwrap(), ["HEAD", "GET"]);
+    }
+
+    #[tokio::test]
+    async fn error_status_is_kept() {
+        let (addr, _) = stub(&[("HEAD", 404), ("GET", 404)]).await;
+        let health = checker(addr).probe(&stub_url(addr)).await;
+        assert_eq!(health.status_code, Some(404));
+    }
+
+    #[tokio::test]
+    async fn internal_address_is_not_probed() {
+        let (addr, seen) = stub(&[("HEAD", 200)]).await;
+        let health = checker(addr)
+            .probe(&format!("http://127.0.0.1:{}/", addr.port()))
+            .await;
+        assert_eq!(health.status_code, None);
+        assert!(health.error.unwrap().contains("loopback"));
+        assert!(seen.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn blocked_domain_is_not_probed() {
+        let (addr, seen) = stub(&[("HEAD", 200)]).await;
+        let policy = UrlPolicy::new(vec![STUB_HOST.to_string()], Vec::new());
+        let health = checker(addr)
+            .with_url_policy(policy)
+            .probe(&stub_url(addr))
+            .await;
+        assert!(health.error.unwrap().contains("blocked"));
+        assert!(seen.lock().unwrap().is_empty());
+    }
+}
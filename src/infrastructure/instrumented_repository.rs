@@ -0,0 +1,149 @@
+use crate::domain::entities::{LinkHealth, ShortUrl};
+use crate::domain::errors::DomainResult as Result;
+use crate::domain::repositories::{UrlFilter, UrlRepository};
+use crate::infrastructure::metrics::Metrics;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Instant;
+use uuid::Uuid;
+
+/// Decorator recording the latency and outcome of every repository call
+pub struct InstrumentedUrlRepository {
+    inner: Arc<dyn UrlRepository>,
+    metrics: Arc<Metrics>,
+}
+
+impl InstrumentedUrlRepository {
+    pub fn new(inner: Arc<dyn UrlRepository>, metrics: Arc<Metrics>) -> Self {
+        Self { inner, metrics }
+    }
+
+    async fn timed<T>(&self, method: &str, call: impl Future<Output = Result<T>>) -> Result<T> {
+        let started = Instant::now();
+        let result = call.await;
+        let outcome = if result.is_ok() { "ok" } else { "error" };
+        self.metrics
+            .repository_duration
+            .with_label_values(&[method, outcome])
+            .observe(started.elapsed().as_secs_f64());
+        result
+    }
+}
+
+#[async_trait]
+impl UrlRepository for InstrumentedUrlRepository {
+    async fn create(
+        &self,
+        short_code: &str,
+        target_url: &str,
+        expires_at: Option<DateTime<Utc>>,
+    ) -> Result<ShortUrl> {
+        self.timed(
+            "create",
+            self.inner.create(short_code, target_url, expires_at),
+        )
+        .await
+    }
+
+    async fn find_by_code(&self, code: &str) -> Result<Option<ShortUrl>> {
+        self.timed("find_by_code", self.inner.find_by_code(code))
+            .await
+    }
+
+    async fn increments_clicks(&self, id: Uuid) -> Result<()> {
+        self.timed("increments_clicks", self.inner.increments_clicks(id))
+            .await
+    }
+
+    async fn get_all_url(&self, filter: &UrlFilter) -> Result<Vec<ShortUrl>> {
+        self.timed("get_all_url", self.inner.get_all_url(filter))
+            .await
+    }
+
+    async fn find_active_urls(&self) -> Result<Vec<ShortUrl>> {
+        self.timed("find_active_urls", self.inner.find_active_urls())
+            .await
+    }
+
+    async fn update_health(&self, id: Uuid, health: &LinkHealth) -> Result<()> {
+        self.timed("update_health", self.inner.update_health(id, health))
+            .await
+    }
+
+    /// Every call is one cleanup run
+    async fn delete_expired_url(&self) -> Result<Vec<ShortUrl>> {
+        let result = self
+            .timed("delete_expired_url", self.inner.delete_expired_url())
+            .await;
+        match &result {
+            Ok(deleted) => {
+                self.metrics.cleanup_runs.with_label_values(&["ok"]).inc();
+                self.metrics.cleanup_deleted.inc_by(deleted.len() as u64);
+            }
+            Err(_) => self
+                .metrics
+                .cleanup_runs
+                .with_label_values(&["error"])
+                .inc(),
+        }
+        result
+    }
+
+    /// Every call is one cleanup run, like `delete_expired_url`
+    async fn archive_expired_url(&self) -> Result<Vec<ShortUrl>> {
+        let result = self
+            .timed("archive_expired_url", self.inner.archive_expired_url())
+            .await;
+        match &result {
+            Ok(archived) => {
+                self.metrics.cleanup_runs.with_label_values(&["ok"]).inc();
+                self.metrics.cleanup_archived.inc_by(archived.len() as u64);
+            }
+            Err(_) => self
+                .metrics
+                .cleanup_runs
+                .with_label_values(&["error"])
+                .inc(),
+        }
+        result
+    }
+
+    async fn purge_archived_url(&self, before: DateTime<Utc>) -> Result<u64> {
+        let result = self
+            .timed("purge_archived_url", self.inner.purge_archived_url(before))
+            .await;
+        if let Ok(purged) = result {
+            self.metrics.cleanup_deleted.inc_by(purged);
+        }
+        result
+    }
+
+    async fn next_code_sequence(&self) -> Result<i64> {
+        self.timed("next_code_sequence", self.inner.next_code_sequence())
+            .await
+    }
+
+    async fn find_case_collisions(&self) -> Result<Vec<String>> {
+        self.timed("find_case_collisions", self.inner.find_case_collisions())
+            .await
+    }
+
+    async fn reserve_case_insensitive_codes(&self) -> Result<u64> {
+        self.timed(
+            "reserve_case_insensitive_codes",
+            self.inner.reserve_case_insensitive_codes(),
+        )
+        .await
+    }
+
+    async fn update_url(&self, link: &ShortUrl) -> Result<()> {
+        self.timed("update_url", self.inner.update_url(link)).await
+    }
+
+    async fn delete_by_code(&self, code: &str) -> Result<ShortUrl> {
+        self.timed("delete_by_code", self.inner.delete_by_code(code))
+            .await
+    }
+}
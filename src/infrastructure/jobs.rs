@@ -0,0 +1,149 @@
+use crate::config::{CleanupConfig, ExpiredAction};
+use crate::domain::events::LinkEventRelay;
+use crate::domain::repositories::UrlRepository;
+use crate::domain::validators::expiry::LinkLifetime;
+use crate::infrastructure::health_checker::HealthChecker;
+use crate::infrastructure::leader::LeaderElection;
+use crate::infrastructure::scheduler::{Job, RunOutcome};
+use crate::infrastructure::webhook_dispatcher::WebhookDispatcher;
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use chrono::Utc;
+use std::sync::Arc;
+
+/// Takes and renews the scheduler lease on every instance
+pub struct LeaderElectionJob(pub Arc<LeaderElection>);
+
+#[async_trait]
+impl Job for LeaderElectionJob {
+    fn name(&self) -> &'static str {
+        "leader_election"
+    }
+
+    fn leader_only(&self) -> bool {
+        false
+    }
+
+    async fn run(&self) -> Result<RunOutcome> {
+        self.0.try_lead().await.context("scheduler lease")?;
+        Ok(RunOutcome::Done)
+    }
+}
+
+/// Archives or deletes expired links, then deletes archived links past the
+/// retention window
+pub struct CleanupJob<R> {
+    repo: Arc<R>,
+    action: ExpiredAction,
+    retention: Option<LinkLifetime>,
+}
+
+impl<R: UrlRepository> CleanupJob<R> {
+    pub fn new(repo: Arc<R>, config: &CleanupConfig) -> Self {
+        Self {
+            repo,
+            action: config.expired_action,
+            retention: config.archive_retention,
+        }
+    }
+}
+
+#[async_trait]
+impl<R: UrlRepository + 'static> Job for CleanupJob<R> {
+    fn name(&self) -> &'static str {
+        "cleanup"
+    }
+
+    async fn run(&self) -> Result<RunOutcome> {
+        let (links, verb) = match self.action {
+            ExpiredAction::Archive => (self.repo.archive_expired_url().await?, "archived"),
+            ExpiredAction::Delete => (self.repo.delete_expired_url().await?, "cleaned"),
+        };
+        if !links.is_empty() {
+            tracing::info!("🧹 Auto-{verb} {} expired short URLs", links.len());
+        }
+
+        if let Some(retention) = self.retention {
+            let before = Utc::now()
+                .checked_sub_signed(retention.as_delta())
+                .with_context(|| format!("archive retention {retention} is out of range"))?;
+            let purged = self
+                .repo
+                .purge_archived_url(before)
+                .await
+                .context("archive purge")?;
+            if purged > 0 {
+                tracing::info!("🧹 Purged {purged} archived short URLs");
+            }
+        }
+        Ok(RunOutcome::Done)
+    }
+}
+
+/// Probes every active link's target once per run
+pub struct HealthCheckJob<R> {
+    repo: Arc<R>,
+    checker: HealthChecker,
+}
+
+impl<R: UrlRepository> HealthCheckJob<R> {
+    pub fn new(repo: Arc<R>, checker: HealthChecker) -> Self {
+        Self { repo, checker }
+    }
+}
+
+#[async_trait]
+impl<R: UrlRepository + 'static> Job for HealthCheckJob<R> {
+    fn name(&self) -> &'static str {
+        "health_check"
+    }
+
+    async fn run(&self) -> Result<RunOutcome> {
+        let count = self.checker.run_sweep(self.repo.clone()).await?;
+        tracing::info!("🩺 Health checked {count} short URL targets");
+        Ok(RunOutcome::Done)
+    }
+}
+
+/// Turns recorded link events into deliveries, then sends a batch of due
+/// deliveries. Both are claimed under a lease, so every instance runs it, and
+/// a full batch of either runs again right away.
+pub struct WebhookDeliveryJob {
+    events: Arc<dyn LinkEventRelay>,
+    dispatcher: WebhookDispatcher,
+}
+
+impl WebhookDeliveryJob {
+    pub fn new(events: Arc<dyn LinkEventRelay>, dispatcher: WebhookDispatcher) -> Self {
+        Self { events, dispatcher }
+    }
+}
+
+#[async_trait]
+impl Job for WebhookDeliveryJob {
+    fn name(&self) -> &'static str {
+        "webhooks"
+    }
+
+    fn leader_only(&self) -> bool {
+        false
+    }
+
+    async fn run(&self) -> Result<RunOutcome> {
+        let batch = self.dispatcher.batch_size();
+        let relayed = self
+            .events
+            .relay(batch as i64)
+            .await
+            .context("link event relay")?;
+        let count = self.dispatcher.run_once().await?;
+        if count > 0 {
+            tracing::debug!("📬 Attempted {count} webhook deliveries");
+        }
+        Ok(if relayed < batch && count < batch {
+            RunOutcome::Done
+        } else {
+            RunOutcome::More
+        })
+    }
+}
@@ -0,0 +1,88 @@
+use crate::domain::entities::SchedulerLease;
+use crate::domain::errors::DomainResult as Result;
+use crate::domain::repositories::LeaseRepository;
+use chrono::TimeDelta;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+/// Lease name shared by the periodic jobs
+pub const SCHEDULER_LEASE: &str = "scheduler";
+
+/// Elects the one instance that runs the periodic jobs through a lease in the
+/// database. The leader renews it every third of its TTL; when it dies, the
+/// lease expires and the next instance to try takes over.
+pub struct LeaderElection {
+    repo: Arc<dyn LeaseRepository>,
+    instance_id: String,
+    ttl: TimeDelta,
+    /// Until when our last successful renewal lets us act as leader
+    held_until: Mutex<Option<Instant>>,
+}
+
+impl LeaderElection {
+    pub fn new(repo: Arc<dyn LeaseRepository>, instance_id: String, ttl: TimeDelta) -> Self {
+        Self {
+            repo,
+            instance_id,
+            ttl,
+            held_until: Mutex::new(None),
+        }
+    }
+
+    pub fn instance_id(&self) -> &str {
+        &self.instance_id
+    }
+
+    pub fn renew_interval(&self) -> std::time::Duration {
+        (self.ttl / 3).to_std().unwrap_or_default()
+    }
+
+    /// Whether this instance holds the lease. Judged by the local clock from
+    /// the last renewal, so a leader cut off from the database steps down
+    /// before its lease can be taken over
+    pub fn is_leader(&self) -> bool {
+        self.held_until
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .is_some_and(|until| Instant::now() < until)
+    }
+
+    /// Take or renew the lease, logging changes of leadership. On error the
+    /// current state is kept until the lease could have expired anyway
+    pub async fn try_lead(&self) -> Result<()> {
+        let started = Instant::now();
+        let was_leader = self.is_leader();
+        let held = self
+            .repo
+            .try_acquire(SCHEDULER_LEASE, &self.instance_id, self.ttl)
+            .await?;
+
+        // Counted from before the request, the lease may expire that early
+        let until = held.then(|| started + self.ttl.to_std().unwrap_or_default());
+        *self.held_until.lock().unwrap_or_else(|e| e.into_inner()) = until;
+
+        match (was_leader, held) {
+            (false, true) => tracing::info!("👑 {} is now the scheduler leader", self.instance_id),
+            (true, false) => tracing::warn!("{} lost the scheduler lease", self.instance_id),
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Hand the lease over on shutdown instead of letting it expire
+    pub async fn step_down(&self) {
+        if !self.is_leader() {
+            return;
+        }
+        *self.held_until.lock().unwrap_or_else(|e| e.into_inner()) = None;
+        match self.repo.release(SCHEDULER_LEASE, &self.instance_id).await {
+            Ok(()) => tracing::info!("{} released the scheduler lease", self.instance_id),
+            Err(e) => tracing::error!("Scheduler lease release error: {:?}", e),
+        }
+    }
+
+    /// The lease as stored, whoever holds it
+    pub async fn current_lease(&self) -> Result<Option<SchedulerLease>> {
+        self.repo.find_lease(SCHEDULER_LEASE).await
+    }
+}
@@ -0,0 +1,18 @@
+use crate::config::{LogFormat, LoggingConfig};
+use tracing_subscriber::EnvFilter;
+
+/// Install the global subscriber. Levels come from `RUST_LOG` (default
+/// `info`); JSON lines carry the fields of the current request span.
+pub fn init_tracing(config: &LoggingConfig) {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+    let builder = tracing_subscriber::fmt().with_env_filter(filter);
+
+    match config.format {
+        LogFormat::Text => builder.init(),
+        LogFormat::Json => builder
+            .json()
+            .with_current_span(true)
+            .with_span_list(false)
+            .init(),
+    }
+}
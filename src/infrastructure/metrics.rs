@@ -0,0 +1,147 @@
+use crate::infrastructure::database::Database;
+use anyhow::Result;
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGaugeVec, Opts, Registry,
+    TextEncoder,
+};
+
+/// Prometheus metrics of the service, rendered by `/metrics`
+pub struct Metrics {
+    registry: Registry,
+    db: Database,
+    pub http_requests: IntCounterVec,
+    pub http_request_duration: HistogramVec,
+    pub redirects: IntCounter,
+    /// Lookups of unknown (`not_found`) or expired (`expired`) codes
+    pub lookup_failures: IntCounterVec,
+    pub links_created: IntCounter,
+    pub cleanup_runs: IntCounterVec,
+    pub cleanup_deleted: IntCounter,
+    pub cleanup_archived: IntCounter,
+    pub repository_duration: HistogramVec,
+    /// Webhook delivery attempts by `delivered`, `retry` or `failed`
+    pub webhook_deliveries: IntCounterVec,
+    /// Background job runs by `job` and `outcome`
+    pub job_runs: IntCounterVec,
+    pub job_duration: HistogramVec,
+    db_pool_connections: IntGaugeVec,
+}
+
+impl Metrics {
+    pub fn new(db: Database) -> Result<Self> {
+        let registry = Registry::new();
+
+        let http_requests = IntCounterVec::new(
+            Opts::new("http_requests_total", "HTTP requests handled"),
+            &["method", "route", "status"],
+        )?;
+        let http_request_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "http_request_duration_seconds",
+                "Time spent handling HTTP requests",
+            ),
+            &["method", "route", "status"],
+        )?;
+        let redirects = IntCounter::new("redirects_total", "Redirects served")?;
+        let lookup_failures = IntCounterVec::new(
+            Opts::new(
+                "link_lookup_failures_total",
+                "Short code lookups that did not redirect",
+            ),
+            &["reason"],
+        )?;
+        let links_created = IntCounter::new("links_created_total", "Short links created")?;
+        let cleanup_runs = IntCounterVec::new(
+            Opts::new("cleanup_runs_total", "Expired link cleanup runs"),
+            &["outcome"],
+        )?;
+        let cleanup_deleted = IntCounter::new(
+            "cleanup_deleted_rows_total",
+            "Expired or archived links deleted by cleanup",
+        )?;
+        let cleanup_archived = IntCounter::new(
+            "cleanup_archived_rows_total",
+            "Expired links archived by cleanup",
+        )?;
+        let repository_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "repository_query_duration_seconds",
+                "Time spent in repository methods",
+            )
+            .buckets(vec![
+                0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5,
+            ]),
+            &["method", "outcome"],
+        )?;
+        let webhook_deliveries = IntCounterVec::new(
+            Opts::new("webhook_deliveries_total", "Webhook delivery attempts"),
+            &["outcome"],
+        )?;
+        let job_runs = IntCounterVec::new(
+            Opts::new("job_runs_total", "Background job runs"),
+            &["job", "outcome"],
+        )?;
+        let job_duration = HistogramVec::new(
+            HistogramOpts::new("job_duration_seconds", "Time spent in background job runs")
+                .buckets(vec![
+                    0.01, 0.1, 0.5, 1.0, 5.0, 15.0, 60.0, 300.0, 900.0, 3600.0,
+                ]),
+            &["job"],
+        )?;
+        let db_pool_connections = IntGaugeVec::new(
+            Opts::new("db_pool_connections", "Database pool connections by state"),
+            &["state"],
+        )?;
+
+        registry.register(Box::new(http_requests.clone()))?;
+        registry.register(Box::new(http_request_duration.clone()))?;
+        registry.register(Box::new(redirects.clone()))?;
+        registry.register(Box::new(lookup_failures.clone()))?;
+        registry.register(Box::new(links_created.clone()))?;
+        registry.register(Box::new(cleanup_runs.clone()))?;
+        registry.register(Box::new(cleanup_deleted.clone()))?;
+        registry.register(Box::new(cleanup_archived.clone()))?;
+        registry.register(Box::new(repository_duration.clone()))?;
+        registry.register(Box::new(webhook_deliveries.clone()))?;
+        registry.register(Box::new(job_runs.clone()))?;
+        registry.register(Box::new(job_duration.clone()))?;
+        registry.register(Box::new(db_pool_connections.clone()))?;
+
+        Ok(Self {
+            registry,
+            db,
+            http_requests,
+            http_request_duration,
+            redirects,
+            lookup_failures,
+            links_created,
+            cleanup_runs,
+            cleanup_deleted,
+            cleanup_archived,
+            repository_duration,
+            webhook_deliveries,
+            job_runs,
+            job_duration,
+            db_pool_connections,
+        })
+    }
+
+    /// Prometheus text exposition of every metric, sampling the pool first
+    pub fn render(&self) -> Result<String> {
+        let stats = self.db.pool_stats();
+        let (size, idle, max) = (stats.size as i64, stats.idle as i64, stats.max as i64);
+        self.db_pool_connections
+            .with_label_values(&["idle"])
+            .set(idle);
+        self.db_pool_connections
+            .with_label_values(&["in_use"])
+            .set(size - idle);
+        self.db_pool_connections
+            .with_label_values(&["max"])
+            .set(max);
+
+        let mut buffer = Vec::new();
+        TextEncoder::new().encode(&self.registry.gather(), &mut buffer)?;
+        Ok(String::from_utf8(buffer)?)
+    }
+}
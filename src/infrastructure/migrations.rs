@@ -0,0 +1,231 @@
+use crate::infrastructure::database::Database;
+#[cfg(feature = "redb")]
+use crate::infrastructure::redb_repository;
+use anyhow::{Result, bail};
+use sqlx::PgPool;
+use sqlx::migrate::Migrator;
+
+/// Postgres migrations embedded from `migrations/` at compile time. Running
+/// them holds an advisory lock, so replicas starting together apply each once.
+pub static MIGRATOR: Migrator = sqlx::migrate!();
+
+/// SQLite has its own history in `migrations_sqlite/`
+#[cfg(feature = "sqlite")]
+pub static SQLITE_MIGRATOR: Migrator = sqlx::migrate!("./migrations_sqlite");
+
+/// The first migration used to start with `DROP TABLE short_urls;`. Databases
+/// that recorded that version get the checksum of the rewritten script, the
+/// schema it creates is the same.
+const INITIAL_VERSION: i64 = 20251024072202;
+const DESTRUCTIVE_INITIAL_CHECKSUM: &str = "4c7d820389d72f6ed56572743abfeceacf92d505534d9614b6d308d60a1e3a0cd78a5f4f30f8842502cedf150e527322";
+
+/// SQL migrations of the backend, `None` for the redb store whose layout is
+/// versioned when the file is opened
+pub fn migrator(db: &Database) -> Option<&'static Migrator> {
+    match db {
+        Database::Postgres(_) => Some(&MIGRATOR),
+        #[cfg(feature = "sqlite")]
+        Database::Sqlite(_) => Some(&SQLITE_MIGRATOR),
+        #[cfg(feature = "redb")]
+        Database::Redb(_) => None,
+    }
+}
+
+/// Latest migration this binary knows for the backend
+pub fn latest_version(db: &Database) -> i64 {
+    match migrator(db) {
+        Some(migrator) => migrator.iter().map(|m| m.version).max().unwrap_or_default(),
+        #[cfg(feature = "redb")]
+        None => redb_repository::SCHEMA_VERSION,
+        #[cfg(not(feature = "redb"))]
+        None => 0,
+    }
+}
+
+/// Refuse a schema migrated by a newer binary, then apply pending migrations
+/// when `run` is set
+pub async fn prepare_schema(db: &Database, run: bool) -> Result<()> {
+    let Some(migrator) = migrator(db) else {
+        return Ok(());
+    };
+    let applied = applied_versions(db).await?;
+    let latest = latest_version(db);
+
+    let unknown: Vec<i64> = applied
+        .iter()
+        .copied()
+        .filter(|v| !migrator.iter().any(|m| m.version == *v))
+        .collect();
+    if !unknown.is_empty() {
+        bail!(
+            "database schema has migrations {unknown:?} unknown to this binary (latest {latest}), \
+             it was migrated by a newer version; deploy that version or newer"
+        );
+    }
+
+    if !run {
+        if applied.last().is_none_or(|v| *v < latest) {
+            tracing::warn!(
+                "database schema is behind this binary (latest migration {latest}), \
+                 apply migrations or set RUN_MIGRATIONS=true"
+            );
+        }
+        return Ok(());
+    }
+
+    match db {
+        Database::Postgres(pool) => {
+            repair_initial_checksum(pool).await?;
+            migrator.run(pool).await?;
+        }
+        #[cfg(feature = "sqlite")]
+        Database::Sqlite(pool) => migrator.run(pool).await?,
+        #[cfg(feature = "redb")]
+        Database::Redb(_) => unreachable!("redb has no SQL migrations"),
+    }
+
+    let pending = migrator
+        .iter()
+        .filter(|m| !applied.contains(&m.version))
+        .count();
+    if pending > 0 {
+        tracing::info!("Applied {pending} migrations, schema at {latest}");
+    }
+    Ok(())
+}
+
+const APPLIED_VERSIONS: &str =
+    "SELECT version FROM _sqlx_migrations WHERE success ORDER BY version";
+
+/// Versions recorded in `_sqlx_migrations`, ascending. Empty on a database
+/// that was never migrated by sqlx. For redb, the layout version of the store.
+pub async fn applied_versions(db: &Database) -> Result<Vec<i64>> {
+    let versions = match db {
+        Database::Postgres(pool) => {
+            if !migrations_table_exists(pool).await? {
+                return Ok(Vec::new());
+            }
+            sqlx::query_scalar(APPLIED_VERSIONS).fetch_all(pool).await?
+        }
+        #[cfg(feature = "sqlite")]
+        Database::Sqlite(pool) => {
+            let exists: bool = sqlx::query_scalar(
+                "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = '_sqlx_migrations')",
+            )
+            .fetch_one(pool)
+            .await?;
+            if !exists {
+                return Ok(Vec::new());
+            }
+            sqlx::query_scalar(APPLIED_VERSIONS).fetch_all(pool).await?
+        }
+        #[cfg(feature = "redb")]
+        Database::Redb(db) => redb_repository::schema_version(db)?.into_iter().collect(),
+    };
+    Ok(versions)
+}
+
+/// Whether the checksum of the destructive initial script was replaced
+async fn repair_initial_checksum(pool: &PgPool) -> Result<bool> {
+    let Some(initial) = MIGRATOR.iter().find(|m| m.version == INITIAL_VERSION) else {
+        return Ok(false);
+    };
+    if !migrations_table_exists(pool).await? {
+        return Ok(false);
+    }
+
+    let repaired = sqlx::query(
+        "UPDATE _sqlx_migrations SET checksum = $1
+        WHERE version = $2 AND encode(checksum, 'hex') = $3",
+    )
+    .bind(initial.checksum.as_ref())
+    .bind(INITIAL_VERSION)
+    .bind(DESTRUCTIVE_INITIAL_CHECKSUM)
+    .execute(pool)
+    .await?
+    .rows_affected();
+    if repaired > 0 {
+        tracing::info!("Updated the checksum of the rewritten initial migration");
+    }
+    Ok(repaired > 0)
+}
+
+async fn migrations_table_exists(pool: &PgPool) -> Result<bool> {
+    let exists = sqlx::query_scalar("SELECT to_regclass('_sqlx_migrations') IS NOT NULL")
+        .fetch_one(pool)
+        .await?;
+    Ok(exists)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn initial_checksum(pool: &PgPool) -> String {
+        sqlx::query_scalar(
+            "SELECT encode(checksum, 'hex') FROM _sqlx_migrations WHERE version = $1",
+        )
+        .bind(INITIAL_VERSION)
+        .fetch_one(pool)
+        .await
+        .unwrap()
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn the_destructive_initial_checksum_is_repaired_once(pool: PgPool) {
+        let db = Database::Postgres(pool.clone());
+        prepare_schema(&db, true).await.unwrap();
+        let rewritten = initial_checksum(&pool).await;
+
+        // As recorded by a database that ran the old script
+        sqlx::query("UPDATE _sqlx_migrations SET checksum = decode($1, 'hex') WHERE version = $2")
+            .bind(DESTRUCTIVE_INITIAL_CHECKSUM)
+            .bind(INITIAL_VERSION)
+            .execute(&pool)
+            .await
+            .unwrap();
+        assert!(MIGRATOR.run(&pool).await.is_err());
+
+        prepare_schema(&db, true).await.unwrap();
+        assert_eq!(initial_checksum(&pool).await, rewritten);
+        assert!(!repair_initial_checksum(&pool).await.unwrap());
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn a_fresh_database_needs_no_repair(pool: PgPool) {
+        assert!(!repair_initial_checksum(&pool).await.unwrap());
+        let db = Database::Postgres(pool);
+        assert!(applied_versions(&db).await.unwrap().is_empty());
+        prepare_schema(&db, true).await.unwrap();
+        assert_eq!(
+            applied_versions(&db).await.unwrap().last(),
+            Some(&latest_version(&db))
+        );
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn unknown_and_newer_versions_are_refused(pool: PgPool) {
+        let db = Database::Postgres(pool.clone());
+        prepare_schema(&db, true).await.unwrap();
+        let unknown = 20200101000000;
+        let newer = latest_version(&db) + 1;
+        for version in [unknown, newer] {
+            sqlx::query(
+                "INSERT INTO _sqlx_migrations (version, description, success, checksum, execution_time)
+                VALUES ($1, 'not ours', true, '\\x00', 0)",
+            )
+            .bind(version)
+            .execute(&pool)
+            .await
+            .unwrap();
+        }
+
+        for run in [true, false] {
+            let err = prepare_schema(&db, run).await.unwrap_err().to_string();
+            let expected = format!(
+                "database schema has migrations [{unknown}, {newer}] unknown to this binary"
+            );
+            assert!(err.starts_with(&expected), "{err}");
+        }
+    }
+}
@@ -1,3 +1,17 @@
 pub mod database;
+pub mod health_checker;
+pub mod instrumented_repository;
+pub mod jobs;
+pub mod leader;
+pub mod logging;
+pub mod metrics;
+pub mod migrations;
+pub mod outbound;
+pub mod readiness;
+#[cfg(feature = "redb")]
+pub mod redb_repository;
 pub mod repositories;
 pub mod scheduler;
+#[cfg(feature = "sqlite")]
+pub mod sqlite_repository;
+pub mod webhook_dispatcher;
@@ -0,0 +1,77 @@
+use crate::domain::validators::url_validator::check_ip;
+use reqwest::ClientBuilder;
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+/// Resolver for requests to user-supplied URLs. A public host name may
+/// resolve to an internal address, which the URL checks can't see, so the
+/// addresses are checked again here, right before connecting.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PublicResolver;
+
+impl Resolve for PublicResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        Box::pin(async move {
+            let addrs = resolve_public(name.as_str()).await?;
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+/// Addresses of `host` once internal ones are dropped, an error when none
+/// is left
+pub async fn resolve_public(host: &str) -> io::Result<Vec<SocketAddr>> {
+    // The port is replaced by the one of the URL
+    let resolved: Vec<SocketAddr> = tokio::net::lookup_host((host, 0)).await?.collect();
+    let mut refused = None;
+    let public: Vec<SocketAddr> = resolved
+        .into_iter()
+        .filter(|addr| match check_ip(addr.ip()) {
+            Ok(()) => true,
+            Err(e) => {
+                refused.get_or_insert(e);
+                false
+            }
+        })
+        .collect();
+
+    match refused {
+        Some(e) if public.is_empty() => Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!("{host} resolves to a refused address: {e}"),
+        )),
+        _ => Ok(public),
+    }
+}
+
+/// Client settings for requests to user-supplied URLs: hosts resolve through
+/// [`PublicResolver`], and no proxy is used, it would resolve them itself
+pub fn guard(builder: ClientBuilder) -> ClientBuilder {
+    builder.dns_resolver(Arc::new(PublicResolver)).no_proxy()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn refuses_host_resolving_to_loopback() {
+        let err = resolve_public("localhost").await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+    }
+
+    #[tokio::test]
+    async fn refuses_internal_address_literals() {
+        for host in ["127.0.0.1", "10.1.2.3", "169.254.169.254", "::1"] {
+            assert!(resolve_public(host).await.is_err(), "{host}");
+        }
+    }
+
+    #[tokio::test]
+    async fn keeps_public_address_literals() {
+        let addrs = resolve_public("93.184.215.14").await.unwrap();
+        assert_eq!(addrs.len(), 1);
+    }
+}
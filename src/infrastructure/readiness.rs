@@ -0,0 +1,104 @@
+use crate::domain::utils::utilities::format_datetime;
+use crate::infrastructure::database::Database;
+use crate::infrastructure::migrations;
+use crate::infrastructure::scheduler::JobStatus;
+use salvo::oapi::ToSchema;
+use serde::Serialize;
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::time::{Duration, timeout};
+
+/// How long the database may take to answer the readiness query
+const DB_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Outcome of one readiness check
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Check {
+    pub name: String,
+    pub ok: bool,
+    pub detail: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ReadinessReport {
+    pub ready: bool,
+    pub checks: Vec<Check>,
+}
+
+/// Whether the instance can serve traffic: the database answers, its schema
+/// is at the version this binary was built for and the background jobs run.
+pub struct ReadinessCheck {
+    db: Database,
+    jobs: Vec<Arc<JobStatus>>,
+}
+
+impl ReadinessCheck {
+    pub fn new(db: Database, jobs: Vec<Arc<JobStatus>>) -> Self {
+        Self { db, jobs }
+    }
+
+    pub async fn check(&self) -> ReadinessReport {
+        let mut checks = vec![self.check_database().await, self.check_migrations().await];
+        checks.extend(self.jobs.iter().map(|job| check_job(job)));
+
+        ReadinessReport {
+            ready: checks.iter().all(|c| c.ok),
+            checks,
+        }
+    }
+
+    async fn check_database(&self) -> Check {
+        let started = Instant::now();
+        let result = timeout(DB_TIMEOUT, self.db.ping()).await;
+
+        let (ok, detail) = match result {
+            Ok(Ok(_)) => (true, format!("{} ms", started.elapsed().as_millis())),
+            Ok(Err(e)) => (false, e.to_string()),
+            Err(_) => (false, format!("no answer within {DB_TIMEOUT:?}")),
+        };
+        Check {
+            name: "database".to_string(),
+            ok,
+            detail,
+        }
+    }
+
+    async fn check_migrations(&self) -> Check {
+        let expected = migrations::latest_version(&self.db);
+        let applied = timeout(DB_TIMEOUT, migrations::applied_versions(&self.db)).await;
+
+        let (ok, detail) = match applied {
+            Ok(Ok(versions)) => match versions.last() {
+                Some(&applied) => (
+                    applied == expected,
+                    format!("applied {applied}, expected {expected}"),
+                ),
+                None => (false, format!("none applied, expected {expected}")),
+            },
+            Ok(Err(e)) => (false, e.to_string()),
+            Err(_) => (false, format!("no answer within {DB_TIMEOUT:?}")),
+        };
+        Check {
+            name: "migrations".to_string(),
+            ok,
+            detail,
+        }
+    }
+}
+
+fn check_job(job: &JobStatus) -> Check {
+    // A failed run is retried on schedule, only a stopped job is not ready
+    let detail = match (job.is_alive(), job.last_run_at(), job.last_error()) {
+        (false, _, _) => "stopped".to_string(),
+        (true, Some(at), Some(error)) => {
+            format!("last run {} failed: {error}", format_datetime(&at))
+        }
+        (true, Some(at), None) => format!("last run {}", format_datetime(&at)),
+        (true, None, _) => "running, not finished a run yet".to_string(),
+    };
+    Check {
+        name: format!("job:{}", job.name()),
+        ok: job.is_alive(),
+        detail,
+    }
+}
@@ -0,0 +1,1453 @@
+use crate::domain::entities::{
+    ApiKey, LinkEvent, LinkHealth, SchedulerLease, ShortUrl, WebhookDelivery, WebhookSubscription,
+};
+use crate::domain::errors::{DomainError, DomainResult as Result};
+use crate::domain::events::LinkEventKind;
+use crate::domain::repositories::{
+    ApiKeyRepository, LeaseRepository, UrlFilter, UrlRepository, WebhookRepository,
+};
+use async_trait::async_trait;
+use chrono::{DateTime, TimeDelta, Timelike, Utc};
+use redb::{MultimapTableDefinition, ReadableMultimapTable, ReadableTable, TableDefinition};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, Ordering};
+use uuid::Uuid;
+
+/// Links by id, JSON encoded
+const LINKS: TableDefinition<u128, &[u8]> = TableDefinition::new("links");
+/// Unique index of the exact short code
+const CODES: TableDefinition<&str, u128> = TableDefinition::new("codes");
+/// Lowercased short codes, for case-insensitive lookups and collision reports
+const CODES_LOWER: MultimapTableDefinition<&str, u128> =
+    MultimapTableDefinition::new("codes_lower");
+/// Links with an expiry, ordered by (expires_at in ms, id) so cleanup only
+/// reads the expired range
+const EXPIRY: TableDefinition<(i64, u128), ()> = TableDefinition::new("expiry");
+/// Archived links ordered by (archived_at in ms, id), for the retention purge.
+/// Archived links are no longer in `EXPIRY`
+const ARCHIVED: TableDefinition<(i64, u128), ()> = TableDefinition::new("archived");
+/// Webhook subscriptions by id, JSON encoded
+const SUBSCRIPTIONS: TableDefinition<u128, &[u8]> = TableDefinition::new("webhook_subscriptions");
+/// Webhook deliveries by id, JSON encoded
+const DELIVERIES: TableDefinition<u128, &[u8]> = TableDefinition::new("webhook_deliveries");
+/// Pending deliveries ordered by (next attempt in ms, id)
+const DELIVERIES_DUE: TableDefinition<(i64, u128), ()> = TableDefinition::new("webhook_due");
+/// Outbox of link events ordered by (occurred_at in ms, id), with the time
+/// in ms until which a relay holds them and the JSON encoded event
+const LINK_EVENTS: TableDefinition<(i64, u128), (i64, &[u8])> = TableDefinition::new("link_events");
+/// Scheduler leases by name, JSON encoded
+const LEASES: TableDefinition<&str, &[u8]> = TableDefinition::new("scheduler_leases");
+/// API keys by id, JSON encoded
+const API_KEYS: TableDefinition<u128, &[u8]> = TableDefinition::new("api_keys");
+/// Store layout version and the short code sequence
+const META: TableDefinition<&str, i64> = TableDefinition::new("meta");
+
+const SCHEMA_VERSION_KEY: &str = "schema_version";
+const SEQUENCE_KEY: &str = "short_code_seq";
+
+/// Click commits skip the fsync, every this many clicks one is durable
+const CLICKS_PER_SYNC: u32 = 64;
+
+/// Layout of the tables above. The redb store has no migrations: tables are
+/// created on open and the recorded version raised, a binary refuses a file
+/// written with a newer layout.
+///
+/// 2: `link_events` outbox
+/// 3: `archived` table and `archived_at` on links
+/// 4: `api_keys` table
+pub const SCHEMA_VERSION: i64 = 4;
+
+/// Open or create the store file, creating the tables on first use
+pub fn open_store(path: impl AsRef<Path>) -> anyhow::Result<redb::Database> {
+    let db = redb::Database::create(path)?;
+
+    let txn = db.begin_write()?;
+    {
+        txn.open_table(LINKS)?;
+        txn.open_table(CODES)?;
+        txn.open_multimap_table(CODES_LOWER)?;
+        txn.open_table(EXPIRY)?;
+        txn.open_table(ARCHIVED)?;
+        txn.open_table(SUBSCRIPTIONS)?;
+        txn.open_table(DELIVERIES)?;
+        txn.open_table(DELIVERIES_DUE)?;
+        txn.open_table(LINK_EVENTS)?;
+        txn.open_table(LEASES)?;
+        txn.open_table(API_KEYS)?;
+
+        let mut meta = txn.open_table(META)?;
+        let version = meta.get(SCHEMA_VERSION_KEY)?.map(|v| v.value());
+        match version {
+            Some(version) if version > SCHEMA_VERSION => anyhow::bail!(
+                "redb store has layout version {version}, this binary supports up to {SCHEMA_VERSION}; \
+                 it was written by a newer version"
+            ),
+            Some(SCHEMA_VERSION) => {}
+            // Older layouts only lack tables, created above
+            _ => {
+                meta.insert(SCHEMA_VERSION_KEY, SCHEMA_VERSION)?;
+            }
+        }
+    }
+    txn.commit()?;
+    Ok(db)
+}
+
+/// Persist commits made without fsync, at shutdown
+pub fn flush(db: &redb::Database) -> anyhow::Result<()> {
+    let mut txn = db.begin_write()?;
+    txn.set_durability(redb::Durability::Immediate);
+    txn.commit()?;
+    Ok(())
+}
+
+/// Layout version recorded in the store
+pub fn schema_version(db: &redb::Database) -> anyhow::Result<Option<i64>> {
+    let txn = db.begin_read()?;
+    let meta = txn.open_table(META)?;
+    Ok(meta.get(SCHEMA_VERSION_KEY)?.map(|v| v.value()))
+}
+
+fn storage(e: impl Into<redb::Error>) -> DomainError {
+    DomainError::Storage(e.into().into())
+}
+
+fn encode(value: &impl Serialize) -> Result<Vec<u8>> {
+    serde_json::to_vec(value).map_err(|e| DomainError::Storage(e.into()))
+}
+
+fn decode<T: DeserializeOwned>(bytes: &[u8]) -> Result<T> {
+    serde_json::from_slice(bytes).map_err(|e| DomainError::Storage(e.into()))
+}
+
+fn expiry_key(link: &ShortUrl) -> Option<(i64, u128)> {
+    link.expires_at
+        .map(|at| (at.timestamp_millis(), link.id.as_u128()))
+}
+
+fn archive_key(link: &ShortUrl) -> Option<(i64, u128)> {
+    link.archived_at
+        .map(|at| (at.timestamp_millis(), link.id.as_u128()))
+}
+
+fn is_active(link: &ShortUrl, now: DateTime<Utc>) -> bool {
+    link.expires_at.is_none_or(|at| at > now)
+}
+
+/// Record `kind` for `link` in the event outbox within `txn`, unless no
+/// webhook subscribes to it
+fn record_event(txn: &redb::WriteTransaction, kind: LinkEventKind, link: &ShortUrl) -> Result<()> {
+    let subscriptions = txn.open_table(SUBSCRIPTIONS).map_err(storage)?;
+    let mut subscribed = false;
+    for entry in subscriptions.iter().map_err(storage)? {
+        let (_, bytes) = entry.map_err(storage)?;
+        let subscription: WebhookSubscription = decode(bytes.value())?;
+        if subscription.events.iter().any(|e| e == kind.as_str()) {
+            subscribed = true;
+            break;
+        }
+    }
+    if !subscribed {
+        return Ok(());
+    }
+
+    let event = LinkEvent {
+        id: Uuid::new_v4(),
+        kind: kind.as_str().to_string(),
+        link: link.clone(),
+        occurred_at: Utc::now(),
+    };
+    let occurred_at = event.occurred_at.timestamp_millis();
+    txn.open_table(LINK_EVENTS)
+        .map_err(storage)?
+        .insert(
+            (occurred_at, event.id.as_u128()),
+            (occurred_at, encode(&event)?.as_slice()),
+        )
+        .map_err(storage)?;
+    Ok(())
+}
+
+/// `UrlRepository` on an embedded redb file, for single-node deployments
+/// without any database server. Every call runs one ACID transaction on the
+/// blocking pool.
+///
+/// Clicks are committed without waiting for the disk, so a redirect doesn't
+/// pay for an fsync: every [`CLICKS_PER_SYNC`]th click, any other write and
+/// [`flush`] at shutdown persist them. A crash loses at most the clicks
+/// counted since.
+#[derive(Clone)]
+pub struct RedbUrlRepository {
+    pub db: Arc<redb::Database>,
+    case_insensitive_codes: bool,
+    unsynced_clicks: Arc<AtomicU32>,
+}
+
+impl RedbUrlRepository {
+    pub fn new(db: Arc<redb::Database>) -> Self {
+        Self {
+            db,
+            case_insensitive_codes: false,
+            unsynced_clicks: Arc::new(AtomicU32::new(0)),
+        }
+    }
+
+    /// Look codes up ignoring case, served by the lowercased code table
+    pub fn with_case_insensitive_codes(mut self, enabled: bool) -> Self {
+        self.case_insensitive_codes = enabled;
+        self
+    }
+
+    async fn blocking<T, F>(&self, f: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&redb::Database, bool) -> Result<T> + Send + 'static,
+    {
+        let db = self.db.clone();
+        let case_insensitive = self.case_insensitive_codes;
+        tokio::task::spawn_blocking(move || f(&db, case_insensitive))
+            .await
+            .map_err(|e| DomainError::Storage(e.into()))?
+    }
+}
+
+/// Id of the link with `code`, honouring the case mode
+fn lookup_id(
+    codes: &impl ReadableTable<&'static str, u128>,
+    codes_lower: &impl ReadableMultimapTable<&'static str, u128>,
+    code: &str,
+    case_insensitive: bool,
+) -> Result<Option<u128>> {
+    if !case_insensitive {
+        return Ok(codes.get(code).map_err(storage)?.map(|id| id.value()));
+    }
+    let mut ids = codes_lower
+        .get(code.to_lowercase().as_str())
+        .map_err(storage)?;
+    ids.next()
+        .transpose()
+        .map(|id| id.map(|id| id.value()))
+        .map_err(storage)
+}
+
+/// Read-modify-write of one link, a no-op when it is gone. `change` returns
+/// the event the change amounts to, if any
+fn update_link(
+    db: &redb::Database,
+    id: Uuid,
+    durability: redb::Durability,
+    change: impl FnOnce(&mut ShortUrl) -> Option<LinkEventKind>,
+) -> Result<()> {
+    let mut txn = db.begin_write().map_err(storage)?;
+    txn.set_durability(durability);
+    {
+        let mut links = txn.open_table(LINKS).map_err(storage)?;
+        let link = links
+            .get(id.as_u128())
+            .map_err(storage)?
+            .map(|bytes| decode(bytes.value()))
+            .transpose()?;
+        if let Some(mut link) = link {
+            let event = change(&mut link);
+            links
+                .insert(id.as_u128(), encode(&link)?.as_slice())
+                .map_err(storage)?;
+            if let Some(kind) = event {
+                record_event(&txn, kind, &link)?;
+            }
+        }
+    }
+    txn.commit().map_err(storage)
+}
+
+#[async_trait]
+impl UrlRepository for RedbUrlRepository {
+    async fn create(
+        &self,
+        short_code: &str,
+        target_url: &str,
+        expires_at: Option<DateTime<Utc>>,
+    ) -> Result<ShortUrl> {
+        let link = ShortUrl {
+            id: Uuid::new_v4(),
+            short_code: short_code.to_string(),
+            target_url: target_url.to_string(),
+            clicks: 0,
+            created_at: Utc::now().with_nanosecond(0).unwrap(),
+            expires_at,
+            health_status_code: None,
+            health_latency_ms: None,
+            health_error: None,
+            health_checked_at: None,
+            archived_at: None,
+        };
+
+        self.blocking(move |db, case_insensitive| {
+            let id = link.id.as_u128();
+            let lower = link.short_code.to_lowercase();
+            let txn = db.begin_write().map_err(storage)?;
+            {
+                let mut codes = txn.open_table(CODES).map_err(storage)?;
+                let mut codes_lower = txn.open_multimap_table(CODES_LOWER).map_err(storage)?;
+                // Write transactions are serialized, so checking is enough
+                let taken = if case_insensitive {
+                    !codes_lower.get(lower.as_str()).map_err(storage)?.is_empty()
+                } else {
+                    codes
+                        .get(link.short_code.as_str())
+                        .map_err(storage)?
+                        .is_some()
+                };
+                if taken {
+                    return Err(DomainError::Conflict(format!(
+                        "short code {} already exists",
+                        link.short_code
+                    )));
+                }
+                codes
+                    .insert(link.short_code.as_str(), id)
+                    .map_err(storage)?;
+                codes_lower.insert(lower.as_str(), id).map_err(storage)?;
+                txn.open_table(LINKS)
+                    .map_err(storage)?
+                    .insert(id, encode(&link)?.as_slice())
+                    .map_err(storage)?;
+                if let Some(key) = expiry_key(&link) {
+                    txn.open_table(EXPIRY)
+                        .map_err(storage)?
+                        .insert(key, ())
+                        .map_err(storage)?;
+                }
+                record_event(&txn, LinkEventKind::Created, &link)?;
+            }
+            txn.commit().map_err(storage)?;
+            Ok(link)
+        })
+        .await
+    }
+
+    async fn find_by_code(&self, code: &str) -> Result<Option<ShortUrl>> {
+        let code = code.to_string();
+        self.blocking(move |db, case_insensitive| {
+            let txn = db.begin_read().map_err(storage)?;
+            let codes = txn.open_table(CODES).map_err(storage)?;
+            let codes_lower = txn.open_multimap_table(CODES_LOWER).map_err(storage)?;
+            let Some(id) = lookup_id(&codes, &codes_lower, &code, case_insensitive)? else {
+                return Ok(None);
+            };
+            let links = txn.open_table(LINKS).map_err(storage)?;
+            let link = links.get(id).map_err(storage)?;
+            link.map(|bytes| decode(bytes.value())).transpose()
+        })
+        .await
+    }
+
+    async fn increments_clicks(&self, id: Uuid) -> Result<()> {
+        let count = self.unsynced_clicks.fetch_add(1, Ordering::Relaxed) + 1;
+        let durability = if count.is_multiple_of(CLICKS_PER_SYNC) {
+            redb::Durability::Immediate
+        } else {
+            redb::Durability::None
+        };
+        self.blocking(move |db, _| {
+            update_link(db, id, durability, |link| {
+                link.clicks += 1;
+                None
+            })
+        })
+        .await
+    }
+
+    async fn get_all_url(&self, filter: &UrlFilter) -> Result<Vec<ShortUrl>> {
+        let (broken, archived) = (filter.broken, filter.archived);
+        self.blocking(move |db, _| {
+            let txn = db.begin_read().map_err(storage)?;
+            let links = txn.open_table(LINKS).map_err(storage)?;
+            let mut all = Vec::new();
+            // Same order as `ORDER BY id DESC` on the SQL backends
+            for entry in links.iter().map_err(storage)?.rev() {
+                let (_, bytes) = entry.map_err(storage)?;
+                let link: ShortUrl = decode(bytes.value())?;
+                if link.archived_at.is_some() == archived
+                    && broken.is_none_or(|broken| link.is_broken() == broken)
+                {
+                    all.push(link);
+                }
+            }
+            Ok(all)
+        })
+        .await
+    }
+
+    async fn find_active_urls(&self) -> Result<Vec<ShortUrl>> {
+        self.blocking(|db, _| {
+            let now = Utc::now();
+            let txn = db.begin_read().map_err(storage)?;
+            let links = txn.open_table(LINKS).map_err(storage)?;
+            let mut active = Vec::new();
+            for entry in links.iter().map_err(storage)? {
+                let (_, bytes) = entry.map_err(storage)?;
+                let link = decode(bytes.value())?;
+                if is_active(&link, now) {
+                    active.push(link);
+                }
+            }
+            Ok(active)
+        })
+        .await
+    }
+
+    async fn update_health(&self, id: Uuid, health: &LinkHealth) -> Result<()> {
+        let health = health.clone();
+        self.blocking(move |db, _| {
+            update_link(db, id, redb::Durability::Immediate, |link| {
+                let checked = link.clone().with_health(&health);
+                let flipped = checked.is_broken() != link.is_broken();
+                *link = checked;
+                flipped.then_some(LinkEventKind::Updated)
+            })
+        })
+        .await
+    }
+
+    async fn update_url(&self, link: &ShortUrl) -> Result<()> {
+        let link = link.clone();
+        self.blocking(move |db, _| {
+            let txn = db.begin_write().map_err(storage)?;
+            {
+                let mut links = txn.open_table(LINKS).map_err(storage)?;
+                let old: ShortUrl = links
+                    .get(link.id.as_u128())
+                    .map_err(storage)?
+                    .map(|bytes| decode(bytes.value()))
+                    .transpose()?
+                    .ok_or(DomainError::NotFound)?;
+                // The code, clicks and creation time stay as stored
+                let updated = ShortUrl {
+                    target_url: link.target_url,
+                    expires_at: link.expires_at,
+                    archived_at: link.archived_at,
+                    health_status_code: link.health_status_code,
+                    health_latency_ms: link.health_latency_ms,
+                    health_error: link.health_error,
+                    health_checked_at: link.health_checked_at,
+                    ..old.clone()
+                };
+                links
+                    .insert(updated.id.as_u128(), encode(&updated)?.as_slice())
+                    .map_err(storage)?;
+
+                // Only live links are in the expiry index
+                let mut expiry = txn.open_table(EXPIRY).map_err(storage)?;
+                if old.archived_at.is_none()
+                    && let Some(key) = expiry_key(&old)
+                {
+                    expiry.remove(key).map_err(storage)?;
+                }
+                if updated.archived_at.is_none()
+                    && let Some(key) = expiry_key(&updated)
+                {
+                    expiry.insert(key, ()).map_err(storage)?;
+                }
+                let mut archive = txn.open_table(ARCHIVED).map_err(storage)?;
+                if let Some(key) = archive_key(&old) {
+                    archive.remove(key).map_err(storage)?;
+                }
+                if let Some(key) = archive_key(&updated) {
+                    archive.insert(key, ()).map_err(storage)?;
+                }
+                record_event(&txn, LinkEventKind::Updated, &updated)?;
+            }
+            txn.commit().map_err(storage)
+        })
+        .await
+    }
+
+    async fn delete_expired_url(&self) -> Result<Vec<ShortUrl>> {
+        self.blocking(|db, _| {
+            let now = Utc::now().timestamp_millis();
+            let txn = db.begin_write().map_err(storage)?;
+            let mut deleted = Vec::new();
+            {
+                let mut expiry = txn.open_table(EXPIRY).map_err(storage)?;
+                let expired = expiry
+                    .range(..(now, 0u128))
+                    .map_err(storage)?
+                    .map(|entry| entry.map(|(key, _)| key.value()))
+                    .collect::<std::result::Result<Vec<_>, _>>()
+                    .map_err(storage)?;
+
+                let mut links = txn.open_table(LINKS).map_err(storage)?;
+                let mut codes = txn.open_table(CODES).map_err(storage)?;
+                let mut codes_lower = txn.open_multimap_table(CODES_LOWER).map_err(storage)?;
+                for key in &expired {
+                    expiry.remove(key).map_err(storage)?;
+                    let link: Option<ShortUrl> = links
+                        .remove(key.1)
+                        .map_err(storage)?
+                        .map(|bytes| decode(bytes.value()))
+                        .transpose()?;
+                    if let Some(link) = link {
+                        codes.remove(link.short_code.as_str()).map_err(storage)?;
+                        codes_lower
+                            .remove(link.short_code.to_lowercase().as_str(), key.1)
+                            .map_err(storage)?;
+                        record_event(&txn, LinkEventKind::Expired, &link)?;
+                        deleted.push(link);
+                    }
+                }
+            }
+            txn.commit().map_err(storage)?;
+            Ok(deleted)
+        })
+        .await
+    }
+
+    async fn archive_expired_url(&self) -> Result<Vec<ShortUrl>> {
+        self.blocking(|db, _| {
+            let now = Utc::now();
+            let txn = db.begin_write().map_err(storage)?;
+            let mut archived = Vec::new();
+            {
+                let mut expiry = txn.open_table(EXPIRY).map_err(storage)?;
+                let expired = expiry
+                    .range(..(now.timestamp_millis(), 0u128))
+                    .map_err(storage)?
+                    .map(|entry| entry.map(|(key, _)| key.value()))
+                    .collect::<std::result::Result<Vec<_>, _>>()
+                    .map_err(storage)?;
+
+                let mut links = txn.open_table(LINKS).map_err(storage)?;
+                let mut archive = txn.open_table(ARCHIVED).map_err(storage)?;
+                for key in &expired {
+                    expiry.remove(key).map_err(storage)?;
+                    let link: Option<ShortUrl> = links
+                        .get(key.1)
+                        .map_err(storage)?
+                        .map(|bytes| decode(bytes.value()))
+                        .transpose()?;
+                    if let Some(mut link) = link {
+                        link.archived_at = Some(now);
+                        links
+                            .insert(key.1, encode(&link)?.as_slice())
+                            .map_err(storage)?;
+                        archive
+                            .insert((now.timestamp_millis(), key.1), ())
+                            .map_err(storage)?;
+                        record_event(&txn, LinkEventKind::Expired, &link)?;
+                        archived.push(link);
+                    }
+                }
+            }
+            txn.commit().map_err(storage)?;
+            Ok(archived)
+        })
+        .await
+    }
+
+    async fn purge_archived_url(&self, before: DateTime<Utc>) -> Result<u64> {
+        self.blocking(move |db, _| {
+            let txn = db.begin_write().map_err(storage)?;
+            let mut purged = 0;
+            {
+                let mut archive = txn.open_table(ARCHIVED).map_err(storage)?;
+                let due = archive
+                    .range(..(before.timestamp_millis(), 0u128))
+                    .map_err(storage)?
+                    .map(|entry| entry.map(|(key, _)| key.value()))
+                    .collect::<std::result::Result<Vec<_>, _>>()
+                    .map_err(storage)?;
+
+                let mut links = txn.open_table(LINKS).map_err(storage)?;
+                let mut codes = txn.open_table(CODES).map_err(storage)?;
+                let mut codes_lower = txn.open_multimap_table(CODES_LOWER).map_err(storage)?;
+                for key in &due {
+                    archive.remove(key).map_err(storage)?;
+                    let link: Option<ShortUrl> = links
+                        .remove(key.1)
+                        .map_err(storage)?
+                        .map(|bytes| decode(bytes.value()))
+                        .transpose()?;
+                    if let Some(link) = link {
+                        codes.remove(link.short_code.as_str()).map_err(storage)?;
+                        codes_lower
+                            .remove(link.short_code.to_lowercase().as_str(), key.1)
+                            .map_err(storage)?;
+                        purged += 1;
+                    }
+                }
+            }
+            txn.commit().map_err(storage)?;
+            Ok(purged)
+        })
+        .await
+    }
+
+    async fn next_code_sequence(&self) -> Result<i64> {
+        self.blocking(|db, _| {
+            let txn = db.begin_write().map_err(storage)?;
+            let value;
+            {
+                let mut meta = txn.open_table(META).map_err(storage)?;
+                let current = meta.get(SEQUENCE_KEY).map_err(storage)?.map(|v| v.value());
+                value = current.unwrap_or(0) + 1;
+                meta.insert(SEQUENCE_KEY, value).map_err(storage)?;
+            }
+            txn.commit().map_err(storage)?;
+            Ok(value)
+        })
+        .await
+    }
+
+    async fn find_case_collisions(&self) -> Result<Vec<String>> {
+        self.blocking(|db, _| {
+            let txn = db.begin_read().map_err(storage)?;
+            let codes_lower = txn.open_multimap_table(CODES_LOWER).map_err(storage)?;
+            let mut collisions = Vec::new();
+            for entry in codes_lower.iter().map_err(storage)? {
+                let (code, ids) = entry.map_err(storage)?;
+                if ids.len() > 1 {
+                    collisions.push(code.value().to_string());
+                }
+            }
+            Ok(collisions)
+        })
+        .await
+    }
+
+    async fn reserve_case_insensitive_codes(&self) -> Result<u64> {
+        // Every code is already in the lowercased code table
+        Ok(0)
+    }
+
+    async fn delete_by_code(&self, code: &str) -> Result<ShortUrl> {
+        let code = code.to_string();
+        self.blocking(move |db, case_insensitive| {
+            let txn = db.begin_write().map_err(storage)?;
+            let link: ShortUrl;
+            {
+                let mut codes = txn.open_table(CODES).map_err(storage)?;
+                let mut codes_lower = txn.open_multimap_table(CODES_LOWER).map_err(storage)?;
+                let Some(id) = lookup_id(&codes, &codes_lower, &code, case_insensitive)? else {
+                    return Err(DomainError::NotFound);
+                };
+
+                link = txn
+                    .open_table(LINKS)
+                    .map_err(storage)?
+                    .remove(id)
+                    .map_err(storage)?
+                    .map(|bytes| decode(bytes.value()))
+                    .transpose()?
+                    .ok_or(DomainError::NotFound)?;
+                codes.remove(link.short_code.as_str()).map_err(storage)?;
+                codes_lower
+                    .remove(link.short_code.to_lowercase().as_str(), id)
+                    .map_err(storage)?;
+                if let Some(key) = expiry_key(&link) {
+                    txn.open_table(EXPIRY)
+                        .map_err(storage)?
+                        .remove(key)
+                        .map_err(storage)?;
+                }
+                if let Some(key) = archive_key(&link) {
+                    txn.open_table(ARCHIVED)
+                        .map_err(storage)?
+                        .remove(key)
+                        .map_err(storage)?;
+                }
+                record_event(&txn, LinkEventKind::Deleted, &link)?;
+            }
+            txn.commit().map_err(storage)?;
+            Ok(link)
+        })
+        .await
+    }
+}
+
+fn due_key(delivery: &WebhookDelivery) -> Option<(i64, u128)> {
+    (delivery.status == WebhookDelivery::PENDING).then(|| {
+        (
+            delivery.next_attempt_at.timestamp_millis(),
+            delivery.id.as_u128(),
+        )
+    })
+}
+
+/// Write a delivery and keep the due index in step with it
+fn put_delivery(
+    txn: &redb::WriteTransaction,
+    previous: Option<&WebhookDelivery>,
+    delivery: &WebhookDelivery,
+) -> Result<()> {
+    let mut due = txn.open_table(DELIVERIES_DUE).map_err(storage)?;
+    if let Some(key) = previous.and_then(due_key) {
+        due.remove(key).map_err(storage)?;
+    }
+    if let Some(key) = due_key(delivery) {
+        due.insert(key, ()).map_err(storage)?;
+    }
+    txn.open_table(DELIVERIES)
+        .map_err(storage)?
+        .insert(delivery.id.as_u128(), encode(delivery)?.as_slice())
+        .map_err(storage)?;
+    Ok(())
+}
+
+fn get_delivery(txn: &redb::WriteTransaction, id: Uuid) -> Result<Option<WebhookDelivery>> {
+    let deliveries = txn.open_table(DELIVERIES).map_err(storage)?;
+    let delivery = deliveries.get(id.as_u128()).map_err(storage)?;
+    delivery.map(|bytes| decode(bytes.value())).transpose()
+}
+
+/// Webhook subscriptions, link events and their deliveries in the same redb
+/// file as the links
+#[derive(Clone)]
+pub struct RedbWebhookRepository {
+    pub db: Arc<redb::Database>,
+}
+
+impl RedbWebhookRepository {
+    pub fn new(db: Arc<redb::Database>) -> Self {
+        Self { db }
+    }
+
+    async fn blocking<T, F>(&self, f: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&redb::Database) -> Result<T> + Send + 'static,
+    {
+        let db = self.db.clone();
+        tokio::task::spawn_blocking(move || f(&db))
+            .await
+            .map_err(|e| DomainError::Storage(e.into()))?
+    }
+}
+
+#[async_trait]
+impl WebhookRepository for RedbWebhookRepository {
+    async fn create_subscription(
+        &self,
+        url: &str,
+        secret: &str,
+        events: &[String],
+    ) -> Result<WebhookSubscription> {
+        let subscription = WebhookSubscription {
+            id: Uuid::new_v4(),
+            url: url.to_string(),
+            secret: secret.to_string(),
+            events: events.to_vec(),
+            created_at: Utc::now().with_nanosecond(0).unwrap(),
+        };
+
+        self.blocking(move |db| {
+            let txn = db.begin_write().map_err(storage)?;
+            txn.open_table(SUBSCRIPTIONS)
+                .map_err(storage)?
+                .insert(subscription.id.as_u128(), encode(&subscription)?.as_slice())
+                .map_err(storage)?;
+            txn.commit().map_err(storage)?;
+            Ok(subscription)
+        })
+        .await
+    }
+
+    async fn list_subscriptions(&self) -> Result<Vec<WebhookSubscription>> {
+        self.blocking(|db| {
+            let txn = db.begin_read().map_err(storage)?;
+            let subscriptions = txn.open_table(SUBSCRIPTIONS).map_err(storage)?;
+            let mut all: Vec<WebhookSubscription> = Vec::new();
+            for entry in subscriptions.iter().map_err(storage)? {
+                let (_, bytes) = entry.map_err(storage)?;
+                all.push(decode(bytes.value())?);
+            }
+            all.sort_by_key(|s| s.created_at);
+            Ok(all)
+        })
+        .await
+    }
+
+    async fn delete_subscription(&self, id: Uuid) -> Result<()> {
+        self.blocking(move |db| {
+            let txn = db.begin_write().map_err(storage)?;
+            {
+                let removed = txn
+                    .open_table(SUBSCRIPTIONS)
+                    .map_err(storage)?
+                    .remove(id.as_u128())
+                    .map_err(storage)?
+                    .is_some();
+                if !removed {
+                    return Err(DomainError::NotFound);
+                }
+
+                // No foreign keys here, drop its deliveries by hand
+                let mut deliveries = txn.open_table(DELIVERIES).map_err(storage)?;
+                let mut due = txn.open_table(DELIVERIES_DUE).map_err(storage)?;
+                let mut orphans = Vec::new();
+                for entry in deliveries.iter().map_err(storage)? {
+                    let (_, bytes) = entry.map_err(storage)?;
+                    let delivery: WebhookDelivery = decode(bytes.value())?;
+                    if delivery.subscription_id == id {
+                        orphans.push(delivery);
+                    }
+                }
+                for delivery in &orphans {
+                    deliveries.remove(delivery.id.as_u128()).map_err(storage)?;
+                    if let Some(key) = due_key(delivery) {
+                        due.remove(key).map_err(storage)?;
+                    }
+                }
+            }
+            txn.commit().map_err(storage)
+        })
+        .await
+    }
+
+    async fn claim_link_events(&self, limit: i64, lease: TimeDelta) -> Result<Vec<LinkEvent>> {
+        self.blocking(move |db| {
+            let now = Utc::now().timestamp_millis();
+            let held_until = (Utc::now() + lease).timestamp_millis();
+            let txn = db.begin_write().map_err(storage)?;
+            let mut claimed = Vec::new();
+            {
+                let mut events = txn.open_table(LINK_EVENTS).map_err(storage)?;
+                let mut available = Vec::new();
+                for entry in events.iter().map_err(storage)? {
+                    if available.len() >= usize::try_from(limit).unwrap_or(0) {
+                        break;
+                    }
+                    let (key, value) = entry.map_err(storage)?;
+                    let (until, bytes) = value.value();
+                    if until <= now {
+                        available.push((key.value(), bytes.to_vec()));
+                    }
+                }
+                for (key, bytes) in available {
+                    events
+                        .insert(key, (held_until, bytes.as_slice()))
+                        .map_err(storage)?;
+                    claimed.push(decode(&bytes)?);
+                }
+            }
+            txn.commit().map_err(storage)?;
+            Ok(claimed)
+        })
+        .await
+    }
+
+    async fn enqueue_event_deliveries(&self, event: &LinkEvent, payload: &str) -> Result<u64> {
+        let event = event.clone();
+        let payload = payload.to_string();
+        self.blocking(move |db| {
+            let now = Utc::now();
+            let txn = db.begin_write().map_err(storage)?;
+            let mut queued = 0;
+            {
+                let subscriptions = txn.open_table(SUBSCRIPTIONS).map_err(storage)?;
+                for entry in subscriptions.iter().map_err(storage)? {
+                    let (_, bytes) = entry.map_err(storage)?;
+                    let subscription: WebhookSubscription = decode(bytes.value())?;
+                    if !subscription.events.contains(&event.kind) {
+                        continue;
+                    }
+                    let delivery = WebhookDelivery {
+                        id: Uuid::new_v4(),
+                        subscription_id: subscription.id,
+                        event: event.kind.clone(),
+                        payload: payload.clone(),
+                        status: WebhookDelivery::PENDING.to_string(),
+                        attempts: 0,
+                        next_attempt_at: now,
+                        last_status_code: None,
+                        last_error: None,
+                        created_at: now,
+                        delivered_at: None,
+                    };
+                    put_delivery(&txn, None, &delivery)?;
+                    queued += 1;
+                }
+                txn.open_table(LINK_EVENTS)
+                    .map_err(storage)?
+                    .remove((event.occurred_at.timestamp_millis(), event.id.as_u128()))
+                    .map_err(storage)?;
+            }
+            txn.commit().map_err(storage)?;
+            Ok(queued)
+        })
+        .await
+    }
+
+    async fn claim_due_deliveries(
+        &self,
+        limit: i64,
+        lease: TimeDelta,
+    ) -> Result<Vec<WebhookDelivery>> {
+        self.blocking(move |db| {
+            let now = Utc::now();
+            let txn = db.begin_write().map_err(storage)?;
+            let mut claimed = Vec::new();
+            {
+                let due: Vec<(i64, u128)> = txn
+                    .open_table(DELIVERIES_DUE)
+                    .map_err(storage)?
+                    .range(..=(now.timestamp_millis(), u128::MAX))
+                    .map_err(storage)?
+                    .take(usize::try_from(limit).unwrap_or(0))
+                    .map(|entry| entry.map(|(key, _)| key.value()))
+                    .collect::<std::result::Result<_, _>>()
+                    .map_err(storage)?;
+
+                for (_, id) in due {
+                    let Some(previous) = get_delivery(&txn, Uuid::from_u128(id))? else {
+                        continue;
+                    };
+                    let mut delivery = previous.clone();
+                    delivery.next_attempt_at = now + lease;
+                    put_delivery(&txn, Some(&previous), &delivery)?;
+                    claimed.push(delivery);
+                }
+            }
+            txn.commit().map_err(storage)?;
+            Ok(claimed)
+        })
+        .await
+    }
+
+    async fn update_delivery(&self, delivery: &WebhookDelivery) -> Result<()> {
+        let delivery = delivery.clone();
+        self.blocking(move |db| {
+            let txn = db.begin_write().map_err(storage)?;
+            // Gone with its subscription in the meantime
+            if let Some(previous) = get_delivery(&txn, delivery.id)? {
+                put_delivery(&txn, Some(&previous), &delivery)?;
+            }
+            txn.commit().map_err(storage)
+        })
+        .await
+    }
+
+    async fn list_deliveries(
+        &self,
+        status: Option<&str>,
+        limit: i64,
+    ) -> Result<Vec<WebhookDelivery>> {
+        let status = status.map(str::to_string);
+        self.blocking(move |db| {
+            let txn = db.begin_read().map_err(storage)?;
+            let deliveries = txn.open_table(DELIVERIES).map_err(storage)?;
+            let mut matching: Vec<WebhookDelivery> = Vec::new();
+            for entry in deliveries.iter().map_err(storage)? {
+                let (_, bytes) = entry.map_err(storage)?;
+                let delivery: WebhookDelivery = decode(bytes.value())?;
+                if status.as_ref().is_none_or(|s| *s == delivery.status) {
+                    matching.push(delivery);
+                }
+            }
+            matching.sort_by_key(|d| std::cmp::Reverse(d.created_at));
+            matching.truncate(usize::try_from(limit).unwrap_or(0));
+            Ok(matching)
+        })
+        .await
+    }
+
+    async fn replay_delivery(&self, id: Uuid) -> Result<WebhookDelivery> {
+        self.blocking(move |db| {
+            let txn = db.begin_write().map_err(storage)?;
+            let previous = get_delivery(&txn, id)?.ok_or(DomainError::NotFound)?;
+            let mut delivery = previous.clone();
+            delivery.status = WebhookDelivery::PENDING.to_string();
+            delivery.attempts = 0;
+            delivery.next_attempt_at = Utc::now();
+            delivery.delivered_at = None;
+            put_delivery(&txn, Some(&previous), &delivery)?;
+            txn.commit().map_err(storage)?;
+            Ok(delivery)
+        })
+        .await
+    }
+}
+
+/// Leases in the redb file. The file is locked by one process, so this only
+/// ever elects that process, but keeps the scheduler code the same
+#[derive(Clone)]
+pub struct RedbLeaseRepository {
+    pub db: Arc<redb::Database>,
+}
+
+impl RedbLeaseRepository {
+    pub fn new(db: Arc<redb::Database>) -> Self {
+        Self { db }
+    }
+
+    async fn blocking<T, F>(&self, f: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&redb::Database) -> Result<T> + Send + 'static,
+    {
+        let db = self.db.clone();
+        tokio::task::spawn_blocking(move || f(&db))
+            .await
+            .map_err(|e| DomainError::Storage(e.into()))?
+    }
+}
+
+#[async_trait]
+impl LeaseRepository for RedbLeaseRepository {
+    async fn try_acquire(&self, name: &str, holder: &str, ttl: TimeDelta) -> Result<bool> {
+        let (name, holder) = (name.to_string(), holder.to_string());
+        self.blocking(move |db| {
+            let now = Utc::now();
+            let txn = db.begin_write().map_err(storage)?;
+            let held;
+            {
+                let mut leases = txn.open_table(LEASES).map_err(storage)?;
+                let current: Option<SchedulerLease> = leases
+                    .get(name.as_str())
+                    .map_err(storage)?
+                    .map(|bytes| decode(bytes.value()))
+                    .transpose()?;
+
+                let acquired_at = match current {
+                    Some(lease) if lease.holder == holder => Some(lease.acquired_at),
+                    Some(lease) if lease.expires_at >= now => None,
+                    _ => Some(now),
+                };
+                held = acquired_at.is_some();
+                if let Some(acquired_at) = acquired_at {
+                    let lease = SchedulerLease {
+                        name: name.clone(),
+                        holder,
+                        acquired_at,
+                        renewed_at: now,
+                        expires_at: now + ttl,
+                    };
+                    leases
+                        .insert(name.as_str(), encode(&lease)?.as_slice())
+                        .map_err(storage)?;
+                }
+            }
+            txn.commit().map_err(storage)?;
+            Ok(held)
+        })
+        .await
+    }
+
+    async fn release(&self, name: &str, holder: &str) -> Result<()> {
+        let (name, holder) = (name.to_string(), holder.to_string());
+        self.blocking(move |db| {
+            let txn = db.begin_write().map_err(storage)?;
+            {
+                let mut leases = txn.open_table(LEASES).map_err(storage)?;
+                let current: Option<SchedulerLease> = leases
+                    .get(name.as_str())
+                    .map_err(storage)?
+                    .map(|bytes| decode(bytes.value()))
+                    .transpose()?;
+                if current.is_some_and(|lease| lease.holder == holder) {
+                    leases.remove(name.as_str()).map_err(storage)?;
+                }
+            }
+            txn.commit().map_err(storage)
+        })
+        .await
+    }
+
+    async fn find_lease(&self, name: &str) -> Result<Option<SchedulerLease>> {
+        let name = name.to_string();
+        self.blocking(move |db| {
+            let txn = db.begin_read().map_err(storage)?;
+            let leases = txn.open_table(LEASES).map_err(storage)?;
+            let lease = leases.get(name.as_str()).map_err(storage)?;
+            lease.map(|bytes| decode(bytes.value())).transpose()
+        })
+        .await
+    }
+}
+
+/// API keys in the redb file
+#[derive(Clone)]
+pub struct RedbApiKeyRepository {
+    pub db: Arc<redb::Database>,
+}
+
+impl RedbApiKeyRepository {
+    pub fn new(db: Arc<redb::Database>) -> Self {
+        Self { db }
+    }
+
+    async fn blocking<T, F>(&self, f: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&redb::Database) -> Result<T> + Send + 'static,
+    {
+        let db = self.db.clone();
+        tokio::task::spawn_blocking(move || f(&db))
+            .await
+            .map_err(|e| DomainError::Storage(e.into()))?
+    }
+}
+
+#[async_trait]
+impl ApiKeyRepository for RedbApiKeyRepository {
+    async fn create_key(&self, name: &str, prefix: &str, key_hash: &str) -> Result<ApiKey> {
+        let key = ApiKey {
+            id: Uuid::new_v4(),
+            name: name.to_string(),
+            prefix: prefix.to_string(),
+            key_hash: key_hash.to_string(),
+            created_at: Utc::now().with_nanosecond(0).unwrap(),
+            revoked_at: None,
+        };
+
+        self.blocking(move |db| {
+            let txn = db.begin_write().map_err(storage)?;
+            {
+                let mut keys = txn.open_table(API_KEYS).map_err(storage)?;
+                // Unique like the SQL column, a clash means a broken generator
+                for entry in keys.iter().map_err(storage)? {
+                    let (_, bytes) = entry.map_err(storage)?;
+                    let existing: ApiKey = decode(bytes.value())?;
+                    if existing.key_hash == key.key_hash {
+                        return Err(DomainError::Conflict("API key already exists".to_string()));
+                    }
+                }
+                keys.insert(key.id.as_u128(), encode(&key)?.as_slice())
+                    .map_err(storage)?;
+            }
+            txn.commit().map_err(storage)?;
+            Ok(key)
+        })
+        .await
+    }
+
+    async fn list_keys(&self) -> Result<Vec<ApiKey>> {
+        self.blocking(|db| {
+            let txn = db.begin_read().map_err(storage)?;
+            let keys = txn.open_table(API_KEYS).map_err(storage)?;
+            let mut all: Vec<ApiKey> = Vec::new();
+            for entry in keys.iter().map_err(storage)? {
+                let (_, bytes) = entry.map_err(storage)?;
+                all.push(decode(bytes.value())?);
+            }
+            all.sort_by_key(|k| (k.created_at, k.id));
+            Ok(all)
+        })
+        .await
+    }
+
+    async fn revoke_key(&self, id: Uuid) -> Result<ApiKey> {
+        self.blocking(move |db| {
+            let txn = db.begin_write().map_err(storage)?;
+            let key;
+            {
+                let mut keys = txn.open_table(API_KEYS).map_err(storage)?;
+                let mut current: ApiKey = keys
+                    .get(id.as_u128())
+                    .map_err(storage)?
+                    .map(|bytes| decode(bytes.value()))
+                    .transpose()?
+                    .ok_or(DomainError::NotFound)?;
+                current
+                    .revoked_at
+                    .get_or_insert_with(|| Utc::now().with_nanosecond(0).unwrap());
+                keys.insert(id.as_u128(), encode(&current)?.as_slice())
+                    .map_err(storage)?;
+                key = current;
+            }
+            txn.commit().map_err(storage)?;
+            Ok(key)
+        })
+        .await
+    }
+
+    async fn find_active_key(&self, key_hash: &str) -> Result<Option<ApiKey>> {
+        let key_hash = key_hash.to_string();
+        self.blocking(move |db| {
+            let txn = db.begin_read().map_err(storage)?;
+            let keys = txn.open_table(API_KEYS).map_err(storage)?;
+            // Keys are few, a scan is cheaper than keeping a hash index
+            for entry in keys.iter().map_err(storage)? {
+                let (_, bytes) = entry.map_err(storage)?;
+                let key: ApiKey = decode(bytes.value())?;
+                if key.key_hash == key_hash && key.revoked_at.is_none() {
+                    return Ok(Some(key));
+                }
+            }
+            Ok(None)
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn store() -> (TempDir, Arc<redb::Database>) {
+        let dir = tempfile::tempdir().unwrap();
+        let db = open_store(dir.path().join("links.redb")).unwrap();
+        (dir, Arc::new(db))
+    }
+
+    /// Keys of an index table, to check it against the links
+    fn index(db: &redb::Database, table: TableDefinition<(i64, u128), ()>) -> Vec<(i64, u128)> {
+        let txn = db.begin_read().unwrap();
+        let table = txn.open_table(table).unwrap();
+        table
+            .iter()
+            .unwrap()
+            .map(|entry| entry.unwrap().0.value())
+            .collect()
+    }
+
+    fn in_minutes(minutes: i64) -> DateTime<Utc> {
+        Utc::now().with_nanosecond(0).unwrap() + TimeDelta::minutes(minutes)
+    }
+
+    #[tokio::test]
+    async fn codes_differing_in_case_conflict_when_case_insensitive() {
+        let (_dir, db) = store();
+        let repo = RedbUrlRepository::new(db.clone()).with_case_insensitive_codes(true);
+        repo.create("Promo", "https://example.com/", None)
+            .await
+            .unwrap();
+
+        let clash = repo.create("pROMO", "https://example.org/", None).await;
+        assert!(matches!(clash, Err(DomainError::Conflict(_))));
+        let found = repo.find_by_code("PROMO").await.unwrap().unwrap();
+        assert_eq!(found.short_code, "Promo");
+
+        // Both spellings are distinct codes in the default mode
+        let sensitive = RedbUrlRepository::new(db);
+        sensitive
+            .create("pROMO", "https://example.org/", None)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn deleting_a_link_frees_its_code() {
+        let (_dir, db) = store();
+        let repo = RedbUrlRepository::new(db.clone()).with_case_insensitive_codes(true);
+        repo.create("Gone", "https://example.com/", Some(in_minutes(10)))
+            .await
+            .unwrap();
+
+        let deleted = repo.delete_by_code("gONE").await.unwrap();
+        assert_eq!(deleted.short_code, "Gone");
+        assert!(index(&db, EXPIRY).is_empty());
+        assert!(repo.find_by_code("Gone").await.unwrap().is_none());
+        assert!(matches!(
+            repo.delete_by_code("Gone").await,
+            Err(DomainError::NotFound)
+        ));
+
+        repo.create("gone", "https://example.org/", None)
+            .await
+            .unwrap();
+    }
+
+    /// Same rows as `DELETE FROM short_urls WHERE expires_at < NOW()` on
+    /// Postgres
+    #[tokio::test]
+    async fn cleanup_deletes_the_rows_postgres_would() {
+        let (_dir, db) = store();
+        let repo = RedbUrlRepository::new(db.clone());
+        let expired = repo
+            .create("expired", "https://example.com/b", Some(in_minutes(-1)))
+            .await
+            .unwrap();
+        repo.create("later", "https://example.com/c", Some(in_minutes(10)))
+            .await
+            .unwrap();
+        repo.create("forever", "https://example.com/d", None)
+            .await
+            .unwrap();
+
+        let deleted = repo.delete_expired_url().await.unwrap();
+        assert_eq!(
+            deleted.iter().map(|l| l.id).collect::<Vec<_>>(),
+            vec![expired.id]
+        );
+        assert!(repo.find_by_code("expired").await.unwrap().is_none());
+        assert_eq!(index(&db, EXPIRY).len(), 1);
+        assert!(repo.delete_expired_url().await.unwrap().is_empty());
+
+        let live = repo.get_all_url(&UrlFilter::default()).await.unwrap();
+        let mut codes: Vec<_> = live.iter().map(|l| l.short_code.as_str()).collect();
+        codes.sort();
+        assert_eq!(codes, ["forever", "later"]);
+    }
+
+    #[tokio::test]
+    async fn clicks_committed_without_fsync_survive_a_flush() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("links.redb");
+        let db = Arc::new(open_store(&path).unwrap());
+        let repo = RedbUrlRepository::new(db.clone());
+        let link = repo
+            .create("abc", "https://example.com/", None)
+            .await
+            .unwrap();
+        for _ in 0..3 {
+            repo.increments_clicks(link.id).await.unwrap();
+        }
+        flush(&db).unwrap();
+        drop(repo);
+        drop(db);
+
+        let repo = RedbUrlRepository::new(Arc::new(open_store(&path).unwrap()));
+        let link = repo.find_by_code("abc").await.unwrap().unwrap();
+        assert_eq!(link.clicks, 3);
+    }
+
+    #[tokio::test]
+    async fn the_due_index_follows_delivery_state() {
+        let (_dir, db) = store();
+        let links = RedbUrlRepository::new(db.clone());
+        let webhooks = RedbWebhookRepository::new(db.clone());
+        let subscription = webhooks
+            .create_subscription(
+                "https://hooks.example.com/",
+                "secret",
+                &["link.created".to_string()],
+            )
+            .await
+            .unwrap();
+        links
+            .create("abc", "https://example.com/", None)
+            .await
+            .unwrap();
+
+        let lease = TimeDelta::seconds(60);
+        let events = webhooks.claim_link_events(10, lease).await.unwrap();
+        assert_eq!(events.len(), 1);
+        assert!(
+            webhooks
+                .claim_link_events(10, lease)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+        webhooks
+            .enqueue_event_deliveries(&events[0], "{}")
+            .await
+            .unwrap();
+        assert_eq!(index(&db, DELIVERIES_DUE).len(), 1);
+
+        // Claiming pushes the entry past the lease
+        let mut due = webhooks.claim_due_deliveries(10, lease).await.unwrap();
+        assert_eq!(index(&db, DELIVERIES_DUE), vec![due_key(&due[0]).unwrap()]);
+        assert!(
+            webhooks
+                .claim_due_deliveries(10, lease)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+
+        let mut delivery = due.remove(0);
+        delivery.status = WebhookDelivery::DELIVERED.to_string();
+        delivery.delivered_at = Some(Utc::now());
+        webhooks.update_delivery(&delivery).await.unwrap();
+        assert!(index(&db, DELIVERIES_DUE).is_empty());
+
+        let replayed = webhooks.replay_delivery(delivery.id).await.unwrap();
+        assert_eq!(
+            index(&db, DELIVERIES_DUE),
+            vec![due_key(&replayed).unwrap()]
+        );
+
+        webhooks.delete_subscription(subscription.id).await.unwrap();
+        assert!(index(&db, DELIVERIES_DUE).is_empty());
+        assert!(webhooks.list_deliveries(None, 10).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn archiving_leaves_the_expiry_index_but_keeps_the_code() {
+        let (_dir, db) = store();
+        let repo = RedbUrlRepository::new(db.clone());
+        let link = repo
+            .create("old", "https://example.com/", Some(in_minutes(-5)))
+            .await
+            .unwrap();
+
+        let archived = repo.archive_expired_url().await.unwrap();
+        assert_eq!(archived.len(), 1);
+        assert!(index(&db, EXPIRY).is_empty());
+        assert_eq!(
+            index(&db, ARCHIVED),
+            vec![archive_key(&archived[0]).unwrap()]
+        );
+
+        let clash = repo.create("old", "https://example.org/", None).await;
+        assert!(matches!(clash, Err(DomainError::Conflict(_))));
+        let found = repo.find_by_code("old").await.unwrap().unwrap();
+        assert_eq!(found.id, link.id);
+        assert!(found.archived_at.is_some());
+        // Nothing left to archive
+        assert!(repo.archive_expired_url().await.unwrap().is_empty());
+    }
+
+    /// Same rows as `expires_at < NOW() AND archived_at IS NULL` and
+    /// `archived_at < $1` on Postgres
+    #[tokio::test]
+    async fn archived_links_are_purged_like_postgres() {
+        let (_dir, db) = store();
+        let repo = RedbUrlRepository::new(db.clone());
+        repo.create("archived", "https://example.com/a", Some(in_minutes(-10)))
+            .await
+            .unwrap();
+        repo.archive_expired_url().await.unwrap();
+        // Archived links are left to the retention purge
+        assert!(repo.delete_expired_url().await.unwrap().is_empty());
+
+        assert_eq!(repo.purge_archived_url(in_minutes(-60)).await.unwrap(), 0);
+        assert_eq!(repo.purge_archived_url(in_minutes(1)).await.unwrap(), 1);
+        assert!(index(&db, ARCHIVED).is_empty());
+        assert!(repo.find_by_code("archived").await.unwrap().is_none());
+        repo.create("archived", "https://example.org/", None)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn updating_the_expiry_moves_its_index_entry() {
+        let (_dir, db) = store();
+        let repo = RedbUrlRepository::new(db.clone());
+        let mut link = repo
+            .create("abc", "https://example.com/", Some(in_minutes(10)))
+            .await
+            .unwrap();
+        assert_eq!(index(&db, EXPIRY), vec![expiry_key(&link).unwrap()]);
+
+        link.expires_at = Some(in_minutes(60));
+        repo.update_url(&link).await.unwrap();
+        assert_eq!(index(&db, EXPIRY), vec![expiry_key(&link).unwrap()]);
+
+        link.expires_at = None;
+        repo.update_url(&link).await.unwrap();
+        assert!(index(&db, EXPIRY).is_empty());
+    }
+
+    #[tokio::test]
+    async fn only_unrevoked_keys_are_found_by_hash() {
+        let (_dir, db) = store();
+        let keys = RedbApiKeyRepository::new(db);
+        let key = keys.create_key("ci", "usk_0123", "hash").await.unwrap();
+        keys.create_key("other", "usk_4567", "other").await.unwrap();
+
+        let found = keys.find_active_key("hash").await.unwrap();
+        assert_eq!(found.map(|k| k.id), Some(key.id));
+        assert!(keys.find_active_key("unknown").await.unwrap().is_none());
+
+        keys.revoke_key(key.id).await.unwrap();
+        assert!(keys.find_active_key("hash").await.unwrap().is_none());
+        assert!(keys.find_active_key("other").await.unwrap().is_some());
+    }
+}
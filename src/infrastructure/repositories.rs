@@ -1,19 +1,61 @@
-use crate::domain::entities::ShortUrl;
-use crate::domain::repositories::UrlRepository;
-use anyhow::Result;
+use crate::domain::entities::{
+    ApiKey, LinkEvent, LinkHealth, SchedulerLease, ShortUrl, WebhookDelivery, WebhookSubscription,
+};
+use crate::domain::errors::{DomainError, DomainResult as Result};
+use crate::domain::events::LinkEventKind;
+use crate::domain::repositories::{
+    ApiKeyRepository, LeaseRepository, UrlFilter, UrlRepository, WebhookRepository,
+};
 use async_trait::async_trait;
-use chrono::{DateTime, Timelike, Utc};
-use sqlx::PgPool;
+use chrono::{DateTime, TimeDelta, Timelike, Utc};
+use sqlx::{PgConnection, PgPool};
 use uuid::Uuid;
 
+impl From<sqlx::Error> for DomainError {
+    fn from(e: sqlx::Error) -> Self {
+        match &e {
+            sqlx::Error::Database(db) if db.is_unique_violation() => {
+                DomainError::Conflict(db.message().to_string())
+            }
+            _ => DomainError::Storage(e.into()),
+        }
+    }
+}
+
+/// Record `kind` for `link` in the event outbox within the caller's
+/// transaction, unless no webhook subscribes to it
+async fn record_event(conn: &mut PgConnection, kind: LinkEventKind, link: &ShortUrl) -> Result<()> {
+    let snapshot = serde_json::to_string(link).map_err(|e| DomainError::Storage(e.into()))?;
+    sqlx::query!(
+        r#"INSERT INTO link_events (kind, link)
+        SELECT $1::text, $2::text
+        WHERE EXISTS (SELECT 1 FROM webhook_subscriptions WHERE $1 = ANY(events))"#,
+        kind.as_str(),
+        snapshot
+    )
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
 #[derive(Clone)]
 pub struct PostgresUrlRepository {
     pub pool: PgPool,
+    case_insensitive_codes: bool,
 }
 
 impl PostgresUrlRepository {
     pub fn new(pool: PgPool) -> Self {
-        Self { pool }
+        Self {
+            pool,
+            case_insensitive_codes: false,
+        }
+    }
+
+    /// Look codes up ignoring case, served by the `lower(short_code)` index
+    pub fn with_case_insensitive_codes(mut self, enabled: bool) -> Self {
+        self.case_insensitive_codes = enabled;
+        self
     }
 }
 
@@ -26,20 +68,42 @@ impl UrlRepository for PostgresUrlRepository {
         expires_at: Option<DateTime<Utc>>,
     ) -> Result<ShortUrl> {
         let created_at = Utc::now().with_nanosecond(0).unwrap();
+        let mut tx = self.pool.begin().await?;
         let record = sqlx::query_as!(ShortUrl, "INSERT INTO short_urls (short_code, target_url, created_at, expires_at) VALUES ($1, $2, $3, $4) RETURNING *", short_code, target_url, created_at, expires_at)
-            .fetch_one(&self.pool)
+            .fetch_one(&mut *tx)
+            .await?;
+        if self.case_insensitive_codes {
+            sqlx::query!(
+                "INSERT INTO case_insensitive_codes (code, link_id) VALUES (lower($1), $2)",
+                short_code,
+                record.id
+            )
+            .execute(&mut *tx)
             .await?;
+        }
+        record_event(&mut tx, LinkEventKind::Created, &record).await?;
+        tx.commit().await?;
         Ok(record)
     }
 
     async fn find_by_code(&self, code: &str) -> Result<Option<ShortUrl>> {
-        let record = sqlx::query_as!(
-            ShortUrl,
-            "SELECT * FROM short_urls WHERE short_code = $1",
-            code
-        )
-        .fetch_optional(&self.pool)
-        .await?;
+        let record = if self.case_insensitive_codes {
+            sqlx::query_as!(
+                ShortUrl,
+                "SELECT * FROM short_urls WHERE lower(short_code) = lower($1)",
+                code
+            )
+            .fetch_optional(&self.pool)
+            .await?
+        } else {
+            sqlx::query_as!(
+                ShortUrl,
+                "SELECT * FROM short_urls WHERE short_code = $1",
+                code
+            )
+            .fetch_optional(&self.pool)
+            .await?
+        };
         Ok(record)
     }
 
@@ -53,32 +117,472 @@ impl UrlRepository for PostgresUrlRepository {
         Ok(())
     }
 
-    async fn get_all_url(&self) -> Result<Vec<ShortUrl>> {
-        let records = sqlx::query_as!(ShortUrl, "SELECT * FROM short_urls ORDER BY id DESC")
-            .fetch_all(&self.pool)
-            .await?;
+    async fn get_all_url(&self, filter: &UrlFilter) -> Result<Vec<ShortUrl>> {
+        let records = sqlx::query_as!(
+            ShortUrl,
+            r#"SELECT * FROM short_urls
+            WHERE (archived_at IS NOT NULL) = $2
+              AND ($1::bool IS NULL
+               OR (health_checked_at IS NOT NULL
+                   AND (health_error IS NOT NULL OR COALESCE(health_status_code >= 400, false))) = $1)
+            ORDER BY id DESC"#,
+            filter.broken,
+            filter.archived
+        )
+        .fetch_all(&self.pool)
+        .await?;
         Ok(records)
     }
 
-    async fn delete_expired_url(&self) -> Result<u64> {
-        let records = sqlx::query!(
-            "DELETE FROM short_urls WHERE expires_at IS NOT NULL AND expires_at < NOW()"
+    async fn find_active_urls(&self) -> Result<Vec<ShortUrl>> {
+        let records = sqlx::query_as!(
+            ShortUrl,
+            "SELECT * FROM short_urls WHERE expires_at IS NULL OR expires_at > NOW()"
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(records)
+    }
+
+    async fn update_health(&self, id: Uuid, health: &LinkHealth) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        let Some(old) = sqlx::query_as!(
+            ShortUrl,
+            "SELECT * FROM short_urls WHERE id = $1 FOR UPDATE",
+            id
+        )
+        .fetch_optional(&mut *tx)
+        .await?
+        else {
+            return Ok(());
+        };
+        sqlx::query!(
+            "UPDATE short_urls SET health_status_code = $2, health_latency_ms = $3, health_error = $4, health_checked_at = $5 WHERE id = $1",
+            id,
+            health.status_code,
+            health.latency_ms,
+            health.error,
+            health.checked_at
+        )
+        .execute(&mut *tx)
+        .await?;
+        let checked = old.clone().with_health(health);
+        if checked.is_broken() != old.is_broken() {
+            record_event(&mut tx, LinkEventKind::Updated, &checked).await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn update_url(&self, link: &ShortUrl) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        let record = sqlx::query_as!(
+            ShortUrl,
+            "UPDATE short_urls SET target_url = $2, expires_at = $3, archived_at = $4, health_status_code = $5, health_latency_ms = $6, health_error = $7, health_checked_at = $8 WHERE id = $1 RETURNING *",
+            link.id,
+            link.target_url,
+            link.expires_at,
+            link.archived_at,
+            link.health_status_code,
+            link.health_latency_ms,
+            link.health_error,
+            link.health_checked_at
+        )
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or(DomainError::NotFound)?;
+        record_event(&mut tx, LinkEventKind::Updated, &record).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn delete_expired_url(&self) -> Result<Vec<ShortUrl>> {
+        let mut tx = self.pool.begin().await?;
+        let records = sqlx::query_as!(
+            ShortUrl,
+            "DELETE FROM short_urls WHERE expires_at < NOW() AND archived_at IS NULL RETURNING *"
+        )
+        .fetch_all(&mut *tx)
+        .await?;
+        for record in &records {
+            record_event(&mut tx, LinkEventKind::Expired, record).await?;
+        }
+        tx.commit().await?;
+        Ok(records)
+    }
+
+    async fn archive_expired_url(&self) -> Result<Vec<ShortUrl>> {
+        let mut tx = self.pool.begin().await?;
+        let records = sqlx::query_as!(
+            ShortUrl,
+            "UPDATE short_urls SET archived_at = NOW() WHERE expires_at < NOW() AND archived_at IS NULL RETURNING *"
+        )
+        .fetch_all(&mut *tx)
+        .await?;
+        for record in &records {
+            record_event(&mut tx, LinkEventKind::Expired, record).await?;
+        }
+        tx.commit().await?;
+        Ok(records)
+    }
+
+    async fn purge_archived_url(&self, before: DateTime<Utc>) -> Result<u64> {
+        let result = sqlx::query!("DELETE FROM short_urls WHERE archived_at < $1", before)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected())
+    }
+
+    async fn next_code_sequence(&self) -> Result<i64> {
+        let value = sqlx::query_scalar!(r#"SELECT nextval('short_code_seq') AS "value!""#)
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(value)
+    }
+
+    async fn find_case_collisions(&self) -> Result<Vec<String>> {
+        let codes = sqlx::query_scalar!(
+            r#"SELECT lower(short_code) AS "code!" FROM short_urls
+            GROUP BY lower(short_code) HAVING COUNT(*) > 1 ORDER BY 1"#
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(codes)
+    }
+
+    async fn reserve_case_insensitive_codes(&self) -> Result<u64> {
+        let result = sqlx::query!(
+            r#"INSERT INTO case_insensitive_codes (code, link_id)
+            SELECT lower(short_code), id FROM short_urls
+            ON CONFLICT DO NOTHING"#
         )
         .execute(&self.pool)
         .await?;
-        Ok(records.rows_affected())
+        Ok(result.rows_affected())
+    }
+
+    async fn delete_by_code(&self, code: &str) -> Result<ShortUrl> {
+        let mut tx = self.pool.begin().await?;
+        let record = if self.case_insensitive_codes {
+            sqlx::query_as!(
+                ShortUrl,
+                "DELETE FROM short_urls WHERE lower(short_code) = lower($1) RETURNING *",
+                code
+            )
+            .fetch_optional(&mut *tx)
+            .await?
+        } else {
+            sqlx::query_as!(
+                ShortUrl,
+                "DELETE FROM short_urls WHERE short_code = $1 RETURNING *",
+                code
+            )
+            .fetch_optional(&mut *tx)
+            .await?
+        }
+        .ok_or(DomainError::NotFound)?;
+        record_event(&mut tx, LinkEventKind::Deleted, &record).await?;
+        tx.commit().await?;
+        Ok(record)
+    }
+}
+
+#[derive(Clone)]
+pub struct PostgresWebhookRepository {
+    pub pool: PgPool,
+}
+
+impl PostgresWebhookRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl WebhookRepository for PostgresWebhookRepository {
+    async fn create_subscription(
+        &self,
+        url: &str,
+        secret: &str,
+        events: &[String],
+    ) -> Result<WebhookSubscription> {
+        let record = sqlx::query_as!(
+            WebhookSubscription,
+            "INSERT INTO webhook_subscriptions (url, secret, events) VALUES ($1, $2, $3) RETURNING *",
+            url,
+            secret,
+            events
+        )
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(record)
+    }
+
+    async fn list_subscriptions(&self) -> Result<Vec<WebhookSubscription>> {
+        let records = sqlx::query_as!(
+            WebhookSubscription,
+            "SELECT * FROM webhook_subscriptions ORDER BY created_at"
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(records)
     }
 
-    async fn delete_by_code(&self, code: &str) -> Result<(), anyhow::Error> {
-        let rows_affected = sqlx::query("DELETE FROM short_urls WHERE short_code = $1")
-            .bind(code)
+    async fn delete_subscription(&self, id: Uuid) -> Result<()> {
+        let rows_affected = sqlx::query!("DELETE FROM webhook_subscriptions WHERE id = $1", id)
             .execute(&self.pool)
             .await?
             .rows_affected();
 
         if rows_affected == 0 {
-            anyhow::bail!("NOT_FOUND");
+            return Err(DomainError::NotFound);
         }
         Ok(())
     }
+
+    async fn claim_link_events(&self, limit: i64, lease: TimeDelta) -> Result<Vec<LinkEvent>> {
+        let available_at = Utc::now() + lease;
+        let records = sqlx::query!(
+            r#"UPDATE link_events SET available_at = $2
+            WHERE id IN (
+                SELECT id FROM link_events
+                WHERE available_at <= NOW()
+                ORDER BY occurred_at
+                LIMIT $1
+                FOR UPDATE SKIP LOCKED
+            )
+            RETURNING id, kind, link, occurred_at"#,
+            limit,
+            available_at
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut events = records
+            .into_iter()
+            .map(|r| {
+                Ok(LinkEvent {
+                    id: r.id,
+                    kind: r.kind,
+                    link: serde_json::from_str(&r.link)
+                        .map_err(|e| DomainError::Storage(e.into()))?,
+                    occurred_at: r.occurred_at,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        events.sort_by_key(|e| e.occurred_at);
+        Ok(events)
+    }
+
+    async fn enqueue_event_deliveries(&self, event: &LinkEvent, payload: &str) -> Result<u64> {
+        let mut tx = self.pool.begin().await?;
+        let queued = sqlx::query!(
+            r#"INSERT INTO webhook_deliveries (subscription_id, event, payload)
+            SELECT id, $1, $2 FROM webhook_subscriptions WHERE $1 = ANY(events)"#,
+            event.kind,
+            payload
+        )
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+        sqlx::query!("DELETE FROM link_events WHERE id = $1", event.id)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        Ok(queued)
+    }
+
+    async fn claim_due_deliveries(
+        &self,
+        limit: i64,
+        lease: TimeDelta,
+    ) -> Result<Vec<WebhookDelivery>> {
+        let leased_until = Utc::now() + lease;
+        let records = sqlx::query_as!(
+            WebhookDelivery,
+            r#"UPDATE webhook_deliveries SET next_attempt_at = $2
+            WHERE id IN (
+                SELECT id FROM webhook_deliveries
+                WHERE status = 'pending' AND next_attempt_at <= NOW()
+                ORDER BY next_attempt_at
+                LIMIT $1
+                FOR UPDATE SKIP LOCKED
+            )
+            RETURNING *"#,
+            limit,
+            leased_until
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(records)
+    }
+
+    async fn update_delivery(&self, delivery: &WebhookDelivery) -> Result<()> {
+        sqlx::query!(
+            r#"UPDATE webhook_deliveries
+            SET status = $2, attempts = $3, next_attempt_at = $4, last_status_code = $5,
+                last_error = $6, delivered_at = $7
+            WHERE id = $1"#,
+            delivery.id,
+            delivery.status,
+            delivery.attempts,
+            delivery.next_attempt_at,
+            delivery.last_status_code,
+            delivery.last_error,
+            delivery.delivered_at
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn list_deliveries(
+        &self,
+        status: Option<&str>,
+        limit: i64,
+    ) -> Result<Vec<WebhookDelivery>> {
+        let records = sqlx::query_as!(
+            WebhookDelivery,
+            r#"SELECT * FROM webhook_deliveries
+            WHERE $1::text IS NULL OR status = $1
+            ORDER BY created_at DESC
+            LIMIT $2"#,
+            status,
+            limit
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(records)
+    }
+
+    async fn replay_delivery(&self, id: Uuid) -> Result<WebhookDelivery> {
+        let record = sqlx::query_as!(
+            WebhookDelivery,
+            r#"UPDATE webhook_deliveries
+            SET status = 'pending', attempts = 0, next_attempt_at = NOW(), delivered_at = NULL
+            WHERE id = $1
+            RETURNING *"#,
+            id
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+        record.ok_or(DomainError::NotFound)
+    }
+}
+
+#[derive(Clone)]
+pub struct PostgresLeaseRepository {
+    pub pool: PgPool,
+}
+
+impl PostgresLeaseRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl LeaseRepository for PostgresLeaseRepository {
+    async fn try_acquire(&self, name: &str, holder: &str, ttl: TimeDelta) -> Result<bool> {
+        let record = sqlx::query_scalar!(
+            r#"INSERT INTO scheduler_leases (name, holder, acquired_at, renewed_at, expires_at)
+            VALUES ($1, $2, NOW(), NOW(), NOW() + make_interval(secs => $3))
+            ON CONFLICT (name) DO UPDATE
+            SET holder = EXCLUDED.holder,
+                acquired_at = CASE WHEN scheduler_leases.holder = EXCLUDED.holder
+                                   THEN scheduler_leases.acquired_at ELSE NOW() END,
+                renewed_at = NOW(),
+                expires_at = EXCLUDED.expires_at
+            WHERE scheduler_leases.holder = EXCLUDED.holder
+               OR scheduler_leases.expires_at < NOW()
+            RETURNING holder"#,
+            name,
+            holder,
+            ttl.as_seconds_f64()
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(record.is_some())
+    }
+
+    async fn release(&self, name: &str, holder: &str) -> Result<()> {
+        sqlx::query!(
+            "DELETE FROM scheduler_leases WHERE name = $1 AND holder = $2",
+            name,
+            holder
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn find_lease(&self, name: &str) -> Result<Option<SchedulerLease>> {
+        let record = sqlx::query_as!(
+            SchedulerLease,
+            "SELECT * FROM scheduler_leases WHERE name = $1",
+            name
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(record)
+    }
+}
+
+#[derive(Clone)]
+pub struct PostgresApiKeyRepository {
+    pub pool: PgPool,
+}
+
+impl PostgresApiKeyRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl ApiKeyRepository for PostgresApiKeyRepository {
+    async fn create_key(&self, name: &str, prefix: &str, key_hash: &str) -> Result<ApiKey> {
+        let created_at = Utc::now().with_nanosecond(0).unwrap();
+        let record = sqlx::query_as!(
+            ApiKey,
+            "INSERT INTO api_keys (name, prefix, key_hash, created_at) VALUES ($1, $2, $3, $4) RETURNING *",
+            name,
+            prefix,
+            key_hash,
+            created_at
+        )
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(record)
+    }
+
+    async fn list_keys(&self) -> Result<Vec<ApiKey>> {
+        let records = sqlx::query_as!(ApiKey, "SELECT * FROM api_keys ORDER BY created_at, id")
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(records)
+    }
+
+    async fn revoke_key(&self, id: Uuid) -> Result<ApiKey> {
+        let record = sqlx::query_as!(
+            ApiKey,
+            "UPDATE api_keys SET revoked_at = COALESCE(revoked_at, $2) WHERE id = $1 RETURNING *",
+            id,
+            Utc::now().with_nanosecond(0).unwrap()
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+        record.ok_or(DomainError::NotFound)
+    }
+
+    async fn find_active_key(&self, key_hash: &str) -> Result<Option<ApiKey>> {
+        let record = sqlx::query_as!(
+            ApiKey,
+            "SELECT * FROM api_keys WHERE key_hash = $1 AND revoked_at IS NULL",
+            key_hash
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(record)
+    }
 }
@@ -1,29 +1,648 @@
-use crate::domain::repositories::UrlRepository;
-use crate::infrastructure::database::db_pool;
-use crate::infrastructure::repositories::PostgresUrlRepository;
-use std::env;
-use std::sync::Arc;
-use tokio::time::{Duration, sleep};
-
-pub fn start_cleanup_scheduler() {
-    tokio::spawn(async move {
-        let pool = db_pool().clone();
-        let repo = Arc::new(PostgresUrlRepository::new(pool));
-        let interval_secs: u64 = env::var("CLEANUP_INTERVAL_SECS")
-            .ok()
-            .and_then(|v| v.parse::<u64>().ok())
-            .unwrap_or(60);
-
-        loop {
-            match repo.delete_expired_url().await {
-                Ok(count) if count > 0 => {
-                    tracing::info!("🧹 Auto-cleaned {count} expired short URLs");
-                }
-                Ok(_) => {}
-                Err(e) => tracing::error!("Cleanup error: {:?}", e),
+use crate::config::JobConfig;
+use crate::domain::utils::utilities::serialize_option_datetime;
+use crate::infrastructure::leader::LeaderElection;
+use crate::infrastructure::metrics::Metrics;
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::{DateTime, TimeDelta, Utc};
+use rand::Rng;
+use salvo::oapi::ToSchema;
+use serde::Serialize;
+use std::any::Any;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tokio::task::JoinHandle;
+use tokio::time::{Duration, sleep, timeout};
+use tokio_util::sync::CancellationToken;
+
+/// A unit of periodic work run by the [`JobRunner`]
+#[async_trait]
+pub trait Job: Send + Sync + 'static {
+    fn name(&self) -> &'static str;
+
+    /// Whether only the scheduler leader runs it, true unless overridden
+    fn leader_only(&self) -> bool {
+        true
+    }
+
+    /// One run. `Ok(RunOutcome::More)` asks to be run again right away
+    /// instead of waiting for the schedule.
+    async fn run(&self) -> Result<RunOutcome>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RunOutcome {
+    Done,
+    /// Work is left over, e.g. a full batch was claimed
+    More,
+}
+
+/// Cron expression evaluated in UTC. Five fields (minute, hour, day of month,
+/// month, day of week) as in crontab, or six with leading seconds.
+#[derive(Debug, Clone)]
+pub struct CronSchedule {
+    expression: String,
+    schedule: Box<cron::Schedule>,
+}
+
+impl CronSchedule {
+    fn next_after(&self, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        self.schedule.after(&after).next()
+    }
+}
+
+impl FromStr for CronSchedule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let expression = s.split_whitespace().collect::<Vec<_>>().join(" ");
+        // The cron crate wants seconds first
+        let full = match expression.split(' ').count() {
+            5 => format!("0 {expression}"),
+            _ => expression.clone(),
+        };
+        let schedule = cron::Schedule::from_str(&full)
+            .map(Box::new)
+            .map_err(|e| format!("invalid cron expression {expression:?}: {e}"))?;
+        Ok(Self {
+            expression,
+            schedule,
+        })
+    }
+}
+
+impl fmt::Display for CronSchedule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.expression)
+    }
+}
+
+/// When a job runs
+#[derive(Debug, Clone)]
+pub enum Schedule {
+    /// Right away at startup, then every interval, varied by the jitter
+    Every(Duration),
+    Cron(CronSchedule),
+}
+
+impl Schedule {
+    /// First run, `None` when a cron expression never fires again
+    fn first(&self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        match self {
+            Self::Every(_) => Some(now),
+            Self::Cron(cron) => cron.next_after(now),
+        }
+    }
+
+    fn next(&self, now: DateTime<Utc>, jitter_percent: u8) -> Option<DateTime<Utc>> {
+        match self {
+            Self::Every(interval) => {
+                let wait = jittered(*interval, jitter_percent);
+                Some(now + TimeDelta::from_std(wait).unwrap_or(TimeDelta::MAX))
             }
+            Self::Cron(cron) => cron.next_after(now),
+        }
+    }
+}
+
+impl fmt::Display for Schedule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Every(interval) => write!(f, "every {}s", interval.as_secs()),
+            Self::Cron(cron) => write!(f, "cron {cron}"),
+        }
+    }
+}
+
+/// State of a job as last recorded, for the readiness check and the status
+/// endpoint
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct JobReport {
+    pub name: String,
+    /// `every 60s` or `cron <expression>`
+    pub schedule: String,
+    /// False once the job's task has stopped
+    pub alive: bool,
+    pub running: bool,
+    #[serde(serialize_with = "serialize_option_datetime")]
+    pub last_run_at: Option<DateTime<Utc>>,
+    pub last_duration_ms: Option<u64>,
+    /// `ok`, `error`, `timeout`, `panic` or `skipped` (not the leader)
+    pub last_outcome: Option<String>,
+    /// Error of the last failed run, kept until a run succeeds
+    pub last_error: Option<String>,
+    #[serde(serialize_with = "serialize_option_datetime")]
+    pub next_run_at: Option<DateTime<Utc>>,
+    pub runs: u64,
+    pub failures: u64,
+}
+
+#[derive(Debug, Default)]
+struct JobState {
+    running: bool,
+    last_run_at: Option<DateTime<Utc>>,
+    last_duration: Option<Duration>,
+    last_outcome: Option<&'static str>,
+    last_error: Option<String>,
+    next_run_at: Option<DateTime<Utc>>,
+    runs: u64,
+    failures: u64,
+}
+
+/// Liveness and run history of a background job, shared with the readiness
+/// check
+#[derive(Debug)]
+pub struct JobStatus {
+    name: &'static str,
+    schedule: String,
+    alive: AtomicBool,
+    state: Mutex<JobState>,
+}
+
+impl JobStatus {
+    fn new(name: &'static str, schedule: &Schedule) -> Arc<Self> {
+        Arc::new(Self {
+            name,
+            schedule: schedule.to_string(),
+            alive: AtomicBool::new(true),
+            state: Mutex::new(JobState::default()),
+        })
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// False once the task has returned or panicked
+    pub fn is_alive(&self) -> bool {
+        self.alive.load(Ordering::Relaxed)
+    }
+
+    /// Start of the last run, skipped runs aside
+    pub fn last_run_at(&self) -> Option<DateTime<Utc>> {
+        self.state().last_run_at
+    }
+
+    pub fn last_error(&self) -> Option<String> {
+        self.state().last_error.clone()
+    }
+
+    pub fn report(&self) -> JobReport {
+        let state = self.state();
+        JobReport {
+            name: self.name.to_string(),
+            schedule: self.schedule.clone(),
+            alive: self.is_alive(),
+            running: state.running,
+            last_run_at: state.last_run_at,
+            last_duration_ms: state.last_duration.map(|d| d.as_millis() as u64),
+            last_outcome: state.last_outcome.map(str::to_string),
+            last_error: state.last_error.clone(),
+            next_run_at: state.next_run_at,
+            runs: state.runs,
+            failures: state.failures,
+        }
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, JobState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Marks the job dead when its task ends, including by panic
+struct AliveGuard(Arc<JobStatus>);
+
+impl Drop for AliveGuard {
+    fn drop(&mut self) {
+        self.0.alive.store(false, Ordering::Relaxed);
+    }
+}
+
+/// A spawned job loop
+pub struct BackgroundJob {
+    pub status: Arc<JobStatus>,
+    pub handle: JoinHandle<()>,
+}
 
-            sleep(Duration::from_secs(interval_secs)).await;
+/// `interval` varied randomly by up to `percent` either way
+fn jittered(interval: Duration, percent: u8) -> Duration {
+    if percent == 0 {
+        return interval;
+    }
+    let spread = f64::from(percent) / 100.0;
+    interval.mul_f64(rand::thread_rng().gen_range(1.0 - spread..=1.0 + spread))
+}
+
+struct Registration {
+    job: Arc<dyn Job>,
+    schedule: Schedule,
+    timeout: Option<Duration>,
+}
+
+/// Runs registered jobs on their schedule until shutdown. Each run is its own
+/// task, so a panic or a timeout fails that run only and the job carries on
+/// at its next slot. A run in progress at shutdown is finished first.
+pub struct JobRunner {
+    leader: Arc<LeaderElection>,
+    metrics: Arc<Metrics>,
+    jitter_percent: u8,
+    jobs: Vec<Registration>,
+}
+
+impl JobRunner {
+    pub fn new(leader: Arc<LeaderElection>, metrics: Arc<Metrics>, jitter_percent: u8) -> Self {
+        Self {
+            leader,
+            metrics,
+            jitter_percent,
+            jobs: Vec::new(),
+        }
+    }
+
+    /// Add a job running `every` interval unless `config` gives a cron
+    /// schedule. Disabled jobs are left out.
+    pub fn register(&mut self, job: impl Job, every: Duration, config: &JobConfig) {
+        if !config.enabled {
+            tracing::info!("Job {} disabled", job.name());
+            return;
         }
-    });
+        let schedule = match &config.schedule {
+            Some(cron) => Schedule::Cron(cron.clone()),
+            None => Schedule::Every(every),
+        };
+        self.register_with(job, schedule, config.timeout());
+    }
+
+    /// Add a job that is not configurable, always enabled
+    pub fn register_with(&mut self, job: impl Job, schedule: Schedule, timeout: Option<Duration>) {
+        self.jobs.push(Registration {
+            job: Arc::new(job),
+            schedule,
+            timeout,
+        });
+    }
+
+    /// Spawn a loop per registered job
+    pub fn start(self, shutdown: CancellationToken) -> Vec<BackgroundJob> {
+        self.jobs
+            .into_iter()
+            .map(|registration| {
+                let status = JobStatus::new(registration.job.name(), &registration.schedule);
+                let worker = Worker {
+                    registration,
+                    status: status.clone(),
+                    leader: self.leader.clone(),
+                    metrics: self.metrics.clone(),
+                    jitter_percent: self.jitter_percent,
+                };
+                let handle = tokio::spawn(worker.run_loop(shutdown.clone()));
+                BackgroundJob { status, handle }
+            })
+            .collect()
+    }
+}
+
+struct Worker {
+    registration: Registration,
+    status: Arc<JobStatus>,
+    leader: Arc<LeaderElection>,
+    metrics: Arc<Metrics>,
+    jitter_percent: u8,
+}
+
+impl Worker {
+    async fn run_loop(self, shutdown: CancellationToken) {
+        let guard = AliveGuard(self.status.clone());
+        let name = self.registration.job.name();
+        tracing::info!("Job {} scheduled {}", name, self.registration.schedule);
+
+        let mut next = self.registration.schedule.first(Utc::now());
+        while let Some(at) = next {
+            guard.0.state().next_run_at = Some(at);
+            let wait = (at - Utc::now()).to_std().unwrap_or_default();
+            tokio::select! {
+                biased;
+                _ = shutdown.cancelled() => break,
+                _ = sleep(wait) => {}
+            }
+
+            next = match self.run_once().await {
+                RunOutcome::More => Some(Utc::now()),
+                RunOutcome::Done => self
+                    .registration
+                    .schedule
+                    .next(Utc::now(), self.jitter_percent),
+            };
+            if next.is_none() {
+                tracing::warn!("Job {name} has no further runs scheduled");
+            }
+        }
+        guard.0.state().next_run_at = None;
+        tracing::info!("Job {name} stopped");
+    }
+
+    async fn run_once(&self) -> RunOutcome {
+        let job = self.registration.job.clone();
+        let name = job.name();
+
+        if job.leader_only() && !self.leader.is_leader() {
+            tracing::debug!("Not the scheduler leader, skipping job {name}");
+            self.status.state().last_outcome = Some("skipped");
+            self.record_outcome("skipped");
+            return RunOutcome::Done;
+        }
+
+        {
+            let mut state = self.status.state();
+            state.running = true;
+            state.next_run_at = None;
+            state.last_run_at = Some(Utc::now());
+        }
+        let started = Instant::now();
+        let task = tokio::spawn(async move { job.run().await });
+        let abort = task.abort_handle();
+
+        let result = match self.registration.timeout {
+            Some(limit) => timeout(limit, task).await.map_err(|_| limit),
+            None => Ok(task.await),
+        };
+        let elapsed = started.elapsed();
+
+        let (label, error, outcome) = match result {
+            Ok(Ok(Ok(outcome))) => ("ok", None, outcome),
+            Ok(Ok(Err(e))) => ("error", Some(format!("{e:#}")), RunOutcome::Done),
+            Ok(Err(e)) if e.is_panic() => (
+                "panic",
+                Some(format!("panicked: {}", panic_message(e.into_panic()))),
+                RunOutcome::Done,
+            ),
+            Ok(Err(e)) => ("error", Some(e.to_string()), RunOutcome::Done),
+            Err(limit) => {
+                abort.abort();
+                (
+                    "timeout",
+                    Some(format!("timed out after {}s", limit.as_secs())),
+                    RunOutcome::Done,
+                )
+            }
+        };
+
+        if let Some(error) = &error {
+            tracing::error!("Job {name} failed after {elapsed:?}: {error}");
+        }
+        {
+            let mut state = self.status.state();
+            state.running = false;
+            state.last_duration = Some(elapsed);
+            state.last_outcome = Some(label);
+            state.runs += 1;
+            if error.is_some() {
+                state.failures += 1;
+                state.last_error = error;
+            } else {
+                state.last_error = None;
+            }
+        }
+        self.record_outcome(label);
+        self.metrics
+            .job_duration
+            .with_label_values(&[name])
+            .observe(elapsed.as_secs_f64());
+        outcome
+    }
+
+    fn record_outcome(&self, outcome: &str) {
+        self.metrics
+            .job_runs
+            .with_label_values(&[self.registration.job.name(), outcome])
+            .inc();
+    }
+}
+
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    match payload.downcast::<String>() {
+        Ok(message) => *message,
+        Err(payload) => payload
+            .downcast_ref::<&str>()
+            .map(|s| s.to_string())
+            .unwrap_or_else(|| "unknown panic".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::entities::SchedulerLease;
+    use crate::domain::errors::DomainResult;
+    use crate::domain::repositories::LeaseRepository;
+    use crate::infrastructure::database::Database;
+
+    fn utc(s: &str) -> DateTime<Utc> {
+        s.parse().unwrap()
+    }
+
+    fn cron(s: &str) -> CronSchedule {
+        s.parse().unwrap()
+    }
+
+    /// Never grants the lease, the jobs below don't need it
+    struct NoLease;
+
+    #[async_trait]
+    impl LeaseRepository for NoLease {
+        async fn try_acquire(&self, _: &str, _: &str, _: TimeDelta) -> DomainResult<bool> {
+            Ok(false)
+        }
+
+        async fn release(&self, _: &str, _: &str) -> DomainResult<()> {
+            Ok(())
+        }
+
+        async fn find_lease(&self, _: &str) -> DomainResult<Option<SchedulerLease>> {
+            Ok(None)
+        }
+    }
+
+    fn runner() -> JobRunner {
+        let leader = LeaderElection::new(Arc::new(NoLease), "test".into(), TimeDelta::seconds(30));
+        let pool = sqlx::PgPool::connect_lazy("postgres://localhost/unused").unwrap();
+        let metrics = Metrics::new(Database::Postgres(pool)).unwrap();
+        JobRunner::new(Arc::new(leader), Arc::new(metrics), 0)
+    }
+
+    /// Sleeps for `sleep`, then marks itself finished or panics
+    struct TestJob {
+        sleep: Duration,
+        panics: bool,
+        finished: Arc<AtomicBool>,
+    }
+
+    #[async_trait]
+    impl Job for TestJob {
+        fn name(&self) -> &'static str {
+            "test"
+        }
+
+        fn leader_only(&self) -> bool {
+            false
+        }
+
+        async fn run(&self) -> Result<RunOutcome> {
+            sleep(self.sleep).await;
+            if self.panics {
+                panic!("boom");
+            }
+            self.finished.store(true, Ordering::Relaxed);
+            Ok(RunOutcome::Done)
+        }
+    }
+
+    /// Run `job` once under the runner and report how its first run went
+    async fn first_run(job: TestJob, limit: Option<Duration>) -> JobReport {
+        let mut runner = runner();
+        runner.register_with(job, Schedule::Every(Duration::from_secs(86_400)), limit);
+        let shutdown = CancellationToken::new();
+        let jobs = runner.start(shutdown.clone());
+        let status = jobs[0].status.clone();
+        while status.report().runs == 0 {
+            sleep(Duration::from_millis(10)).await;
+        }
+        let report = status.report();
+        shutdown.cancel();
+        for job in jobs {
+            job.handle.await.unwrap();
+        }
+        report
+    }
+
+    #[test]
+    fn five_field_expressions_get_a_zero_second() {
+        let schedule = cron("  */15   *  * * * ");
+        assert_eq!(schedule.to_string(), "*/15 * * * *");
+        assert_eq!(
+            schedule.next_after(utc("2025-01-01T00:07:30Z")),
+            Some(utc("2025-01-01T00:15:00Z"))
+        );
+        // 2025-01-01 is a Wednesday
+        assert_eq!(
+            cron("0 9 * * MON").next_after(utc("2025-01-01T00:00:00Z")),
+            Some(utc("2025-01-06T09:00:00Z"))
+        );
+    }
+
+    #[test]
+    fn six_field_expressions_start_with_seconds() {
+        let schedule = cron("30 0 3 * * *");
+        assert_eq!(
+            schedule.next_after(utc("2025-01-01T00:00:00Z")),
+            Some(utc("2025-01-01T03:00:30Z"))
+        );
+        // Strictly after, not at
+        assert_eq!(
+            schedule.next_after(utc("2025-01-01T03:00:30Z")),
+            Some(utc("2025-01-02T03:00:30Z"))
+        );
+    }
+
+    #[test]
+    fn invalid_expressions_are_refused() {
+        for raw in ["", "* * *", "61 * * * *", "* * * * * * * *", "daily"] {
+            let err = raw.parse::<CronSchedule>().unwrap_err();
+            assert!(err.starts_with("invalid cron expression"), "{raw}: {err}");
+        }
+    }
+
+    #[test]
+    fn schedules_compute_their_runs() {
+        let now = utc("2025-01-01T00:00:00Z");
+        let every = Schedule::Every(Duration::from_secs(90));
+        assert_eq!(every.first(now), Some(now));
+        assert_eq!(every.next(now, 0), Some(utc("2025-01-01T00:01:30Z")));
+        assert_eq!(every.to_string(), "every 90s");
+
+        let hourly = Schedule::Cron(cron("0 * * * *"));
+        assert_eq!(hourly.first(now), Some(utc("2025-01-01T01:00:00Z")));
+        assert_eq!(hourly.next(now, 50), Some(utc("2025-01-01T01:00:00Z")));
+        assert_eq!(hourly.to_string(), "cron 0 * * * *");
+
+        // A year field in the past never fires
+        let never = Schedule::Cron(cron("0 0 0 1 1 * 2020"));
+        assert_eq!(never.first(now), None);
+    }
+
+    #[test]
+    fn jitter_stays_within_its_percentage() {
+        let interval = Duration::from_secs(100);
+        assert_eq!(jittered(interval, 0), interval);
+        for _ in 0..1000 {
+            let wait = jittered(interval, 20);
+            assert!(
+                (Duration::from_secs(80)..=Duration::from_secs(120)).contains(&wait),
+                "{wait:?}"
+            );
+            assert!(jittered(interval, 100) <= Duration::from_secs(200));
+        }
+    }
+
+    #[test]
+    fn panic_messages_are_recovered() {
+        assert_eq!(panic_message(Box::new("static")), "static");
+        assert_eq!(panic_message(Box::new(String::from("owned"))), "owned");
+        assert_eq!(panic_message(Box::new(42)), "unknown panic");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn runs_past_their_timeout_are_aborted() {
+        let finished = Arc::new(AtomicBool::new(false));
+        let job = TestJob {
+            sleep: Duration::from_secs(60),
+            panics: false,
+            finished: finished.clone(),
+        };
+        let report = first_run(job, Some(Duration::from_secs(5))).await;
+
+        assert_eq!(report.last_outcome.as_deref(), Some("timeout"));
+        assert_eq!(report.last_error.as_deref(), Some("timed out after 5s"));
+        assert_eq!((report.runs, report.failures), (1, 1));
+        assert!(report.alive && !report.running);
+
+        // The run was aborted rather than left to finish in the background
+        sleep(Duration::from_secs(120)).await;
+        assert!(!finished.load(Ordering::Relaxed));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn panics_fail_the_run_but_not_the_job() {
+        let job = TestJob {
+            sleep: Duration::from_secs(1),
+            panics: true,
+            finished: Arc::new(AtomicBool::new(false)),
+        };
+        let report = first_run(job, None).await;
+
+        assert_eq!(report.last_outcome.as_deref(), Some("panic"));
+        assert_eq!(report.last_error.as_deref(), Some("panicked: boom"));
+        assert_eq!((report.runs, report.failures), (1, 1));
+        assert!(report.alive);
+        assert!(report.next_run_at.is_some());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn finished_runs_clear_the_error() {
+        let finished = Arc::new(AtomicBool::new(false));
+        let job = TestJob {
+            sleep: Duration::from_secs(1),
+            panics: false,
+            finished: finished.clone(),
+        };
+        let report = first_run(job, Some(Duration::from_secs(5))).await;
+
+        assert!(finished.load(Ordering::Relaxed));
+        assert_eq!(report.last_outcome.as_deref(), Some("ok"));
+        assert_eq!(report.last_error, None);
+        assert_eq!((report.runs, report.failures), (1, 0));
+    }
 }
@@ -0,0 +1,1146 @@
+use crate::domain::entities::{
+    ApiKey, LinkEvent, LinkHealth, SchedulerLease, ShortUrl, WebhookDelivery, WebhookSubscription,
+};
+use crate::domain::errors::{DomainError, DomainResult as Result};
+use crate::domain::events::LinkEventKind;
+use crate::domain::repositories::{
+    ApiKeyRepository, LeaseRepository, UrlFilter, UrlRepository, WebhookRepository,
+};
+use async_trait::async_trait;
+use chrono::{DateTime, TimeDelta, Timelike, Utc};
+use sqlx::{Sqlite, SqliteConnection, SqlitePool, Transaction};
+use uuid::Uuid;
+
+/// Row of `short_urls`, decoded at runtime since the query macros only check
+/// against Postgres
+#[derive(sqlx::FromRow)]
+struct ShortUrlRow {
+    id: Uuid,
+    short_code: String,
+    target_url: String,
+    clicks: i64,
+    created_at: DateTime<Utc>,
+    expires_at: Option<DateTime<Utc>>,
+    health_status_code: Option<i32>,
+    health_latency_ms: Option<i32>,
+    health_error: Option<String>,
+    health_checked_at: Option<DateTime<Utc>>,
+    archived_at: Option<DateTime<Utc>>,
+}
+
+impl From<ShortUrlRow> for ShortUrl {
+    fn from(row: ShortUrlRow) -> Self {
+        Self {
+            id: row.id,
+            short_code: row.short_code,
+            target_url: row.target_url,
+            clicks: row.clicks,
+            created_at: row.created_at,
+            expires_at: row.expires_at,
+            health_status_code: row.health_status_code,
+            health_latency_ms: row.health_latency_ms,
+            health_error: row.health_error,
+            health_checked_at: row.health_checked_at,
+            archived_at: row.archived_at,
+        }
+    }
+}
+
+/// Transaction holding the write lock from the start, so one that reads
+/// before writing can't fail to upgrade while another writer is busy
+async fn begin_write(pool: &SqlitePool) -> Result<Transaction<'static, Sqlite>> {
+    Ok(pool.begin_with("BEGIN IMMEDIATE").await?)
+}
+
+/// Record `kind` for `link` in the event outbox within the caller's
+/// transaction, unless no webhook subscribes to it
+async fn record_event(
+    conn: &mut SqliteConnection,
+    kind: LinkEventKind,
+    link: &ShortUrl,
+) -> Result<()> {
+    let now = Utc::now();
+    sqlx::query(
+        r#"INSERT INTO link_events (id, kind, link, occurred_at, available_at)
+        SELECT ?1, ?2, ?3, ?4, ?4
+        WHERE EXISTS (
+            SELECT 1 FROM webhook_subscriptions s, json_each(s.events) e WHERE e.value = ?2
+        )"#,
+    )
+    .bind(Uuid::new_v4())
+    .bind(kind.as_str())
+    .bind(sqlx::types::Json(link))
+    .bind(now)
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+/// `UrlRepository` on SQLite, for small deployments without a Postgres server.
+/// Timestamps are stored as RFC 3339 text and compared through `julianday`.
+#[derive(Clone)]
+pub struct SqliteUrlRepository {
+    pub pool: SqlitePool,
+    case_insensitive_codes: bool,
+}
+
+impl SqliteUrlRepository {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self {
+            pool,
+            case_insensitive_codes: false,
+        }
+    }
+
+    /// Look codes up ignoring case, served by the `lower(short_code)` index
+    pub fn with_case_insensitive_codes(mut self, enabled: bool) -> Self {
+        self.case_insensitive_codes = enabled;
+        self
+    }
+}
+
+#[async_trait]
+impl UrlRepository for SqliteUrlRepository {
+    async fn create(
+        &self,
+        short_code: &str,
+        target_url: &str,
+        expires_at: Option<DateTime<Utc>>,
+    ) -> Result<ShortUrl> {
+        let created_at = Utc::now().with_nanosecond(0).unwrap();
+        let mut tx = begin_write(&self.pool).await?;
+        let link = ShortUrl::from(
+            sqlx::query_as::<_, ShortUrlRow>(
+                "INSERT INTO short_urls (id, short_code, target_url, created_at, expires_at) VALUES (?1, ?2, ?3, ?4, ?5) RETURNING *",
+            )
+            .bind(Uuid::new_v4())
+            .bind(short_code)
+            .bind(target_url)
+            .bind(created_at)
+            .bind(expires_at)
+            .fetch_one(&mut *tx)
+            .await?,
+        );
+        if self.case_insensitive_codes {
+            sqlx::query(
+                "INSERT INTO case_insensitive_codes (code, link_id) VALUES (lower(?1), ?2)",
+            )
+            .bind(short_code)
+            .bind(link.id)
+            .execute(&mut *tx)
+            .await?;
+        }
+        record_event(&mut tx, LinkEventKind::Created, &link).await?;
+        tx.commit().await?;
+        Ok(link)
+    }
+
+    async fn find_by_code(&self, code: &str) -> Result<Option<ShortUrl>> {
+        let sql = if self.case_insensitive_codes {
+            "SELECT * FROM short_urls WHERE lower(short_code) = lower(?1)"
+        } else {
+            "SELECT * FROM short_urls WHERE short_code = ?1"
+        };
+        let row = sqlx::query_as::<_, ShortUrlRow>(sql)
+            .bind(code)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(row.map(ShortUrl::from))
+    }
+
+    async fn increments_clicks(&self, id: Uuid) -> Result<()> {
+        sqlx::query("UPDATE short_urls SET clicks = clicks + 1 WHERE id = ?1")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn get_all_url(&self, filter: &UrlFilter) -> Result<Vec<ShortUrl>> {
+        let rows = sqlx::query_as::<_, ShortUrlRow>(
+            r#"SELECT * FROM short_urls
+            WHERE (archived_at IS NOT NULL) = ?2
+              AND (?1 IS NULL
+               OR (health_checked_at IS NOT NULL
+                   AND (health_error IS NOT NULL OR COALESCE(health_status_code >= 400, 0))) = ?1)
+            ORDER BY id DESC"#,
+        )
+        .bind(filter.broken)
+        .bind(filter.archived)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows.into_iter().map(ShortUrl::from).collect())
+    }
+
+    async fn find_active_urls(&self) -> Result<Vec<ShortUrl>> {
+        let rows = sqlx::query_as::<_, ShortUrlRow>(
+            "SELECT * FROM short_urls WHERE expires_at IS NULL OR julianday(expires_at) > julianday('now')",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows.into_iter().map(ShortUrl::from).collect())
+    }
+
+    async fn update_health(&self, id: Uuid, health: &LinkHealth) -> Result<()> {
+        let mut tx = begin_write(&self.pool).await?;
+        let Some(old) = sqlx::query_as::<_, ShortUrlRow>("SELECT * FROM short_urls WHERE id = ?1")
+            .bind(id)
+            .fetch_optional(&mut *tx)
+            .await?
+            .map(ShortUrl::from)
+        else {
+            return Ok(());
+        };
+        sqlx::query(
+            "UPDATE short_urls SET health_status_code = ?2, health_latency_ms = ?3, health_error = ?4, health_checked_at = ?5 WHERE id = ?1",
+        )
+        .bind(id)
+        .bind(health.status_code)
+        .bind(health.latency_ms)
+        .bind(&health.error)
+        .bind(health.checked_at)
+        .execute(&mut *tx)
+        .await?;
+        let checked = old.clone().with_health(health);
+        if checked.is_broken() != old.is_broken() {
+            record_event(&mut tx, LinkEventKind::Updated, &checked).await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn update_url(&self, link: &ShortUrl) -> Result<()> {
+        let mut tx = begin_write(&self.pool).await?;
+        let row = sqlx::query_as::<_, ShortUrlRow>(
+            "UPDATE short_urls SET target_url = ?2, expires_at = ?3, archived_at = ?4, health_status_code = ?5, health_latency_ms = ?6, health_error = ?7, health_checked_at = ?8 WHERE id = ?1 RETURNING *",
+        )
+        .bind(link.id)
+        .bind(&link.target_url)
+        .bind(link.expires_at)
+        .bind(link.archived_at)
+        .bind(link.health_status_code)
+        .bind(link.health_latency_ms)
+        .bind(&link.health_error)
+        .bind(link.health_checked_at)
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or(DomainError::NotFound)?;
+        record_event(&mut tx, LinkEventKind::Updated, &ShortUrl::from(row)).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn delete_expired_url(&self) -> Result<Vec<ShortUrl>> {
+        let mut tx = begin_write(&self.pool).await?;
+        let links: Vec<ShortUrl> = sqlx::query_as::<_, ShortUrlRow>(
+            "DELETE FROM short_urls WHERE julianday(expires_at) < julianday('now') AND archived_at IS NULL RETURNING *",
+        )
+        .fetch_all(&mut *tx)
+        .await?
+        .into_iter()
+        .map(ShortUrl::from)
+        .collect();
+        for link in &links {
+            record_event(&mut tx, LinkEventKind::Expired, link).await?;
+        }
+        tx.commit().await?;
+        Ok(links)
+    }
+
+    async fn archive_expired_url(&self) -> Result<Vec<ShortUrl>> {
+        let mut tx = begin_write(&self.pool).await?;
+        let links: Vec<ShortUrl> = sqlx::query_as::<_, ShortUrlRow>(
+            "UPDATE short_urls SET archived_at = ?1 WHERE julianday(expires_at) < julianday('now') AND archived_at IS NULL RETURNING *",
+        )
+        .bind(Utc::now())
+        .fetch_all(&mut *tx)
+        .await?
+        .into_iter()
+        .map(ShortUrl::from)
+        .collect();
+        for link in &links {
+            record_event(&mut tx, LinkEventKind::Expired, link).await?;
+        }
+        tx.commit().await?;
+        Ok(links)
+    }
+
+    async fn purge_archived_url(&self, before: DateTime<Utc>) -> Result<u64> {
+        let result =
+            sqlx::query("DELETE FROM short_urls WHERE julianday(archived_at) < julianday(?1)")
+                .bind(before)
+                .execute(&self.pool)
+                .await?;
+        Ok(result.rows_affected())
+    }
+
+    async fn next_code_sequence(&self) -> Result<i64> {
+        let value = sqlx::query_scalar("INSERT INTO short_code_seq DEFAULT VALUES RETURNING value")
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(value)
+    }
+
+    async fn find_case_collisions(&self) -> Result<Vec<String>> {
+        let codes = sqlx::query_scalar(
+            "SELECT lower(short_code) FROM short_urls GROUP BY lower(short_code) HAVING COUNT(*) > 1 ORDER BY 1",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(codes)
+    }
+
+    async fn reserve_case_insensitive_codes(&self) -> Result<u64> {
+        let result = sqlx::query(
+            "INSERT OR IGNORE INTO case_insensitive_codes (code, link_id) SELECT lower(short_code), id FROM short_urls",
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected())
+    }
+
+    async fn delete_by_code(&self, code: &str) -> Result<ShortUrl> {
+        let sql = if self.case_insensitive_codes {
+            "DELETE FROM short_urls WHERE lower(short_code) = lower(?1) RETURNING *"
+        } else {
+            "DELETE FROM short_urls WHERE short_code = ?1 RETURNING *"
+        };
+        let mut tx = begin_write(&self.pool).await?;
+        let link = sqlx::query_as::<_, ShortUrlRow>(sql)
+            .bind(code)
+            .fetch_optional(&mut *tx)
+            .await?
+            .map(ShortUrl::from)
+            .ok_or(DomainError::NotFound)?;
+        record_event(&mut tx, LinkEventKind::Deleted, &link).await?;
+        tx.commit().await?;
+        Ok(link)
+    }
+}
+
+/// Row of `webhook_subscriptions`, events are a JSON array
+#[derive(sqlx::FromRow)]
+struct SubscriptionRow {
+    id: Uuid,
+    url: String,
+    secret: String,
+    events: sqlx::types::Json<Vec<String>>,
+    created_at: DateTime<Utc>,
+}
+
+impl From<SubscriptionRow> for WebhookSubscription {
+    fn from(row: SubscriptionRow) -> Self {
+        Self {
+            id: row.id,
+            url: row.url,
+            secret: row.secret,
+            events: row.events.0,
+            created_at: row.created_at,
+        }
+    }
+}
+
+/// Row of `webhook_deliveries`
+#[derive(sqlx::FromRow)]
+struct DeliveryRow {
+    id: Uuid,
+    subscription_id: Uuid,
+    event: String,
+    payload: String,
+    status: String,
+    attempts: i32,
+    next_attempt_at: DateTime<Utc>,
+    last_status_code: Option<i32>,
+    last_error: Option<String>,
+    created_at: DateTime<Utc>,
+    delivered_at: Option<DateTime<Utc>>,
+}
+
+impl From<DeliveryRow> for WebhookDelivery {
+    fn from(row: DeliveryRow) -> Self {
+        Self {
+            id: row.id,
+            subscription_id: row.subscription_id,
+            event: row.event,
+            payload: row.payload,
+            status: row.status,
+            attempts: row.attempts,
+            next_attempt_at: row.next_attempt_at,
+            last_status_code: row.last_status_code,
+            last_error: row.last_error,
+            created_at: row.created_at,
+            delivered_at: row.delivered_at,
+        }
+    }
+}
+
+/// Row of `link_events`, the link is a JSON snapshot
+#[derive(sqlx::FromRow)]
+struct LinkEventRow {
+    id: Uuid,
+    kind: String,
+    link: sqlx::types::Json<ShortUrl>,
+    occurred_at: DateTime<Utc>,
+}
+
+impl From<LinkEventRow> for LinkEvent {
+    fn from(row: LinkEventRow) -> Self {
+        Self {
+            id: row.id,
+            kind: row.kind,
+            link: row.link.0,
+            occurred_at: row.occurred_at,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct SqliteWebhookRepository {
+    pub pool: SqlitePool,
+}
+
+impl SqliteWebhookRepository {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl WebhookRepository for SqliteWebhookRepository {
+    async fn create_subscription(
+        &self,
+        url: &str,
+        secret: &str,
+        events: &[String],
+    ) -> Result<WebhookSubscription> {
+        let row = sqlx::query_as::<_, SubscriptionRow>(
+            "INSERT INTO webhook_subscriptions (id, url, secret, events, created_at) VALUES (?1, ?2, ?3, ?4, ?5) RETURNING *",
+        )
+        .bind(Uuid::new_v4())
+        .bind(url)
+        .bind(secret)
+        .bind(sqlx::types::Json(events))
+        .bind(Utc::now().with_nanosecond(0).unwrap())
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(row.into())
+    }
+
+    async fn list_subscriptions(&self) -> Result<Vec<WebhookSubscription>> {
+        let rows = sqlx::query_as::<_, SubscriptionRow>(
+            "SELECT * FROM webhook_subscriptions ORDER BY created_at",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows.into_iter().map(WebhookSubscription::from).collect())
+    }
+
+    async fn delete_subscription(&self, id: Uuid) -> Result<()> {
+        let rows_affected = sqlx::query("DELETE FROM webhook_subscriptions WHERE id = ?1")
+            .bind(id)
+            .execute(&self.pool)
+            .await?
+            .rows_affected();
+
+        if rows_affected == 0 {
+            return Err(DomainError::NotFound);
+        }
+        Ok(())
+    }
+
+    async fn claim_link_events(&self, limit: i64, lease: TimeDelta) -> Result<Vec<LinkEvent>> {
+        // A single writer at a time, the update claims the rows atomically
+        let rows = sqlx::query_as::<_, LinkEventRow>(
+            r#"UPDATE link_events SET available_at = ?2
+            WHERE id IN (
+                SELECT id FROM link_events
+                WHERE julianday(available_at) <= julianday('now')
+                ORDER BY julianday(occurred_at)
+                LIMIT ?1
+            )
+            RETURNING id, kind, link, occurred_at"#,
+        )
+        .bind(limit)
+        .bind(Utc::now() + lease)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut events: Vec<LinkEvent> = rows.into_iter().map(LinkEvent::from).collect();
+        events.sort_by_key(|e| e.occurred_at);
+        Ok(events)
+    }
+
+    async fn enqueue_event_deliveries(&self, event: &LinkEvent, payload: &str) -> Result<u64> {
+        let mut tx = begin_write(&self.pool).await?;
+        let subscribers: Vec<Uuid> = sqlx::query_scalar(
+            "SELECT s.id FROM webhook_subscriptions s, json_each(s.events) e WHERE e.value = ?1",
+        )
+        .bind(&event.kind)
+        .fetch_all(&mut *tx)
+        .await?;
+
+        let now = Utc::now();
+        for subscription_id in &subscribers {
+            sqlx::query(
+                "INSERT INTO webhook_deliveries (id, subscription_id, event, payload, next_attempt_at, created_at) VALUES (?1, ?2, ?3, ?4, ?5, ?5)",
+            )
+            .bind(Uuid::new_v4())
+            .bind(subscription_id)
+            .bind(&event.kind)
+            .bind(payload)
+            .bind(now)
+            .execute(&mut *tx)
+            .await?;
+        }
+        sqlx::query("DELETE FROM link_events WHERE id = ?1")
+            .bind(event.id)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        Ok(subscribers.len() as u64)
+    }
+
+    async fn claim_due_deliveries(
+        &self,
+        limit: i64,
+        lease: TimeDelta,
+    ) -> Result<Vec<WebhookDelivery>> {
+        // A single writer at a time, the update claims the rows atomically
+        let rows = sqlx::query_as::<_, DeliveryRow>(
+            r#"UPDATE webhook_deliveries SET next_attempt_at = ?2
+            WHERE id IN (
+                SELECT id FROM webhook_deliveries
+                WHERE status = 'pending' AND julianday(next_attempt_at) <= julianday('now')
+                ORDER BY julianday(next_attempt_at)
+                LIMIT ?1
+            )
+            RETURNING *"#,
+        )
+        .bind(limit)
+        .bind(Utc::now() + lease)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows.into_iter().map(WebhookDelivery::from).collect())
+    }
+
+    async fn update_delivery(&self, delivery: &WebhookDelivery) -> Result<()> {
+        sqlx::query(
+            r#"UPDATE webhook_deliveries
+            SET status = ?2, attempts = ?3, next_attempt_at = ?4, last_status_code = ?5,
+                last_error = ?6, delivered_at = ?7
+            WHERE id = ?1"#,
+        )
+        .bind(delivery.id)
+        .bind(&delivery.status)
+        .bind(delivery.attempts)
+        .bind(delivery.next_attempt_at)
+        .bind(delivery.last_status_code)
+        .bind(&delivery.last_error)
+        .bind(delivery.delivered_at)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn list_deliveries(
+        &self,
+        status: Option<&str>,
+        limit: i64,
+    ) -> Result<Vec<WebhookDelivery>> {
+        let rows = sqlx::query_as::<_, DeliveryRow>(
+            r#"SELECT * FROM webhook_deliveries
+            WHERE ?1 IS NULL OR status = ?1
+            ORDER BY julianday(created_at) DESC
+            LIMIT ?2"#,
+        )
+        .bind(status)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows.into_iter().map(WebhookDelivery::from).collect())
+    }
+
+    async fn replay_delivery(&self, id: Uuid) -> Result<WebhookDelivery> {
+        let row = sqlx::query_as::<_, DeliveryRow>(
+            r#"UPDATE webhook_deliveries
+            SET status = 'pending', attempts = 0, next_attempt_at = ?2, delivered_at = NULL
+            WHERE id = ?1
+            RETURNING *"#,
+        )
+        .bind(id)
+        .bind(Utc::now())
+        .fetch_optional(&self.pool)
+        .await?;
+        row.map(WebhookDelivery::from).ok_or(DomainError::NotFound)
+    }
+}
+
+#[derive(sqlx::FromRow)]
+struct LeaseRow {
+    name: String,
+    holder: String,
+    acquired_at: DateTime<Utc>,
+    renewed_at: DateTime<Utc>,
+    expires_at: DateTime<Utc>,
+}
+
+impl From<LeaseRow> for SchedulerLease {
+    fn from(row: LeaseRow) -> Self {
+        Self {
+            name: row.name,
+            holder: row.holder,
+            acquired_at: row.acquired_at,
+            renewed_at: row.renewed_at,
+            expires_at: row.expires_at,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct SqliteLeaseRepository {
+    pub pool: SqlitePool,
+}
+
+impl SqliteLeaseRepository {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl LeaseRepository for SqliteLeaseRepository {
+    async fn try_acquire(&self, name: &str, holder: &str, ttl: TimeDelta) -> Result<bool> {
+        let now = Utc::now();
+        let held: Option<String> = sqlx::query_scalar(
+            r#"INSERT INTO scheduler_leases (name, holder, acquired_at, renewed_at, expires_at)
+            VALUES (?1, ?2, ?3, ?3, ?4)
+            ON CONFLICT (name) DO UPDATE
+            SET holder = excluded.holder,
+                acquired_at = CASE WHEN scheduler_leases.holder = excluded.holder
+                                   THEN scheduler_leases.acquired_at ELSE excluded.acquired_at END,
+                renewed_at = excluded.renewed_at,
+                expires_at = excluded.expires_at
+            WHERE scheduler_leases.holder = excluded.holder
+               OR julianday(scheduler_leases.expires_at) < julianday(?3)
+            RETURNING holder"#,
+        )
+        .bind(name)
+        .bind(holder)
+        .bind(now)
+        .bind(now + ttl)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(held.is_some())
+    }
+
+    async fn release(&self, name: &str, holder: &str) -> Result<()> {
+        sqlx::query("DELETE FROM scheduler_leases WHERE name = ?1 AND holder = ?2")
+            .bind(name)
+            .bind(holder)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn find_lease(&self, name: &str) -> Result<Option<SchedulerLease>> {
+        let row = sqlx::query_as::<_, LeaseRow>("SELECT * FROM scheduler_leases WHERE name = ?1")
+            .bind(name)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(row.map(SchedulerLease::from))
+    }
+}
+
+#[derive(sqlx::FromRow)]
+struct ApiKeyRow {
+    id: Uuid,
+    name: String,
+    prefix: String,
+    key_hash: String,
+    created_at: DateTime<Utc>,
+    revoked_at: Option<DateTime<Utc>>,
+}
+
+impl From<ApiKeyRow> for ApiKey {
+    fn from(row: ApiKeyRow) -> Self {
+        Self {
+            id: row.id,
+            name: row.name,
+            prefix: row.prefix,
+            key_hash: row.key_hash,
+            created_at: row.created_at,
+            revoked_at: row.revoked_at,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct SqliteApiKeyRepository {
+    pub pool: SqlitePool,
+}
+
+impl SqliteApiKeyRepository {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl ApiKeyRepository for SqliteApiKeyRepository {
+    async fn create_key(&self, name: &str, prefix: &str, key_hash: &str) -> Result<ApiKey> {
+        let row = sqlx::query_as::<_, ApiKeyRow>(
+            "INSERT INTO api_keys (id, name, prefix, key_hash, created_at) VALUES (?1, ?2, ?3, ?4, ?5) RETURNING *",
+        )
+        .bind(Uuid::new_v4())
+        .bind(name)
+        .bind(prefix)
+        .bind(key_hash)
+        .bind(Utc::now().with_nanosecond(0).unwrap())
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(row.into())
+    }
+
+    async fn list_keys(&self) -> Result<Vec<ApiKey>> {
+        let rows = sqlx::query_as::<_, ApiKeyRow>(
+            "SELECT * FROM api_keys ORDER BY julianday(created_at), id",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows.into_iter().map(ApiKey::from).collect())
+    }
+
+    async fn revoke_key(&self, id: Uuid) -> Result<ApiKey> {
+        let row = sqlx::query_as::<_, ApiKeyRow>(
+            "UPDATE api_keys SET revoked_at = COALESCE(revoked_at, ?2) WHERE id = ?1 RETURNING *",
+        )
+        .bind(id)
+        .bind(Utc::now().with_nanosecond(0).unwrap())
+        .fetch_optional(&self.pool)
+        .await?;
+        row.map(ApiKey::from).ok_or(DomainError::NotFound)
+    }
+
+    async fn find_active_key(&self, key_hash: &str) -> Result<Option<ApiKey>> {
+        let row = sqlx::query_as::<_, ApiKeyRow>(
+            "SELECT * FROM api_keys WHERE key_hash = ?1 AND revoked_at IS NULL",
+        )
+        .bind(key_hash)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(row.map(ApiKey::from))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::infrastructure::migrations::SQLITE_MIGRATOR;
+    use sqlx::sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePoolOptions};
+    use std::str::FromStr;
+
+    /// One connection, an in-memory database is private to its connection
+    async fn memory_pool() -> SqlitePool {
+        let options = SqliteConnectOptions::from_str("sqlite::memory:").unwrap();
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect_with(options)
+            .await
+            .unwrap();
+        SQLITE_MIGRATOR.run(&pool).await.unwrap();
+        pool
+    }
+
+    fn in_minutes(minutes: i64) -> DateTime<Utc> {
+        Utc::now().with_nanosecond(0).unwrap() + TimeDelta::minutes(minutes)
+    }
+
+    #[tokio::test]
+    async fn taken_codes_conflict() {
+        let repo = SqliteUrlRepository::new(memory_pool().await);
+        let expires_at = in_minutes(10);
+        let link = repo
+            .create("abc", "https://example.com/", Some(expires_at))
+            .await
+            .unwrap();
+        assert_eq!(link.expires_at, Some(expires_at));
+
+        let clash = repo.create("abc", "https://example.org/", None).await;
+        assert!(matches!(clash, Err(DomainError::Conflict(_))));
+        let all = repo.get_all_url(&UrlFilter::default()).await.unwrap();
+        assert_eq!(all.len(), 1);
+        assert_eq!(all[0].target_url, "https://example.com/");
+    }
+
+    #[tokio::test]
+    async fn case_insensitive_codes_are_reserved_in_the_database() {
+        let pool = memory_pool().await;
+        let sensitive = SqliteUrlRepository::new(pool.clone());
+        for code in ["Abc", "aBc", "xyz"] {
+            sensitive
+                .create(code, "https://example.com/", None)
+                .await
+                .unwrap();
+        }
+        assert_eq!(sensitive.find_case_collisions().await.unwrap(), ["abc"]);
+        sensitive.delete_by_code("aBc").await.unwrap();
+        assert!(sensitive.find_case_collisions().await.unwrap().is_empty());
+
+        let insensitive = sensitive.clone().with_case_insensitive_codes(true);
+        let reserved = insensitive.reserve_case_insensitive_codes().await.unwrap();
+        assert_eq!(reserved, 2);
+        let reserved = insensitive.reserve_case_insensitive_codes().await.unwrap();
+        assert_eq!(reserved, 0);
+        let clash = insensitive
+            .create("ABC", "https://example.org/", None)
+            .await;
+        assert!(matches!(clash, Err(DomainError::Conflict(_))));
+
+        let found = insensitive.find_by_code("ABC").await.unwrap().unwrap();
+        assert_eq!(found.short_code, "Abc");
+        insensitive.delete_by_code("abc").await.unwrap();
+        // The reservation went with the link
+        insensitive
+            .create("ABC", "https://example.org/", None)
+            .await
+            .unwrap();
+    }
+
+    /// RFC 3339 text of different precisions compares wrongly as text,
+    /// `julianday` compares the instants
+    #[tokio::test]
+    async fn expiry_is_compared_as_time() {
+        let repo = SqliteUrlRepository::new(memory_pool().await);
+        let precise = Utc::now() - TimeDelta::milliseconds(1500);
+        repo.create("past", "https://example.com/a", Some(precise))
+            .await
+            .unwrap();
+        repo.create("soon", "https://example.com/b", Some(in_minutes(1)))
+            .await
+            .unwrap();
+        repo.create("forever", "https://example.com/c", None)
+            .await
+            .unwrap();
+
+        let mut active: Vec<_> = repo
+            .find_active_urls()
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|l| l.short_code)
+            .collect();
+        active.sort();
+        assert_eq!(active, ["forever", "soon"]);
+
+        let deleted = repo.delete_expired_url().await.unwrap();
+        assert_eq!(deleted.len(), 1);
+        assert_eq!(deleted[0].short_code, "past");
+        assert!(repo.delete_expired_url().await.unwrap().is_empty());
+        assert!(repo.find_by_code("past").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn links_are_counted_and_checked() {
+        let repo = SqliteUrlRepository::new(memory_pool().await);
+        let link = repo
+            .create("abc", "https://example.com/", None)
+            .await
+            .unwrap();
+        repo.increments_clicks(link.id).await.unwrap();
+        repo.increments_clicks(link.id).await.unwrap();
+
+        let health = LinkHealth {
+            status_code: Some(503),
+            latency_ms: 12,
+            error: None,
+            checked_at: Utc::now(),
+        };
+        repo.update_health(link.id, &health).await.unwrap();
+        let broken = |broken| UrlFilter {
+            broken: Some(broken),
+            ..Default::default()
+        };
+        assert_eq!(repo.get_all_url(&broken(true)).await.unwrap().len(), 1);
+        assert!(repo.get_all_url(&broken(false)).await.unwrap().is_empty());
+
+        let stored = repo.find_by_code("abc").await.unwrap().unwrap();
+        assert_eq!(stored.clicks, 2);
+        assert_eq!(stored.health_status_code, Some(503));
+        assert!(matches!(
+            repo.delete_by_code("nope").await,
+            Err(DomainError::NotFound)
+        ));
+        assert_eq!(repo.next_code_sequence().await.unwrap(), 1);
+        assert_eq!(repo.next_code_sequence().await.unwrap(), 2);
+    }
+
+    /// `BEGIN IMMEDIATE` makes concurrent writers queue on the busy timeout,
+    /// so a transaction reading before it writes doesn't fail to upgrade its
+    /// read lock, and a code raced for is created once
+    #[tokio::test]
+    async fn concurrent_writers_queue_instead_of_failing() {
+        let dir = tempfile::tempdir().unwrap();
+        let options = SqliteConnectOptions::from_str("sqlite:links.db")
+            .unwrap()
+            .filename(dir.path().join("links.db"))
+            .create_if_missing(true)
+            .journal_mode(SqliteJournalMode::Wal)
+            .busy_timeout(std::time::Duration::from_secs(5));
+        let pool = SqlitePoolOptions::new()
+            .max_connections(4)
+            .connect_with(options)
+            .await
+            .unwrap();
+        SQLITE_MIGRATOR.run(&pool).await.unwrap();
+        let repo = SqliteUrlRepository::new(pool).with_case_insensitive_codes(true);
+
+        let attempts = (0..8).map(|i| {
+            let repo = repo.clone();
+            let code = if i % 2 == 0 { "race" } else { "RACE" };
+            tokio::spawn(async move { repo.create(code, "https://example.com/", None).await })
+        });
+        let mut created = 0;
+        for attempt in attempts.collect::<Vec<_>>() {
+            match attempt.await.unwrap() {
+                Ok(_) => created += 1,
+                Err(DomainError::Conflict(_)) => {}
+                Err(e) => panic!("unexpected error {e}"),
+            }
+        }
+        assert_eq!(created, 1);
+
+        let link = repo.find_by_code("race").await.unwrap().unwrap();
+        let checks = (0..16).map(|i| {
+            let repo = repo.clone();
+            tokio::spawn(async move {
+                let health = LinkHealth {
+                    status_code: Some(if i % 2 == 0 { 200 } else { 500 }),
+                    latency_ms: i,
+                    error: None,
+                    checked_at: Utc::now(),
+                };
+                repo.update_health(link.id, &health).await
+            })
+        });
+        for check in checks.collect::<Vec<_>>() {
+            check.await.unwrap().unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn link_events_become_deliveries() {
+        let pool = memory_pool().await;
+        let links = SqliteUrlRepository::new(pool.clone());
+        let webhooks = SqliteWebhookRepository::new(pool);
+
+        // Nothing is recorded without a subscriber
+        links
+            .create("early", "https://example.com/", None)
+            .await
+            .unwrap();
+        let lease = TimeDelta::seconds(60);
+        assert!(
+            webhooks
+                .claim_link_events(10, lease)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+
+        let subscription = webhooks
+            .create_subscription(
+                "https://hooks.example.com/",
+                "secret",
+                &["link.created".to_string()],
+            )
+            .await
+            .unwrap();
+        assert_eq!(webhooks.list_subscriptions().await.unwrap().len(), 1);
+        links
+            .create("abc", "https://example.com/", None)
+            .await
+            .unwrap();
+        links.delete_by_code("early").await.unwrap();
+
+        let events = webhooks.claim_link_events(10, lease).await.unwrap();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].kind, "link.created");
+        assert_eq!(events[0].link.short_code, "abc");
+        // Held by the claim
+        assert!(
+            webhooks
+                .claim_link_events(10, lease)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+
+        let queued = webhooks
+            .enqueue_event_deliveries(&events[0], "{}")
+            .await
+            .unwrap();
+        assert_eq!(queued, 1);
+        let mut due = webhooks.claim_due_deliveries(10, lease).await.unwrap();
+        assert_eq!(due.len(), 1);
+        assert_eq!(due[0].subscription_id, subscription.id);
+        assert!(
+            webhooks
+                .claim_due_deliveries(10, lease)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+
+        let mut delivery = due.remove(0);
+        delivery.status = WebhookDelivery::DELIVERED.to_string();
+        delivery.attempts = 1;
+        delivery.last_status_code = Some(200);
+        delivery.delivered_at = Some(Utc::now());
+        webhooks.update_delivery(&delivery).await.unwrap();
+        let delivered = webhooks
+            .list_deliveries(Some(WebhookDelivery::DELIVERED), 10)
+            .await
+            .unwrap();
+        assert_eq!(delivered.len(), 1);
+        assert_eq!(delivered[0].last_status_code, Some(200));
+        assert!(
+            webhooks
+                .list_deliveries(Some(WebhookDelivery::PENDING), 10)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+
+        let replayed = webhooks.replay_delivery(delivery.id).await.unwrap();
+        assert_eq!(replayed.status, WebhookDelivery::PENDING);
+        assert_eq!(replayed.attempts, 0);
+        assert!(replayed.delivered_at.is_none());
+        assert!(matches!(
+            webhooks.replay_delivery(Uuid::new_v4()).await,
+            Err(DomainError::NotFound)
+        ));
+
+        webhooks.delete_subscription(subscription.id).await.unwrap();
+        assert!(webhooks.list_deliveries(None, 10).await.unwrap().is_empty());
+        assert!(matches!(
+            webhooks.delete_subscription(subscription.id).await,
+            Err(DomainError::NotFound)
+        ));
+    }
+
+    #[tokio::test]
+    async fn expired_links_are_archived_then_purged() {
+        let repo = SqliteUrlRepository::new(memory_pool().await);
+        repo.create("old", "https://example.com/a", Some(in_minutes(-1)))
+            .await
+            .unwrap();
+        repo.create("soon", "https://example.com/b", Some(in_minutes(1)))
+            .await
+            .unwrap();
+
+        let archived = repo.archive_expired_url().await.unwrap();
+        assert_eq!(archived.len(), 1);
+        assert_eq!(archived[0].short_code, "old");
+        assert!(archived[0].archived_at.is_some());
+        assert!(repo.archive_expired_url().await.unwrap().is_empty());
+        // Archived links are left to the retention purge
+        assert!(repo.delete_expired_url().await.unwrap().is_empty());
+        let listed = repo
+            .get_all_url(&UrlFilter {
+                archived: true,
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        assert_eq!(listed.len(), 1);
+
+        assert_eq!(repo.purge_archived_url(in_minutes(-60)).await.unwrap(), 0);
+        assert_eq!(repo.purge_archived_url(in_minutes(1)).await.unwrap(), 1);
+        assert!(repo.find_by_code("old").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn leases_go_to_one_holder_until_they_lapse() {
+        let leases = SqliteLeaseRepository::new(memory_pool().await);
+        let ttl = TimeDelta::seconds(30);
+        assert!(leases.try_acquire("scheduler", "a", ttl).await.unwrap());
+        let first = leases.find_lease("scheduler").await.unwrap().unwrap();
+        assert!(!leases.try_acquire("scheduler", "b", ttl).await.unwrap());
+
+        // Renewal keeps the acquisition time
+        assert!(leases.try_acquire("scheduler", "a", ttl).await.unwrap());
+        let renewed = leases.find_lease("scheduler").await.unwrap().unwrap();
+        assert_eq!(renewed.holder, "a");
+        assert_eq!(renewed.acquired_at, first.acquired_at);
+        assert!(renewed.renewed_at >= first.renewed_at);
+
+        leases.release("scheduler", "b").await.unwrap();
+        assert!(leases.find_lease("scheduler").await.unwrap().is_some());
+        leases.release("scheduler", "a").await.unwrap();
+        assert!(leases.find_lease("scheduler").await.unwrap().is_none());
+
+        // A lapsed lease is taken over
+        assert!(
+            leases
+                .try_acquire("scheduler", "b", TimeDelta::seconds(-1))
+                .await
+                .unwrap()
+        );
+        assert!(leases.try_acquire("scheduler", "a", ttl).await.unwrap());
+        let taken = leases.find_lease("scheduler").await.unwrap().unwrap();
+        assert_eq!(taken.holder, "a");
+    }
+
+    #[tokio::test]
+    async fn updates_keep_the_code_and_clicks() {
+        let repo = SqliteUrlRepository::new(memory_pool().await);
+        let link = repo
+            .create("abc", "https://example.com/", None)
+            .await
+            .unwrap();
+        repo.increments_clicks(link.id).await.unwrap();
+
+        let mut changed = repo.find_by_code("abc").await.unwrap().unwrap();
+        let expires_at = in_minutes(5);
+        changed.target_url = "https://example.org/".to_string();
+        changed.expires_at = Some(expires_at);
+        changed.clicks = 0;
+        repo.update_url(&changed).await.unwrap();
+
+        let updated = repo.find_by_code("abc").await.unwrap().unwrap();
+        assert_eq!(updated.target_url, "https://example.org/");
+        assert_eq!(updated.expires_at, Some(expires_at));
+        assert_eq!(updated.clicks, 1);
+
+        changed.id = Uuid::new_v4();
+        assert!(matches!(
+            repo.update_url(&changed).await,
+            Err(DomainError::NotFound)
+        ));
+    }
+
+    #[tokio::test]
+    async fn api_keys_are_unique_and_stay_revoked() {
+        let keys = SqliteApiKeyRepository::new(memory_pool().await);
+        let key = keys.create_key("ci", "usk_0123", "hash").await.unwrap();
+        assert!(matches!(
+            keys.create_key("other", "usk_0123", "hash").await,
+            Err(DomainError::Conflict(_))
+        ));
+
+        let found = keys.find_active_key("hash").await.unwrap();
+        assert_eq!(found.map(|k| k.id), Some(key.id));
+        assert!(keys.find_active_key("other").await.unwrap().is_none());
+
+        let revoked = keys.revoke_key(key.id).await.unwrap();
+        assert!(revoked.revoked_at.is_some());
+        assert!(keys.find_active_key("hash").await.unwrap().is_none());
+        let again = keys.revoke_key(key.id).await.unwrap();
+        assert_eq!(again.revoked_at, revoked.revoked_at);
+        assert_eq!(keys.list_keys().await.unwrap().len(), 1);
+        assert!(matches!(
+            keys.revoke_key(Uuid::new_v4()).await,
+            Err(DomainError::NotFound)
+        ));
+    }
+}
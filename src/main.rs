@@ -1,38 +1,223 @@
 use dotenvy::dotenv;
-use infrastructure::database::init_db_pool;
-use presentation::routes::router;
+use salvo::catcher::Catcher;
 use salvo::prelude::*;
-use std::env;
-use tracing_subscriber;
-
-use crate::infrastructure::scheduler::start_cleanup_scheduler;
-
-mod application;
-mod domain;
-mod infrastructure;
-mod presentation;
+use salvo::server::ServerHandle;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::signal;
+use tokio_util::sync::CancellationToken;
+use url_shortener::application::api_keys::ApiKeyServiceImpl;
+use url_shortener::application::services::UrlServiceImpl;
+use url_shortener::application::webhooks::WebhookServiceImpl;
+use url_shortener::config::AppConfig;
+use url_shortener::domain::repositories::UrlRepository;
+use url_shortener::infrastructure::database::Database;
+use url_shortener::infrastructure::health_checker::HealthChecker;
+use url_shortener::infrastructure::instrumented_repository::InstrumentedUrlRepository;
+use url_shortener::infrastructure::jobs::{
+    CleanupJob, HealthCheckJob, LeaderElectionJob, WebhookDeliveryJob,
+};
+use url_shortener::infrastructure::leader::LeaderElection;
+use url_shortener::infrastructure::logging::init_tracing;
+use url_shortener::infrastructure::metrics::Metrics;
+use url_shortener::infrastructure::migrations::prepare_schema;
+use url_shortener::infrastructure::readiness::ReadinessCheck;
+use url_shortener::infrastructure::scheduler::{JobRunner, Schedule};
+use url_shortener::infrastructure::webhook_dispatcher::WebhookDispatcher;
+use url_shortener::presentation::datetime::RequestDateTime;
+use url_shortener::presentation::errors::problem_catcher;
+use url_shortener::presentation::metrics::RequestMetrics;
+use url_shortener::presentation::request_id::request_id_hoop;
+use url_shortener::presentation::routes::router;
+use url_shortener::presentation::state::AppState;
 
 #[tokio::main]
 async fn main() {
     dotenv().ok();
-    tracing_subscriber::fmt().init();
 
-    let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
-    init_db_pool(&database_url)
+    // Logging is configured too, so config errors can't go through tracing
+    let config = match AppConfig::load() {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{e}");
+            std::process::exit(1);
+        }
+    };
+    init_tracing(&config.logging);
+
+    let db = Database::connect(&config.database)
         .await
         .expect("Failed to init DB Pool");
+    tracing::info!("Using {} storage", db.backend());
+    if let Err(e) = prepare_schema(&db, config.database.run_migrations).await {
+        tracing::error!("Database schema error: {e:#}");
+        std::process::exit(1);
+    }
+    let metrics = Arc::new(Metrics::new(db.clone()).expect("Failed to register metrics"));
+    let repo = Arc::new(InstrumentedUrlRepository::new(
+        db.url_repository(config.codes.case_insensitive),
+        metrics.clone(),
+    ));
+
+    // Refuse case-insensitive codes while existing codes differ only by case
+    if config.codes.case_insensitive {
+        let collisions = repo
+            .find_case_collisions()
+            .await
+            .expect("Failed to check short codes");
+        if !collisions.is_empty() {
+            eprintln!(
+                "CASE_INSENSITIVE_CODES cannot be enabled, these codes collide ignoring case: {}",
+                collisions.join(", ")
+            );
+            std::process::exit(1);
+        }
+        repo.reserve_case_insensitive_codes()
+            .await
+            .expect("Failed to reserve short codes");
+    }
 
-    let app_port = std::env::var("APP_PORT")
-        .ok()
-        .filter(|s| !s.trim().is_empty())
-        .unwrap_or_else(|| "5800".to_string());
-    let bind_addr = format!("0.0.0.0:{}", app_port);
+    let bind_addr = format!("0.0.0.0:{}", config.server.port);
 
     let acceptor = TcpListener::new(bind_addr).bind().await;
-    let router = router();
+    let webhook_repo = db.webhook_repository();
+    let webhooks = Arc::new(WebhookServiceImpl::new(
+        webhook_repo.clone(),
+        config.targets.url_policy(),
+    ));
+    let url_service = UrlServiceImpl::new(repo.clone())
+        .with_url_policy(config.targets.url_policy())
+        .with_self_reference(config.targets.self_reference_policy())
+        .with_expiry_policy(config.expiry.expiry_policy())
+        .with_code_generator(config.codes.code_generator());
+
+    // Settled before the jobs start so the leader doesn't skip its first run
+    let leader = Arc::new(LeaderElection::new(
+        db.lease_repository(),
+        config.scheduler.instance_id(),
+        config.scheduler.lease_ttl(),
+    ));
+    if let Err(e) = leader.try_lead().await {
+        tracing::error!("Scheduler lease error: {:?}", e);
+    }
+
+    let mut runner = JobRunner::new(
+        leader.clone(),
+        metrics.clone(),
+        config.scheduler.jitter_percent,
+    );
+    let renew = leader.renew_interval();
+    runner.register_with(
+        LeaderElectionJob(leader.clone()),
+        Schedule::Every(renew),
+        Some(renew),
+    );
+    runner.register(
+        CleanupJob::new(repo.clone(), &config.cleanup),
+        config.cleanup.interval(),
+        &config.jobs.cleanup,
+    );
+    if config.health_check.enabled() {
+        let checker = HealthChecker::new(
+            config.health_check.timeout(),
+            config.health_check.concurrency,
+            config.health_check.host_delay(),
+        )
+        .expect("Failed to build the health check client")
+        .with_url_policy(config.targets.url_policy());
+        runner.register(
+            HealthCheckJob::new(repo, checker),
+            config.health_check.interval(),
+            &config.jobs.health_check,
+        );
+    } else {
+        tracing::info!("Destination health checks disabled");
+    }
+    if config.webhooks.enabled() {
+        let dispatcher = WebhookDispatcher::new(webhook_repo, metrics.clone(), &config.webhooks)
+            .expect("Failed to build the webhook client")
+            .with_url_policy(config.targets.url_policy());
+        runner.register(
+            WebhookDeliveryJob::new(webhooks.clone(), dispatcher),
+            config.webhooks.dispatch_interval(),
+            &config.jobs.webhooks,
+        );
+    } else {
+        tracing::info!("Webhook dispatching disabled");
+    }
+
+    let shutdown = CancellationToken::new();
+    let jobs = runner.start(shutdown.clone());
+    let job_statuses: Vec<_> = jobs.iter().map(|job| job.status.clone()).collect();
+
+    let readiness = ReadinessCheck::new(db.clone(), job_statuses.clone());
+    let mut state = AppState::new(
+        Arc::new(url_service),
+        webhooks,
+        leader.clone(),
+        job_statuses,
+        Arc::new(readiness),
+        metrics.clone(),
+    );
+    if config.api.require_key {
+        state = state.with_api_keys(Arc::new(ApiKeyServiceImpl::new(db.api_key_repository())));
+    } else {
+        tracing::info!("API keys not required");
+    }
+    let router = router(state);
+
+    let service = Service::new(router)
+        .hoop(request_id_hoop)
+        .hoop(RequestDateTime::new(config.api.datetime_format))
+        .hoop(RequestMetrics::new(metrics))
+        .catcher(Catcher::default().hoop(problem_catcher));
+    let server = Server::new(acceptor);
+    let timeout = config.server.shutdown_timeout();
+    tokio::spawn(stop_on_signal(server.handle(), shutdown.clone(), timeout));
+    server.serve(service).await;
+
+    // In-flight requests are drained, let the jobs finish their current run
+    // before closing the pool under them
+    shutdown.cancel();
+    let finished = tokio::time::timeout(timeout, async {
+        for job in jobs {
+            if let Err(e) = job.handle.await {
+                tracing::error!("Background job failed: {:?}", e);
+            }
+        }
+    })
+    .await;
+    if finished.is_err() {
+        tracing::warn!("Background jobs still running after {timeout:?}, closing anyway");
+    }
+    // Released once our jobs are done, so the next leader doesn't overlap them
+    leader.step_down().await;
+    db.close().await;
+    tracing::info!("Shutdown complete");
+}
+
+/// On SIGTERM or Ctrl-C stop accepting connections, give in-flight requests
+/// `timeout` to finish and tell background jobs to stop
+async fn stop_on_signal(handle: ServerHandle, shutdown: CancellationToken, timeout: Duration) {
+    let ctrl_c = async {
+        signal::ctrl_c().await.expect("Failed to listen for Ctrl-C");
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        signal::unix::signal(signal::unix::SignalKind::terminate())
+            .expect("Failed to listen for SIGTERM")
+            .recv()
+            .await;
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
 
-    start_cleanup_scheduler();
+    tokio::select! {
+        _ = ctrl_c => tracing::info!("Received Ctrl-C, shutting down"),
+        _ = terminate => tracing::info!("Received SIGTERM, shutting down"),
+    }
 
-    println!("{:?}", router);
-    Server::new(acceptor).serve(router).await;
+    shutdown.cancel();
+    handle.stop_graceful(timeout);
 }
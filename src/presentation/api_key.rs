@@ -0,0 +1,179 @@
+use crate::application::api_keys::ApiKeyService;
+use crate::presentation::errors::{ApiError, render_error};
+use salvo::http::header::{AUTHORIZATION, HeaderValue, WWW_AUTHENTICATE};
+use salvo::prelude::*;
+use std::sync::Arc;
+
+pub const API_KEY_HEADER: &str = "x-api-key";
+
+/// Router hoop letting through only requests carrying an unrevoked API key,
+/// as `Authorization: Bearer <key>` or `X-Api-Key`
+pub struct RequireApiKey {
+    keys: Arc<dyn ApiKeyService>,
+}
+
+impl RequireApiKey {
+    pub fn new(keys: Arc<dyn ApiKeyService>) -> Self {
+        Self { keys }
+    }
+}
+
+/// Key sent with the request, the bearer token first
+fn presented_key(req: &Request) -> Option<String> {
+    let bearer = req.header::<String>(AUTHORIZATION).and_then(|value| {
+        let (scheme, token) = value.trim().split_once(' ')?;
+        scheme
+            .eq_ignore_ascii_case("bearer")
+            .then(|| token.trim().to_string())
+    });
+    bearer.or_else(|| req.header::<String>(API_KEY_HEADER))
+}
+
+#[handler]
+impl RequireApiKey {
+    async fn handle(
+        &self,
+        req: &mut Request,
+        depot: &mut Depot,
+        res: &mut Response,
+        ctrl: &mut FlowCtrl,
+    ) {
+        let err = match presented_key(req) {
+            None => ApiError::new(
+                StatusCode::UNAUTHORIZED,
+                "missing_api_key",
+                "an API key is required",
+            ),
+            Some(secret) => match self.keys.authenticate(&secret).await {
+                Ok(Some(key)) => {
+                    tracing::debug!("Request authenticated by API key {}", key.id);
+                    ctrl.call_next(req, depot, res).await;
+                    return;
+                }
+                Ok(None) => ApiError::new(
+                    StatusCode::UNAUTHORIZED,
+                    "invalid_api_key",
+                    "the API key is unknown or revoked",
+                ),
+                Err(e) => e.into(),
+            },
+        };
+        render_error(req, res, err);
+        if res.status_code == Some(StatusCode::UNAUTHORIZED) {
+            res.headers_mut()
+                .insert(WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"));
+        }
+        ctrl.skip_rest();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::application::dtos::ApiKeyResponse;
+    use crate::domain::entities::ApiKey;
+    use crate::domain::errors::DomainResult;
+    use async_trait::async_trait;
+    use chrono::Utc;
+    use salvo::test::{ResponseExt, TestClient};
+    use uuid::Uuid;
+
+    const SECRET: &str = "usk_valid";
+
+    /// Knows one key, the hoop only authenticates
+    struct OneKey;
+
+    #[async_trait]
+    impl ApiKeyService for OneKey {
+        async fn mint_key(&self, _: &str) -> DomainResult<ApiKeyResponse> {
+            unreachable!()
+        }
+
+        async fn list_keys(&self) -> DomainResult<Vec<ApiKeyResponse>> {
+            unreachable!()
+        }
+
+        async fn revoke_key(&self, _: Uuid) -> DomainResult<ApiKeyResponse> {
+            unreachable!()
+        }
+
+        async fn authenticate(&self, secret: &str) -> DomainResult<Option<ApiKey>> {
+            Ok((secret == SECRET).then(|| ApiKey {
+                id: Uuid::nil(),
+                name: "ci".to_string(),
+                prefix: SECRET.to_string(),
+                key_hash: String::new(),
+                created_at: Utc::now(),
+                revoked_at: None,
+            }))
+        }
+    }
+
+    #[handler]
+    async fn ok(res: &mut Response) {
+        res.render("ok");
+    }
+
+    fn service() -> Service {
+        Service::new(
+            Router::new()
+                .hoop(RequireApiKey::new(Arc::new(OneKey)))
+                .get(ok),
+        )
+    }
+
+    async fn send(headers: &[(&'static str, &str)]) -> (StatusCode, Option<String>, String) {
+        let mut req = TestClient::get("http://127.0.0.1/");
+        for (name, value) in headers {
+            req = req.add_header(*name, *value, true);
+        }
+        let mut res = req.send(&service()).await;
+        let challenge = res
+            .headers()
+            .get(WWW_AUTHENTICATE)
+            .map(|v| v.to_str().unwrap().to_string());
+        let body = res.take_string().await.unwrap();
+        (res.status_code.unwrap(), challenge, body)
+    }
+
+    #[tokio::test]
+    async fn valid_keys_are_let_through() {
+        for headers in [
+            [("authorization", "Bearer usk_valid")],
+            [("authorization", "bearer  usk_valid ")],
+            [(API_KEY_HEADER, SECRET)],
+        ] {
+            let (code, _, body) = send(&headers).await;
+            assert_eq!((code, body.as_str()), (StatusCode::OK, "ok"), "{headers:?}");
+        }
+    }
+
+    #[tokio::test]
+    async fn missing_and_unknown_keys_are_unauthorized() {
+        for (headers, expected) in [
+            (vec![], "missing_api_key"),
+            (
+                vec![("authorization", "Basic dXNlcjpwYXNz")],
+                "missing_api_key",
+            ),
+            (
+                vec![("authorization", "Bearer usk_revoked")],
+                "invalid_api_key",
+            ),
+            // The bearer token wins over X-Api-Key
+            (
+                vec![
+                    ("authorization", "Bearer usk_other"),
+                    (API_KEY_HEADER, SECRET),
+                ],
+                "invalid_api_key",
+            ),
+        ] {
+            let (code, challenge, body) = send(&headers).await;
+            assert_eq!(code, StatusCode::UNAUTHORIZED, "{headers:?}");
+            assert_eq!(challenge.as_deref(), Some("Bearer"));
+            let problem: serde_json::Value = serde_json::from_str(&body).unwrap();
+            assert_eq!(problem["code"], expected, "{headers:?}");
+        }
+    }
+}
@@ -0,0 +1,105 @@
+use crate::domain::utils::utilities::{DateTimeContext, DateTimeFormat, RequestTimezone};
+use crate::presentation::errors::{ApiError, render_error};
+use salvo::prelude::*;
+
+pub const TIMEZONE_HEADER: &str = "x-timezone";
+pub const TIMEZONE_QUERY: &str = "tz";
+
+/// Service hoop running the request in its `DateTimeContext`: naive datetimes
+/// in the body are read in the timezone named by `?tz=` or `X-Timezone`
+/// (the server's when absent), responses are written in the configured format.
+pub struct RequestDateTime {
+    format: DateTimeFormat,
+}
+
+impl RequestDateTime {
+    pub fn new(format: DateTimeFormat) -> Self {
+        Self { format }
+    }
+}
+
+#[handler]
+impl RequestDateTime {
+    async fn handle(
+        &self,
+        req: &mut Request,
+        depot: &mut Depot,
+        res: &mut Response,
+        ctrl: &mut FlowCtrl,
+    ) {
+        let (source, requested) = match req.query::<String>(TIMEZONE_QUERY) {
+            Some(tz) => (TIMEZONE_QUERY, Some(tz)),
+            None => (TIMEZONE_HEADER, req.header::<String>(TIMEZONE_HEADER)),
+        };
+        let timezone = match requested.map(|tz| tz.parse::<RequestTimezone>()) {
+            None => RequestTimezone::Server,
+            Some(Ok(timezone)) => timezone,
+            Some(Err(reason)) => {
+                let err = ApiError::bad_request("invalid_timezone", reason.clone()).with_field(
+                    source,
+                    "unknown_timezone",
+                    reason,
+                );
+                render_error(req, res, err);
+                ctrl.skip_rest();
+                return;
+            }
+        };
+
+        let context = DateTimeContext {
+            timezone,
+            format: self.format,
+        };
+        context.scope(ctrl.call_next(req, depot, res)).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::utils::utilities::format_datetime;
+    use salvo::test::{ResponseExt, TestClient};
+
+    /// Echoes a fixed instant as the request's context writes it
+    #[handler]
+    async fn echo(res: &mut Response) {
+        let dt = "2025-07-01T07:00:00Z".parse().unwrap();
+        res.render(format_datetime(&dt));
+    }
+
+    fn service() -> Service {
+        Service::new(
+            Router::new()
+                .hoop(RequestDateTime::new(DateTimeFormat::Local))
+                .get(echo),
+        )
+    }
+
+    #[tokio::test]
+    async fn the_query_wins_over_the_header() {
+        let mut res = TestClient::get("http://127.0.0.1/?tz=Asia/Tokyo")
+            .add_header(TIMEZONE_HEADER, "Europe/Paris", true)
+            .send(&service())
+            .await;
+        assert_eq!(res.take_string().await.unwrap(), "2025-07-01 16:00:00");
+
+        let mut res = TestClient::get("http://127.0.0.1/")
+            .add_header(TIMEZONE_HEADER, "Europe/Paris", true)
+            .send(&service())
+            .await;
+        assert_eq!(res.take_string().await.unwrap(), "2025-07-01 09:00:00");
+    }
+
+    #[tokio::test]
+    async fn unknown_timezones_are_a_bad_request() {
+        let mut res = TestClient::get("http://127.0.0.1/")
+            .add_header(TIMEZONE_HEADER, "Europe/Atlantis", true)
+            .send(&service())
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::BAD_REQUEST));
+        let problem: serde_json::Value = res.take_json().await.unwrap();
+        assert_eq!(problem["code"], "invalid_timezone");
+        assert_eq!(problem["errors"][0]["field"], TIMEZONE_HEADER);
+        assert_eq!(problem["errors"][0]["code"], "unknown_timezone");
+    }
+}
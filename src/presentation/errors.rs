@@ -0,0 +1,177 @@
+use crate::domain::errors::DomainError;
+use crate::domain::utils::utilities::format_datetime;
+use crate::presentation::request_id::REQUEST_ID_HEADER;
+use salvo::http::header::{CONTENT_TYPE, HeaderValue};
+use salvo::oapi::ToSchema;
+use salvo::prelude::*;
+use serde::Serialize;
+use uuid::Uuid;
+
+pub const PROBLEM_CONTENT_TYPE: &str = "application/problem+json";
+
+/// RFC 7807 problem details, the error body of every endpoint
+#[derive(Debug, Serialize, ToSchema)]
+#[salvo(schema(example = json!({
+    "type": "about:blank",
+    "title": "Bad Request",
+    "status": 400,
+    "detail": "host 10.0.0.1 is a private network address",
+    "code": "validation_failed",
+    "errors": [{
+        "field": "target_url",
+        "code": "private_address",
+        "message": "host 10.0.0.1 is a private network address"
+    }],
+    "instance": "/api/v1/shorten",
+    "request_id": "1b4e28ba-2fa1-11d2-883f-0016d3cca427"
+})))]
+pub struct Problem {
+    #[serde(rename = "type")]
+    pub problem_type: String,
+    pub title: String,
+    pub status: u16,
+    pub detail: String,
+    /// Machine-readable error code
+    pub code: String,
+    /// Field-level validation details
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<FieldError>,
+    pub instance: String,
+    pub request_id: String,
+    /// When an expired link stopped working
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expired_at: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct FieldError {
+    pub field: String,
+    pub code: String,
+    pub message: String,
+}
+
+/// An error response before the request details are filled in
+#[derive(Debug)]
+pub struct ApiError {
+    status: StatusCode,
+    code: &'static str,
+    detail: String,
+    errors: Vec<FieldError>,
+    expired_at: Option<String>,
+}
+
+impl ApiError {
+    pub fn new(status: StatusCode, code: &'static str, detail: impl Into<String>) -> Self {
+        Self {
+            status,
+            code,
+            detail: detail.into(),
+            errors: Vec::new(),
+            expired_at: None,
+        }
+    }
+
+    pub fn bad_request(code: &'static str, detail: impl Into<String>) -> Self {
+        Self::new(StatusCode::BAD_REQUEST, code, detail)
+    }
+
+    pub fn internal() -> Self {
+        Self::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "internal_error",
+            "internal server error",
+        )
+    }
+
+    pub fn with_field(mut self, field: &str, code: &str, message: impl Into<String>) -> Self {
+        self.errors.push(FieldError {
+            field: field.to_string(),
+            code: code.to_string(),
+            message: message.into(),
+        });
+        self
+    }
+
+    pub fn into_problem(self, req: &Request) -> Problem {
+        Problem {
+            problem_type: "about:blank".to_string(),
+            title: self
+                .status
+                .canonical_reason()
+                .unwrap_or_default()
+                .to_string(),
+            status: self.status.as_u16(),
+            detail: self.detail,
+            code: self.code.to_string(),
+            errors: self.errors,
+            instance: req.uri().path().to_string(),
+            request_id: request_id(req),
+            expired_at: self.expired_at,
+        }
+    }
+}
+
+/// The one place domain failures are mapped to HTTP responses
+impl From<DomainError> for ApiError {
+    fn from(e: DomainError) -> Self {
+        match e {
+            DomainError::NotFound | DomainError::ResourceNotFound(_) => {
+                Self::new(StatusCode::NOT_FOUND, "not_found", e.to_string())
+            }
+            DomainError::Expired { at } => {
+                let mut err = Self::new(StatusCode::GONE, "url_expired", "url expired");
+                err.expired_at = Some(format_datetime(&at));
+                err
+            }
+            DomainError::Conflict(msg) => Self::new(StatusCode::CONFLICT, "conflict", msg),
+            DomainError::Validation(e) => Self::bad_request("validation_failed", e.to_string())
+                .with_field("target_url", e.rule(), e.to_string()),
+            DomainError::Expiry(e) => Self::bad_request("validation_failed", e.to_string())
+                .with_field(e.field(), e.rule(), e.to_string()),
+            DomainError::Invalid {
+                field,
+                rule,
+                message,
+            } => Self::bad_request("validation_failed", message.clone())
+                .with_field(field, rule, message),
+            DomainError::Storage(e) => {
+                // Never leak storage details to clients
+                tracing::error!("storage error: {:?}", e);
+                Self::internal()
+            }
+        }
+    }
+}
+
+/// Render `err` as `application/problem+json`
+pub fn render_error(req: &Request, res: &mut Response, err: impl Into<ApiError>) {
+    let err = err.into();
+    res.status_code(err.status);
+    res.render(Json(err.into_problem(req)));
+    res.headers_mut()
+        .insert(CONTENT_TYPE, HeaderValue::from_static(PROBLEM_CONTENT_TYPE));
+}
+
+/// Turn framework errors without a body (unknown route, wrong method, ...)
+/// into problem details as well
+#[handler]
+pub async fn problem_catcher(req: &mut Request, res: &mut Response, ctrl: &mut FlowCtrl) {
+    let status = res.status_code.unwrap_or(StatusCode::NOT_FOUND);
+    if status.is_client_error() || status.is_server_error() {
+        let code = if status.is_server_error() {
+            "internal_error"
+        } else {
+            "http_error"
+        };
+        let detail = status.canonical_reason().unwrap_or_default().to_lowercase();
+        render_error(req, res, ApiError::new(status, code, detail));
+        ctrl.skip_rest();
+    }
+}
+
+/// Id assigned by `request_id_hoop`, minted here only if it did not run
+fn request_id(req: &Request) -> String {
+    req.header::<String>(REQUEST_ID_HEADER)
+        .filter(|id| !id.is_empty())
+        .unwrap_or_else(|| Uuid::new_v4().to_string())
+}
@@ -1,12 +1,44 @@
-use crate::application::dtos::CreateShortUrlRequest;
-use crate::application::services::{UrlService, UrlServiceImpl};
-use crate::domain::validators::url_validator::normalize_url;
-use crate::infrastructure::{database::db_pool, repositories::PostgresUrlRepository};
+use crate::application::dtos::{
+    CreateShortUrlRequest, CreateUrlResponse, CreateWebhookRequest, SchedulerStatusResponse,
+    WebhookDeliveryResponse, WebhookResponse,
+};
+use crate::application::webhooks::MAX_DELIVERY_PAGE;
+use crate::domain::errors::DomainError;
+use crate::domain::repositories::UrlFilter;
+use crate::domain::utils::utilities::format_datetime;
+use crate::infrastructure::readiness::ReadinessReport;
+use crate::infrastructure::scheduler::JobReport;
+use crate::presentation::errors::{ApiError, Problem, render_error};
+use crate::presentation::state::AppState;
 use salvo::http::header::{HeaderName, HeaderValue};
 use salvo::prelude::*;
 use serde_json::json;
-use std::sync::Arc;
-use tracing;
+use uuid::Uuid;
+
+fn missing_code() -> ApiError {
+    ApiError::bad_request("missing_parameter", "code param missing").with_field(
+        "code",
+        "required",
+        "code param missing",
+    )
+}
+
+/// The `{id}` path parameter, rendering a 400 when it isn't a UUID
+fn id_param(req: &mut Request, res: &mut Response) -> Option<Uuid> {
+    let raw = req.param::<String>("id").unwrap_or_default();
+    match raw.parse() {
+        Ok(id) => Some(id),
+        Err(_) => {
+            let err = ApiError::bad_request("invalid_parameter", "id must be a UUID").with_field(
+                "id",
+                "invalid_uuid",
+                "id must be a UUID",
+            );
+            render_error(req, res, err);
+            None
+        }
+    }
+}
 
 #[endpoint(
     tags("URL Shortener"),
@@ -15,46 +47,41 @@ use tracing;
     request_body(
         content = CreateShortUrlRequest,
         description = "Payload for creating a short URL"
+    ),
+    responses(
+        (status_code = 201, description = "Short URL created", body = CreateUrlResponse),
+        (status_code = 400, description = "Invalid body or rejected target URL", body = Problem, content_type = "application/problem+json"),
+        (status_code = 409, description = "No free short code", body = Problem, content_type = "application/problem+json"),
+        (status_code = 500, description = "Internal server error", body = Problem, content_type = "application/problem+json")
     )
 )]
-pub async fn create_short_handler(req: &mut Request, res: &mut Response) {
+pub async fn create_short_handler(req: &mut Request, depot: &mut Depot, res: &mut Response) {
     // Parse JSON body
-    let mut body: CreateShortUrlRequest = match req.parse_json().await {
+    let body: CreateShortUrlRequest = match req.parse_json().await {
         Ok(b) => b,
-        Err(_) => {
-            res.status_code(StatusCode::BAD_REQUEST);
-            res.render(Json(json!({"error": "invalid request body"})));
-            return;
-        }
-    };
-
-    let url = match normalize_url(&body.target_url) {
-        Ok(u) => u,
-        Err(msg) => {
-            res.status_code(StatusCode::BAD_REQUEST);
-            res.render(Json(json!({ "error": msg })));
+        Err(e) => {
+            let err = ApiError::bad_request("invalid_body", "invalid request body").with_field(
+                "body",
+                "invalid_json",
+                e.to_string(),
+            );
+            render_error(req, res, err);
             return;
         }
     };
 
-    // Normalized URL
-    body.target_url = url.to_string();
-
-    // Build repo + service from global pool (fallback approach)
-    let pool = db_pool().clone();
-    let repo = PostgresUrlRepository::new(pool);
-    let svc = UrlServiceImpl::new(Arc::new(repo));
+    let state = AppState::from_depot(depot);
 
     // Call service
-    match svc.create_short_url(body).await {
+    match state.url_service.create_short_url(body).await {
         Ok(resp) => {
+            state.metrics.links_created.inc();
             res.status_code(StatusCode::CREATED);
             res.render(Json(resp));
         }
         Err(e) => {
             tracing::error!("create_short error: {:?}", e);
-            res.status_code(StatusCode::INTERNAL_SERVER_ERROR);
-            res.render(Json(json!({"error": format!("{}", e)})));
+            render_error(req, res, e);
         }
     }
 }
@@ -67,105 +94,250 @@ pub async fn create_short_handler(req: &mut Request, res: &mut Response) {
     ),
     responses(
         (status_code = 307, description = "Temporary redirect to the target URL"),
-        (status_code = 400, description = "Missing or invalid code parameter", body = serde_json::Value, example = json!({"error": "code param missing"})),
-        (status_code = 404, description = "Short URL not found", body = serde_json::Value, example = json!({"error": "Not Found"})),
-        (status_code = 410, description = "Short URL expired", body = serde_json::Value, example = json!({"error": "url expired"})),
-        (status_code = 500, description = "Internal server error", body = serde_json::Value, example = json!({"error": "internal server error"}))
+        (status_code = 400, description = "Missing or invalid code parameter", body = Problem, content_type = "application/problem+json"),
+        (status_code = 404, description = "Short URL not found", body = Problem, content_type = "application/problem+json"),
+        (status_code = 410, description = "Short URL expired, `expired_at` tells when", body = Problem, content_type = "application/problem+json"),
+        (status_code = 500, description = "Internal server error", body = Problem, content_type = "application/problem+json")
     )
 )]
-pub async fn redirect_handler(req: &mut Request, res: &mut Response) {
+pub async fn redirect_handler(req: &mut Request, depot: &mut Depot, res: &mut Response) {
     let code = req.param("code").unwrap_or("").to_owned();
 
     if code.is_empty() {
-        res.status_code(StatusCode::BAD_REQUEST);
-        res.render("code param missing");
+        render_error(req, res, missing_code());
         return;
     }
 
-    let pool = db_pool().clone();
-    let repo = PostgresUrlRepository::new(pool);
-    let svc = UrlServiceImpl::new(Arc::new(repo));
+    let state = AppState::from_depot(depot);
 
-    match svc.get_target_url(&code).await {
-        Ok(Some(target)) => match HeaderValue::from_str(&target) {
+    match state.url_service.get_target_url(&code).await {
+        Ok(target) => match HeaderValue::from_str(&target) {
             Ok(val) => {
                 tracing::info!("Redirecting to: {}, using code: {}", target, code);
+                state.metrics.redirects.inc();
                 res.status_code(StatusCode::TEMPORARY_REDIRECT);
                 res.headers_mut()
                     .insert(HeaderName::from_static("location"), val);
             }
             Err(_) => {
                 tracing::warn!("Invalid redirect location in DB: {}", target);
-                res.status_code(StatusCode::INTERNAL_SERVER_ERROR);
-                res.render("Invalid stored target URL");
+                render_error(req, res, ApiError::internal());
             }
         },
-        Ok(None) => {
-            tracing::error!("Not found resource: {}", code);
-            res.status_code(StatusCode::NOT_FOUND);
-            res.render(Json(json!({
-                "message": "Not Found URL"
-            })));
-        }
         Err(e) => {
-            let msg = e.to_string();
-            if msg.starts_with("EXPIRED:") {
-                tracing::error!("Expired URL: {}", code);
-                let exp = msg.trim_start_matches("EXPIRED:");
-                res.status_code(StatusCode::GONE);
-                res.render(Json(json!({
-                    "message": "url expired",
-                    "expired_at": exp
-                })));
-            } else {
-                tracing::error!("internal server error: {}", msg);
-                res.status_code(StatusCode::INTERNAL_SERVER_ERROR);
-                res.render(Json(json!({
-                    "error": "internal server error"
-                })));
+            let reason = match e {
+                DomainError::NotFound => Some("not_found"),
+                DomainError::Expired { .. } => Some("expired"),
+                _ => None,
+            };
+            if let Some(reason) = reason {
+                state
+                    .metrics
+                    .lookup_failures
+                    .with_label_values(&[reason])
+                    .inc();
             }
+            tracing::error!("redirect error for {}: {}", code, e);
+            render_error(req, res, e);
         }
     }
 }
 
-#[endpoint(tags("URL Shortener"), summary = "Get all short URLs")]
-pub async fn get_all_handler(_req: &mut Request, res: &mut Response) {
-    let pool = db_pool().clone();
-    let repo = PostgresUrlRepository::new(pool);
-    let svc = UrlServiceImpl::new(Arc::new(repo));
+#[endpoint(
+    tags("URL Shortener"),
+    summary = "Get all short URLs",
+    parameters(
+        ("broken" = Option<bool>, Query, description = "Only links whose last health check failed (true) or passed (false)"),
+        ("archived" = Option<bool>, Query, description = "List the archived expired links instead of the live ones")
+    ),
+    responses(
+        (status_code = 200, description = "All short URLs", body = Vec<CreateUrlResponse>),
+        (status_code = 400, description = "Invalid filter", body = Problem, content_type = "application/problem+json"),
+        (status_code = 500, description = "Internal server error", body = Problem, content_type = "application/problem+json")
+    )
+)]
+pub async fn get_all_handler(req: &mut Request, depot: &mut Depot, res: &mut Response) {
+    let filter = match list_filter(req) {
+        Ok(filter) => filter,
+        Err(err) => {
+            render_error(req, res, err);
+            return;
+        }
+    };
+
+    let svc = &AppState::from_depot(depot).url_service;
 
-    match svc.get_all_urls().await {
+    match svc.get_all_urls(filter).await {
         Ok(list) => {
             res.status_code(StatusCode::OK);
             res.render(Json(list));
         }
         Err(e) => {
             tracing::error!("get_all error: {:?}", e);
-            res.status_code(StatusCode::INTERNAL_SERVER_ERROR);
-            res.render(Json(json!({"error": "internal server error"})));
+            render_error(req, res, e);
         }
     }
 }
 
+/// `broken` and `archived` query parameters of the list and export endpoints
+fn list_filter(req: &Request) -> Result<UrlFilter, ApiError> {
+    Ok(UrlFilter {
+        broken: bool_query(req, "broken")?,
+        archived: bool_query(req, "archived")?.unwrap_or(false),
+    })
+}
+
+/// `true` or `false`, anything else is rejected rather than ignored
+fn bool_query(req: &Request, name: &str) -> Result<Option<bool>, ApiError> {
+    let Some(raw) = req.queries().get(name) else {
+        return Ok(None);
+    };
+    raw.parse::<bool>().map(Some).map_err(|_| {
+        let message = format!("{name} must be true or false");
+        ApiError::bad_request("invalid_parameter", message.clone()).with_field(
+            name,
+            "invalid_boolean",
+            message,
+        )
+    })
+}
+
+#[endpoint(
+    tags("URL Shortener"),
+    summary = "Export short URLs",
+    description = "Download the links as CSV (default) or JSON, e.g. `?archived=true` for the archive with its click counts",
+    parameters(
+        ("broken" = Option<bool>, Query, description = "Only links whose last health check failed (true) or passed (false)"),
+        ("archived" = Option<bool>, Query, description = "Export the archived expired links instead of the live ones"),
+        ("format" = Option<String>, Query, description = "`csv` (default) or `json`")
+    ),
+    responses(
+        (status_code = 200, description = "Attachment with the links", content_type = ["text/csv", "application/json"]),
+        (status_code = 400, description = "Unknown format or invalid filter", body = Problem, content_type = "application/problem+json"),
+        (status_code = 500, description = "Internal server error", body = Problem, content_type = "application/problem+json")
+    )
+)]
+pub async fn export_handler(req: &mut Request, depot: &mut Depot, res: &mut Response) {
+    let format = req
+        .query::<String>("format")
+        .unwrap_or_else(|| "csv".to_string());
+    if !matches!(format.as_str(), "csv" | "json") {
+        let err = ApiError::bad_request("invalid_parameter", "format must be csv or json")
+            .with_field("format", "unknown_format", "format must be csv or json");
+        render_error(req, res, err);
+        return;
+    }
+    let filter = match list_filter(req) {
+        Ok(filter) => filter,
+        Err(err) => {
+            render_error(req, res, err);
+            return;
+        }
+    };
+    let name = if filter.archived {
+        "archived-links"
+    } else {
+        "links"
+    };
+
+    let svc = &AppState::from_depot(depot).url_service;
+
+    let list = match svc.get_all_urls(filter).await {
+        Ok(list) => list,
+        Err(e) => {
+            tracing::error!("export error: {:?}", e);
+            render_error(req, res, e);
+            return;
+        }
+    };
+
+    let (content_type, body) = if format == "csv" {
+        ("text/csv; charset=utf-8", links_csv(&list))
+    } else {
+        match serde_json::to_string(&list) {
+            Ok(json) => ("application/json", json),
+            Err(e) => {
+                tracing::error!("export error: {:?}", e);
+                render_error(req, res, ApiError::internal());
+                return;
+            }
+        }
+    };
+    let disposition = format!("attachment; filename=\"{name}.{format}\"");
+    let headers = res.headers_mut();
+    headers.insert(
+        HeaderName::from_static("content-type"),
+        HeaderValue::from_static(content_type),
+    );
+    if let Ok(val) = HeaderValue::from_str(&disposition) {
+        headers.insert(HeaderName::from_static("content-disposition"), val);
+    }
+    res.write_body(body).ok();
+}
+
+/// One row per link, datetimes formatted like the JSON responses
+fn links_csv(links: &[CreateUrlResponse]) -> String {
+    let mut out = String::from(
+        "id,short_code,target_url,clicks,created_at,expires_at,archived_at,broken\r\n",
+    );
+    for link in links {
+        let row = [
+            link.id.to_string(),
+            link.short_code.clone(),
+            link.target_url.clone(),
+            link.clicks.to_string(),
+            format_datetime(&link.created_at),
+            link.expires_at
+                .as_ref()
+                .map(format_datetime)
+                .unwrap_or_default(),
+            link.archived_at
+                .as_ref()
+                .map(format_datetime)
+                .unwrap_or_default(),
+            link.health
+                .as_ref()
+                .map(|h| h.broken.to_string())
+                .unwrap_or_default(),
+        ];
+        let fields: Vec<String> = row.iter().map(|f| csv_field(f)).collect();
+        out.push_str(&fields.join(","));
+        out.push_str("\r\n");
+    }
+    out
+}
+
+/// Quote fields holding separators, quotes or line breaks (RFC 4180)
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\r', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
 #[endpoint(
     tags("URL Shortener"),
     summary = "Delete a short URL",
     parameters(
         ("code" = String, description = "Short code to delete")
+    ),
+    responses(
+        (status_code = 200, description = "Short URL deleted", body = serde_json::Value, example = json!({"message": "short url deleted successfully", "code": "DZMXE5QR"})),
+        (status_code = 400, description = "Missing code parameter", body = Problem, content_type = "application/problem+json"),
+        (status_code = 404, description = "Short URL not found", body = Problem, content_type = "application/problem+json"),
+        (status_code = 500, description = "Internal server error", body = Problem, content_type = "application/problem+json")
     )
 )]
-pub async fn delete_url_handler(req: &mut Request, res: &mut Response) {
+pub async fn delete_url_handler(req: &mut Request, depot: &mut Depot, res: &mut Response) {
     let code = req.param("code").unwrap_or("").to_owned();
 
     if code.is_empty() {
-        res.status_code(StatusCode::BAD_REQUEST);
-        res.render(Json(json!({ "error": "code param missing" })));
+        render_error(req, res, missing_code());
         return;
     }
 
-    let pool = db_pool().clone();
-    let repo = PostgresUrlRepository::new(pool);
-    let svc = UrlServiceImpl::new(Arc::new(repo));
+    let svc = &AppState::from_depot(depot).url_service;
 
     match svc.delete_url(&code).await {
         Ok(_) => {
@@ -176,15 +348,239 @@ pub async fn delete_url_handler(req: &mut Request, res: &mut Response) {
                 "code": code
             })));
         }
-        Err(e) if e.to_string() == "NOT_FOUND" => {
-            tracing::error!("short url not found for delete {}", code);
-            res.status_code(StatusCode::NOT_FOUND);
-            res.render(Json(json!({ "error": "short url not found" })));
+        Err(e) => {
+            tracing::error!("delete error for {}: {}", code, e);
+            render_error(req, res, e);
         }
+    }
+}
+
+#[endpoint(
+    tags("Webhooks"),
+    summary = "Subscribe to link events",
+    description = "Deliveries are signed with the returned secret, which is not shown again",
+    request_body(
+        content = CreateWebhookRequest,
+        description = "Endpoint and events to subscribe to"
+    ),
+    responses(
+        (status_code = 201, description = "Subscription created", body = WebhookResponse),
+        (status_code = 400, description = "Invalid body, endpoint, events or secret", body = Problem, content_type = "application/problem+json"),
+        (status_code = 500, description = "Internal server error", body = Problem, content_type = "application/problem+json")
+    )
+)]
+pub async fn create_webhook_handler(req: &mut Request, depot: &mut Depot, res: &mut Response) {
+    let body: CreateWebhookRequest = match req.parse_json().await {
+        Ok(b) => b,
         Err(e) => {
-            tracing::error!("delete error: {:?}", e);
-            res.status_code(StatusCode::INTERNAL_SERVER_ERROR);
-            res.render(Json(json!({ "error": "internal server error" })));
+            let err = ApiError::bad_request("invalid_body", "invalid request body").with_field(
+                "body",
+                "invalid_json",
+                e.to_string(),
+            );
+            render_error(req, res, err);
+            return;
         }
+    };
+
+    let svc = &AppState::from_depot(depot).webhooks;
+
+    match svc.create_subscription(body).await {
+        Ok(resp) => {
+            res.status_code(StatusCode::CREATED);
+            res.render(Json(resp));
+        }
+        Err(e) => {
+            tracing::error!("create_webhook error: {:?}", e);
+            render_error(req, res, e);
+        }
+    }
+}
+
+#[endpoint(
+    tags("Webhooks"),
+    summary = "List webhook subscriptions",
+    responses(
+        (status_code = 200, description = "All subscriptions, without secrets", body = Vec<WebhookResponse>),
+        (status_code = 500, description = "Internal server error", body = Problem, content_type = "application/problem+json")
+    )
+)]
+pub async fn list_webhooks_handler(req: &mut Request, depot: &mut Depot, res: &mut Response) {
+    let svc = &AppState::from_depot(depot).webhooks;
+
+    match svc.list_subscriptions().await {
+        Ok(list) => res.render(Json(list)),
+        Err(e) => {
+            tracing::error!("list_webhooks error: {:?}", e);
+            render_error(req, res, e);
+        }
+    }
+}
+
+#[endpoint(
+    tags("Webhooks"),
+    summary = "Delete a webhook subscription",
+    description = "Pending deliveries to it are dropped",
+    parameters(
+        ("id" = String, description = "Subscription id")
+    ),
+    responses(
+        (status_code = 204, description = "Subscription deleted"),
+        (status_code = 400, description = "Invalid id", body = Problem, content_type = "application/problem+json"),
+        (status_code = 404, description = "Subscription not found", body = Problem, content_type = "application/problem+json"),
+        (status_code = 500, description = "Internal server error", body = Problem, content_type = "application/problem+json")
+    )
+)]
+pub async fn delete_webhook_handler(req: &mut Request, depot: &mut Depot, res: &mut Response) {
+    let Some(id) = id_param(req, res) else {
+        return;
+    };
+    let svc = &AppState::from_depot(depot).webhooks;
+
+    match svc.delete_subscription(id).await {
+        Ok(()) => {
+            tracing::info!("webhook {} unsubscribed", id);
+            res.status_code(StatusCode::NO_CONTENT);
+        }
+        Err(e) => {
+            tracing::error!("delete_webhook error for {}: {}", id, e);
+            render_error(req, res, e);
+        }
+    }
+}
+
+#[endpoint(
+    tags("Webhooks"),
+    summary = "List webhook deliveries",
+    description = "Newest first, e.g. `?status=failed` to find deliveries to replay",
+    parameters(
+        ("status" = Option<String>, Query, description = "`pending`, `delivered` or `failed`"),
+        ("limit" = Option<i64>, Query, description = "At most this many, default 100, up to 500")
+    ),
+    responses(
+        (status_code = 200, description = "Deliveries", body = Vec<WebhookDeliveryResponse>),
+        (status_code = 400, description = "Unknown status", body = Problem, content_type = "application/problem+json"),
+        (status_code = 500, description = "Internal server error", body = Problem, content_type = "application/problem+json")
+    )
+)]
+pub async fn list_deliveries_handler(req: &mut Request, depot: &mut Depot, res: &mut Response) {
+    let status = req.query::<String>("status");
+    let limit = req
+        .query::<i64>("limit")
+        .unwrap_or(100)
+        .min(MAX_DELIVERY_PAGE);
+    let svc = &AppState::from_depot(depot).webhooks;
+
+    match svc.list_deliveries(status, limit).await {
+        Ok(list) => res.render(Json(list)),
+        Err(e) => {
+            tracing::error!("list_deliveries error: {:?}", e);
+            render_error(req, res, e);
+        }
+    }
+}
+
+#[endpoint(
+    tags("Webhooks"),
+    summary = "Replay a webhook delivery",
+    description = "Queue the delivery again with a fresh set of attempts",
+    parameters(
+        ("id" = String, description = "Delivery id")
+    ),
+    responses(
+        (status_code = 202, description = "Delivery queued", body = WebhookDeliveryResponse),
+        (status_code = 400, description = "Invalid id", body = Problem, content_type = "application/problem+json"),
+        (status_code = 404, description = "Delivery not found", body = Problem, content_type = "application/problem+json"),
+        (status_code = 500, description = "Internal server error", body = Problem, content_type = "application/problem+json")
+    )
+)]
+pub async fn replay_delivery_handler(req: &mut Request, depot: &mut Depot, res: &mut Response) {
+    let Some(id) = id_param(req, res) else {
+        return;
+    };
+    let svc = &AppState::from_depot(depot).webhooks;
+
+    match svc.replay_delivery(id).await {
+        Ok(delivery) => {
+            res.status_code(StatusCode::ACCEPTED);
+            res.render(Json(delivery));
+        }
+        Err(e) => {
+            tracing::error!("replay_delivery error for {}: {}", id, e);
+            render_error(req, res, e);
+        }
+    }
+}
+
+#[endpoint(
+    tags("Scheduler"),
+    summary = "Scheduler leader",
+    description = "Which instance holds the lease to run cleanup and health checks, and whether it is the one answering",
+    responses(
+        (status_code = 200, description = "Scheduler status", body = SchedulerStatusResponse),
+        (status_code = 500, description = "Internal server error", body = Problem, content_type = "application/problem+json")
+    )
+)]
+pub async fn scheduler_status_handler(req: &mut Request, depot: &mut Depot, res: &mut Response) {
+    let leader = &AppState::from_depot(depot).leader;
+
+    match leader.current_lease().await {
+        Ok(lease) => res.render(Json(SchedulerStatusResponse {
+            instance_id: leader.instance_id().to_string(),
+            leader: leader.is_leader(),
+            lease: lease.map(Into::into),
+        })),
+        Err(e) => {
+            tracing::error!("scheduler_status error: {:?}", e);
+            render_error(req, res, e);
+        }
+    }
+}
+
+#[endpoint(
+    tags("Scheduler"),
+    summary = "Background jobs",
+    description = "Schedule, last and next run, and last error of each job running on the answering instance",
+    responses(
+        (status_code = 200, description = "Jobs", body = Vec<JobReport>)
+    )
+)]
+pub async fn list_jobs_handler(depot: &mut Depot, res: &mut Response) {
+    let jobs = &AppState::from_depot(depot).jobs;
+    let reports: Vec<JobReport> = jobs.iter().map(|job| job.report()).collect();
+    res.render(Json(reports));
+}
+
+#[endpoint(
+    tags("Health"),
+    summary = "Liveness probe",
+    description = "The process is up and serving requests. Does not touch the database.",
+    responses(
+        (status_code = 200, description = "Alive", body = serde_json::Value, example = json!({"status": "ok", "version": "0.1.0"}))
+    )
+)]
+pub async fn healthz_handler(res: &mut Response) {
+    res.render(Json(json!({
+        "status": "ok",
+        "version": env!("CARGO_PKG_VERSION")
+    })));
+}
+
+#[endpoint(
+    tags("Health"),
+    summary = "Readiness probe",
+    description = "Checks the database, the applied migration version and the background jobs",
+    responses(
+        (status_code = 200, description = "Ready to serve traffic", body = ReadinessReport),
+        (status_code = 503, description = "A check failed, see `checks`", body = ReadinessReport)
+    )
+)]
+pub async fn readyz_handler(depot: &mut Depot, res: &mut Response) {
+    let report = AppState::from_depot(depot).readiness.check().await;
+
+    if !report.ready {
+        tracing::warn!("not ready: {:?}", report.checks);
+        res.status_code(StatusCode::SERVICE_UNAVAILABLE);
     }
+    res.render(Json(report));
 }
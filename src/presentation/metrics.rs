@@ -0,0 +1,70 @@
+use crate::infrastructure::metrics::Metrics;
+use crate::presentation::errors::{ApiError, render_error};
+use crate::presentation::state::AppState;
+use salvo::http::header::{CONTENT_TYPE, HeaderValue};
+use salvo::prelude::*;
+use std::sync::Arc;
+use std::time::Instant;
+
+/// Service hoop counting and timing every request by route template, so
+/// `/api/v1/{code}` is one series rather than one per code
+pub struct RequestMetrics {
+    metrics: Arc<Metrics>,
+}
+
+impl RequestMetrics {
+    pub fn new(metrics: Arc<Metrics>) -> Self {
+        Self { metrics }
+    }
+}
+
+#[handler]
+impl RequestMetrics {
+    async fn handle(
+        &self,
+        req: &mut Request,
+        depot: &mut Depot,
+        res: &mut Response,
+        ctrl: &mut FlowCtrl,
+    ) {
+        let started = Instant::now();
+        ctrl.call_next(req, depot, res).await;
+
+        let route = match req.matched_path() {
+            "" => "unmatched".to_string(),
+            path => format!("/{path}"),
+        };
+        let status = res.status_code.unwrap_or(StatusCode::OK);
+        let labels = [req.method().as_str(), route.as_str(), status.as_str()];
+
+        self.metrics.http_requests.with_label_values(&labels).inc();
+        self.metrics
+            .http_request_duration
+            .with_label_values(&labels)
+            .observe(started.elapsed().as_secs_f64());
+    }
+}
+
+#[endpoint(
+    tags("Health"),
+    summary = "Prometheus metrics",
+    description = "Metrics in the Prometheus text exposition format",
+    responses(
+        (status_code = 200, description = "Metrics", body = String, content_type = "text/plain")
+    )
+)]
+pub async fn metrics_handler(req: &mut Request, depot: &mut Depot, res: &mut Response) {
+    match AppState::from_depot(depot).metrics.render() {
+        Ok(body) => {
+            res.render(Text::Plain(body));
+            res.headers_mut().insert(
+                CONTENT_TYPE,
+                HeaderValue::from_static("text/plain; version=0.0.4"),
+            );
+        }
+        Err(e) => {
+            tracing::error!("metrics render error: {:?}", e);
+            render_error(req, res, ApiError::internal());
+        }
+    }
+}
@@ -1,2 +1,8 @@
+pub mod api_key;
+pub mod datetime;
+pub mod errors;
 pub mod handlers;
+pub mod metrics;
+pub mod request_id;
 pub mod routes;
+pub mod state;
@@ -0,0 +1,61 @@
+use salvo::http::header::HeaderValue;
+use salvo::prelude::*;
+use std::time::Instant;
+use tracing::Instrument;
+use tracing::field::Empty;
+use uuid::Uuid;
+
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Longest caller supplied id we propagate, anything else gets a fresh one
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+/// Service hoop assigning every request an id (the caller's `X-Request-Id`
+/// or a new UUID) and running it inside a span carrying the id, route, code
+/// and latency, so every log line of the request can be correlated. The id is
+/// echoed in the response header and in problem bodies.
+#[handler]
+pub async fn request_id_hoop(
+    req: &mut Request,
+    depot: &mut Depot,
+    res: &mut Response,
+    ctrl: &mut FlowCtrl,
+) {
+    let id = incoming_id(req).unwrap_or_else(|| Uuid::new_v4().to_string());
+    let header = HeaderValue::from_str(&id).expect("request id is visible ASCII");
+    // Later readers, such as error rendering, take the id from the request
+    req.headers_mut().insert(REQUEST_ID_HEADER, header.clone());
+
+    let route = match req.matched_path() {
+        "" => "unmatched".to_string(),
+        path => format!("/{path}"),
+    };
+    let span = tracing::info_span!(
+        "request",
+        request_id = %id,
+        method = %req.method(),
+        route = %route,
+        code = Empty,
+        latency_ms = Empty,
+    );
+    if let Some(code) = req.param::<String>("code") {
+        span.record("code", code);
+    }
+
+    let started = Instant::now();
+    ctrl.call_next(req, depot, res)
+        .instrument(span.clone())
+        .await;
+
+    span.record("latency_ms", started.elapsed().as_millis() as u64);
+    let status = res.status_code.unwrap_or(StatusCode::OK);
+    span.in_scope(|| tracing::info!(status = status.as_u16(), "request finished"));
+
+    res.headers_mut().insert(REQUEST_ID_HEADER, header);
+}
+
+fn incoming_id(req: &Request) -> Option<String> {
+    req.header::<String>(REQUEST_ID_HEADER).filter(|id| {
+        !id.is_empty() && id.len() <= MAX_REQUEST_ID_LEN && id.bytes().all(|b| b.is_ascii_graphic())
+    })
+}
@@ -1,24 +1,72 @@
+use crate::presentation::api_key::RequireApiKey;
 use crate::presentation::handlers::{
-    create_short_handler, delete_url_handler, get_all_handler, redirect_handler,
+    create_short_handler, create_webhook_handler, delete_url_handler, delete_webhook_handler,
+    export_handler, get_all_handler, healthz_handler, list_deliveries_handler, list_jobs_handler,
+    list_webhooks_handler, readyz_handler, redirect_handler, replay_delivery_handler,
+    scheduler_status_handler,
 };
+use crate::presentation::metrics::metrics_handler;
+use crate::presentation::state::AppState;
+use salvo::affix_state;
 use salvo::oapi::OpenApi;
 use salvo::prelude::*;
 
-pub fn router() -> Router {
-    let api_router = Router::new()
-        .path("/api/v1")
+pub fn router(state: AppState) -> Router {
+    // Every segment under /api/v1 besides `{code}` belongs in `RESERVED_CODES`
+    let mut managed = Router::new()
         .push(
             Router::new()
                 .path("/shorten")
                 .post(create_short_handler)
                 .get(get_all_handler)
+                .push(Router::new().path("/export").get(export_handler))
                 .push(Router::new().path("/{code}").delete(delete_url_handler)),
         )
+        .push(
+            Router::new()
+                .path("/webhooks")
+                .post(create_webhook_handler)
+                .get(list_webhooks_handler)
+                .push(
+                    Router::new()
+                        .path("/deliveries")
+                        .get(list_deliveries_handler)
+                        .push(
+                            Router::new()
+                                .path("/{id}/replay")
+                                .post(replay_delivery_handler),
+                        ),
+                )
+                .push(Router::new().path("/{id}").delete(delete_webhook_handler)),
+        )
+        .push(
+            Router::new()
+                .path("/scheduler")
+                .get(scheduler_status_handler)
+                .push(Router::new().path("/jobs").get(list_jobs_handler)),
+        );
+    // Redirects are for anyone holding the short link
+    if let Some(keys) = &state.api_keys {
+        managed = managed.hoop(RequireApiKey::new(keys.clone()));
+    }
+    let api_router = Router::new()
+        .path("/api/v1")
+        .push(managed)
         .push(Router::new().path("/{code}").get(redirect_handler));
 
-    let doc = OpenApi::default().merge_router(&api_router);
+    // Probes and metrics stay outside /api/v1 so auth and rate limits never apply to them
+    let probe_router = Router::new()
+        .push(Router::with_path("healthz").get(healthz_handler))
+        .push(Router::with_path("readyz").get(readyz_handler))
+        .push(Router::with_path("metrics").get(metrics_handler));
+
+    let doc = OpenApi::default()
+        .merge_router(&api_router)
+        .merge_router(&probe_router);
 
     Router::new()
+        .hoop(affix_state::inject(state))
+        .push(probe_router)
         .push(api_router)
         .push(doc.into_router("/api-doc/openapi.json"))
         .push(SwaggerUi::new("/api-doc/openapi.json").into_router("/documentation"))
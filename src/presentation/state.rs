@@ -0,0 +1,56 @@
+use crate::application::api_keys::ApiKeyService;
+use crate::application::services::UrlService;
+use crate::application::webhooks::WebhookService;
+use crate::infrastructure::leader::LeaderElection;
+use crate::infrastructure::metrics::Metrics;
+use crate::infrastructure::readiness::ReadinessCheck;
+use crate::infrastructure::scheduler::JobStatus;
+use salvo::prelude::*;
+use std::sync::Arc;
+
+/// Dependencies shared by every handler, built once in `main` and injected
+/// into the `Depot` by an `affix_state` hoop
+#[derive(Clone)]
+pub struct AppState {
+    pub url_service: Arc<dyn UrlService>,
+    pub webhooks: Arc<dyn WebhookService>,
+    pub leader: Arc<LeaderElection>,
+    pub jobs: Vec<Arc<JobStatus>>,
+    pub readiness: Arc<ReadinessCheck>,
+    pub metrics: Arc<Metrics>,
+    /// Checks the keys of `/api/v1` calls, `None` when no key is required
+    pub api_keys: Option<Arc<dyn ApiKeyService>>,
+}
+
+impl AppState {
+    pub fn new(
+        url_service: Arc<dyn UrlService>,
+        webhooks: Arc<dyn WebhookService>,
+        leader: Arc<LeaderElection>,
+        jobs: Vec<Arc<JobStatus>>,
+        readiness: Arc<ReadinessCheck>,
+        metrics: Arc<Metrics>,
+    ) -> Self {
+        Self {
+            url_service,
+            webhooks,
+            leader,
+            jobs,
+            readiness,
+            metrics,
+            api_keys: None,
+        }
+    }
+
+    /// Require an API key checked by `keys` on the API, redirects aside
+    pub fn with_api_keys(mut self, keys: Arc<dyn ApiKeyService>) -> Self {
+        self.api_keys = Some(keys);
+        self
+    }
+
+    pub fn from_depot(depot: &Depot) -> &Self {
+        depot
+            .obtain::<Self>()
+            .expect("AppState is not injected into the depot")
+    }
+}